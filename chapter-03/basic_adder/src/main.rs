@@ -1,58 +1,25 @@
 use crossterm::{
-    cursor::{position, MoveTo, Show},
-    event::{
-        read, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, MouseButton, MouseEventKind,
-    },
+    cursor::{position, MoveTo},
+    event::{read, Event, KeyCode, MouseButton, MouseEventKind},
     style::Stylize,
     style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
-    terminal::{disable_raw_mode, enable_raw_mode, size, Clear, ClearType},
+    terminal::{size, Clear, ClearType},
     QueueableCommand, Result,
 };
-use std::io::{stdout, Error, ErrorKind, Write};
-
-use crossterm::terminal::{is_raw_mode_enabled, EnterAlternateScreen, LeaveAlternateScreen};
-use std::panic::catch_unwind;
+use std::io::{stdout, Write};
+use terminal_guard::{Modes, TerminalGuard};
 
 fn main() -> Result<()> {
-    let result = catch_unwind(run);
-    cleanup_tty()?;
-    match result {
-        Ok(Ok(())) => Ok(()),
-        Ok(err) => err,
-        Err(err) => Err(Error::new(
-            ErrorKind::Other,
-            if let Some(&message) = err.downcast_ref::<&str>() {
-                message
-            } else {
-                "Unexpected error"
-            },
-        )),
-    }
-}
-
-fn cleanup_tty() -> Result<()> {
-    if is_raw_mode_enabled()? {
-        disable_raw_mode()?;
-        stdout()
-            .queue(DisableMouseCapture)?
-            .queue(SetForegroundColor(Color::White))?
-            .queue(SetBackgroundColor(Color::Black))?
-            .queue(Clear(ClearType::All))?
-            .queue(LeaveAlternateScreen)?
-            .queue(Show)?
-            .flush()?;
-    }
-    Ok(())
-}
-
-fn run() -> Result<()> {
     let mut addend_1 = String::new();
     let mut addend_2 = String::new();
     let mut sum: u64;
 
     let (mut n_cols, mut n_rows) = size()?;
 
-    enable_raw_mode()?;
+    let _guard = TerminalGuard::enter(Modes {
+        hidden_cursor: false,
+        ..Modes::ALL
+    })?;
 
     let max_n_digits = 18;
     let number_width = max_n_digits as usize;
@@ -60,8 +27,6 @@ fn run() -> Result<()> {
     let mut cursor_at_1 = true;
 
     let mut out = stdout();
-    out.queue(EnterAlternateScreen)?;
-    out.queue(EnableMouseCapture)?;
     loop {
         // Refresh the terminal.
         out.queue(Clear(ClearType::All))?;
@@ -5,8 +5,11 @@ use crossterm::{
     QueueableCommand, Result,
 };
 use std::io::{stdin, stdout, Write};
+use terminal_guard::{Modes, TerminalGuard};
 
 fn main() -> Result<()> {
+    // The text stays on the screen; only the colors and the cursor are restored.
+    let _guard = TerminalGuard::enter(Modes::NONE)?;
     stdout()
         .queue(Clear(ClearType::All))?
         .queue(MoveTo(0, 0))?
@@ -1,11 +1,12 @@
 use crossterm::{
-    cursor::{Hide, MoveTo, Show},
+    cursor::MoveTo,
     style::{Color, Print, SetBackgroundColor, SetForegroundColor},
     terminal::size,
     QueueableCommand, Result,
 };
 use std::fs::File;
 use std::io::{stdin, stdout, Error, ErrorKind, Read, Write};
+use terminal_guard::{Modes, TerminalGuard};
 
 struct Model {
     contents: String,
@@ -71,10 +72,12 @@ fn refresh_screen(model: &Model) -> Result<()> {
 }
 
 fn main() -> Result<()> {
-    stdout().queue(Hide)?.flush()?;
+    let _guard = TerminalGuard::enter(Modes {
+        hidden_cursor: true,
+        ..Modes::NONE
+    })?;
     let model = load_file()?;
     refresh_screen(&model)?;
     stdin().read(&mut [0u8; 0])?;
-    stdout().queue(Show)?;
     Ok(())
 }
@@ -0,0 +1,172 @@
+//! Restoration of the terminal set in raw mode, in the alternate screen or capturing the mouse
+//! by a program, whether the program ends normally, panics or is terminated by a signal.
+//!
+//! ```no_run
+//! use terminal_guard::{Modes, TerminalGuard};
+//!
+//! let _guard = TerminalGuard::enter(Modes::ALL)?;
+//! // Draw and read events; the terminal is restored when the guard is dropped.
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crossterm::{
+    cursor::{Hide, Show},
+    event::{DisableMouseCapture, EnableMouseCapture},
+    style::ResetColor,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    QueueableCommand, Result,
+};
+use std::io::{stdout, Error, Write};
+use std::panic;
+use std::sync::{Mutex, PoisonError};
+
+/// The changes made to the terminal, undone by the restoration.
+/// The colors are reset and the cursor is shown in any case, as programs change them
+/// while drawing.
+#[derive(Clone, Copy)]
+pub struct Modes {
+    /// The keys are read as they are typed, without echo, and Ctrl+C is a key.
+    pub raw: bool,
+    /// The program draws on a screen of its own, and the previous screen is shown again after it.
+    pub alternate_screen: bool,
+    pub mouse_capture: bool,
+    pub hidden_cursor: bool,
+}
+
+impl Modes {
+    pub const NONE: Self = Self {
+        raw: false,
+        alternate_screen: false,
+        mouse_capture: false,
+        hidden_cursor: false,
+    };
+
+    pub const ALL: Self = Self {
+        raw: true,
+        alternate_screen: true,
+        mouse_capture: true,
+        hidden_cursor: true,
+    };
+}
+
+/// The modes to undo, if the terminal is not restored yet.
+static ACTIVE_MODES: Mutex<Option<Modes>> = Mutex::new(None);
+
+/// Whether the handlers restoring the terminal are installed, which is tried again after a failure.
+static HANDLERS_INSTALLED: Mutex<bool> = Mutex::new(false);
+
+/// Keeps the terminal in its modes while it lives, restoring it when dropped.
+/// Only one guard may live at a time.
+pub struct TerminalGuard {
+    _private: (),
+}
+
+impl TerminalGuard {
+    /// Sets the modes of the terminal, after installing the handlers restoring it
+    /// on panic and on SIGINT or SIGTERM. If a mode cannot be set, the others are undone.
+    /// Fails if another guard is active.
+    pub fn enter(modes: Modes) -> Result<Self> {
+        let mut handlers_installed = HANDLERS_INSTALLED
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if !*handlers_installed {
+            install_handlers()?;
+            *handlers_installed = true;
+        }
+        drop(handlers_installed);
+        let mut active_modes = active_modes();
+        if active_modes.is_some() {
+            return Err(Error::other("A terminal guard is already active"));
+        }
+        *active_modes = Some(modes);
+        drop(active_modes);
+        let guard = Self { _private: () };
+        set_modes(modes)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        // A failure cannot be reported, and the program ends anyway.
+        let _ = restore();
+    }
+}
+
+/// Undoes the modes of the guard, if they are not undone yet.
+pub fn restore() -> Result<()> {
+    let Some(modes) = active_modes().take() else {
+        return Ok(());
+    };
+    let mut out = stdout();
+    if modes.mouse_capture {
+        out.queue(DisableMouseCapture)?;
+    }
+    out.queue(ResetColor)?;
+    if modes.alternate_screen {
+        out.queue(LeaveAlternateScreen)?;
+    }
+    out.queue(Show)?.flush()?;
+    if modes.raw {
+        disable_raw_mode()?;
+    }
+    Ok(())
+}
+
+fn set_modes(modes: Modes) -> Result<()> {
+    let mut out = stdout();
+    if modes.raw {
+        enable_raw_mode()?;
+    }
+    if modes.alternate_screen {
+        out.queue(EnterAlternateScreen)?;
+    }
+    if modes.mouse_capture {
+        out.queue(EnableMouseCapture)?;
+    }
+    if modes.hidden_cursor {
+        out.queue(Hide)?;
+    }
+    out.flush()
+}
+
+/// A panic while the modes are locked must not prevent the restoration.
+fn active_modes() -> std::sync::MutexGuard<'static, Option<Modes>> {
+    ACTIVE_MODES.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Restores the terminal before the panic message is printed, so that it is readable,
+/// and before the program is terminated by a signal.
+/// The panic hook is set last, so that a failure leaves nothing installed.
+fn install_handlers() -> Result<()> {
+    install_signal_handlers()?;
+    let previous_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = restore();
+        previous_hook(info);
+    }));
+    Ok(())
+}
+
+#[cfg(unix)]
+fn install_signal_handlers() -> Result<()> {
+    use signal_hook::{
+        consts::{SIGINT, SIGTERM},
+        iterator::Signals,
+    };
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+    std::thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            let _ = restore();
+            // As a shell reports a process terminated by the signal.
+            std::process::exit(128 + signal);
+        }
+    });
+    Ok(())
+}
+
+/// Without Unix signals, Ctrl+C is a key event in raw mode, and is handled by the program.
+#[cfg(not(unix))]
+fn install_signal_handlers() -> Result<()> {
+    Ok(())
+}
@@ -0,0 +1,14 @@
+use terminal_guard::{restore, Modes, TerminalGuard};
+
+#[test]
+// Without modes, no terminal is needed. The guard state is global, so it is checked in one test.
+fn single_guard_and_idempotent_restore() {
+    let guard = TerminalGuard::enter(Modes::NONE).unwrap();
+    assert!(TerminalGuard::enter(Modes::NONE).is_err());
+    restore().unwrap();
+    restore().unwrap();
+    // Dropping the guard after the restoration does nothing more.
+    drop(guard);
+    restore().unwrap();
+    let _guard = TerminalGuard::enter(Modes::NONE).unwrap();
+}
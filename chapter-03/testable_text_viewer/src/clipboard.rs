@@ -0,0 +1,27 @@
+const BASE64_DIGITS: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Returns the OSC 52 escape sequence asking the terminal to put the text in the system clipboard.
+/// It works also through SSH, but some terminals ignore it, or need it to be enabled.
+pub fn copy_sequence(text: &str) -> String {
+    format!("\u{1b}]52;c;{}\u{7}", base64(text.as_bytes()))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (index, byte)| {
+            group | u32::from(*byte) << (16 - 8 * index)
+        });
+        // A chunk of n bytes gives n + 1 digits, completed with padding.
+        for digit_index in 0..4 {
+            if digit_index <= chunk.len() {
+                let digit = (group >> (18 - 6 * digit_index)) & 0x3F;
+                encoded.push(char::from(BASE64_DIGITS[digit as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
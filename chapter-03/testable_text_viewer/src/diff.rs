@@ -0,0 +1,142 @@
+/// Greatest number of inserted and deleted lines searched for, beyond which
+/// the differing part of the texts is shown as a single change, to bound memory and time.
+const MAX_EDIT_DISTANCE: usize = 2000;
+
+/// A row of a side-by-side diff.
+pub struct DiffRow {
+    /// Index of the line of each text shown on the row, or `None` for a filler.
+    pub lines: [Option<usize>; 2],
+    /// Whether the row shows a removed, added or modified line.
+    pub changed: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Edit {
+    Keep,
+    Delete,
+    Insert,
+}
+
+/// Aligns the lines of two texts, putting the equal lines on the same rows,
+/// and the lines replaced by others side by side.
+pub fn align<T: PartialEq>(old: &[T], new: &[T]) -> Vec<DiffRow> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+    let middle_edits = shortest_edits(old_middle, new_middle).unwrap_or_else(|| {
+        let mut edits = vec![Edit::Delete; old_middle.len()];
+        edits.resize(old_middle.len() + new_middle.len(), Edit::Insert);
+        edits
+    });
+    let mut edits = vec![Edit::Keep; prefix];
+    edits.extend(middle_edits);
+    edits.resize(edits.len() + suffix, Edit::Keep);
+
+    let mut rows = vec![];
+    let (mut old_index, mut new_index) = (0, 0);
+    let mut edits = edits.into_iter().peekable();
+    while let Some(edit) = edits.next() {
+        if edit == Edit::Keep {
+            rows.push(DiffRow {
+                lines: [Some(old_index), Some(new_index)],
+                changed: false,
+            });
+            old_index += 1;
+            new_index += 1;
+            continue;
+        }
+        // The deleted and the inserted lines between two kept ones are paired in order.
+        let (mut n_deleted, mut n_inserted) = (0, 0);
+        let mut edit = Some(edit);
+        while let Some(change) = edit {
+            match change {
+                Edit::Delete => n_deleted += 1,
+                Edit::Insert => n_inserted += 1,
+                Edit::Keep => unreachable!(),
+            }
+            edit = edits.next_if(|edit| *edit != Edit::Keep);
+        }
+        for index in 0..n_deleted.max(n_inserted) {
+            rows.push(DiffRow {
+                lines: [
+                    (index < n_deleted).then_some(old_index + index),
+                    (index < n_inserted).then_some(new_index + index),
+                ],
+                changed: true,
+            });
+        }
+        old_index += n_deleted;
+        new_index += n_inserted;
+    }
+    rows
+}
+
+/// Returns the shortest list of edits transforming the old text into the new one,
+/// by the algorithm of Eugene Myers, or `None` if it is longer than `MAX_EDIT_DISTANCE`.
+fn shortest_edits<T: PartialEq>(old: &[T], new: &[T]) -> Option<Vec<Edit>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let offset = n + m + 1;
+    // The furthest position in the old text reached on each diagonal k = x - y.
+    let mut furthest = vec![0; 2 * offset as usize + 1];
+    // The furthest positions before each step, on the diagonals -d - 1 to d + 1.
+    let mut trace: Vec<Vec<isize>> = vec![];
+    for d in 0..=(n + m).min(MAX_EDIT_DISTANCE as isize) {
+        trace.push(furthest[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let index = (offset + k) as usize;
+            let mut x = if k == -d || k != d && furthest[index - 1] < furthest[index + 1] {
+                furthest[index + 1]
+            } else {
+                furthest[index - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            furthest[index] = x;
+            if x >= n && y >= m {
+                return Some(backtrack(&trace, n, m));
+            }
+        }
+    }
+    None
+}
+
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<Edit> {
+    let mut edits = vec![];
+    let (mut x, mut y) = (n, m);
+    for (d, furthest) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| furthest[(k + d + 1) as usize];
+        let k = x - y;
+        let previous_k = if k == -d || k != d && at(k - 1) < at(k + 1) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = at(previous_k);
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            edits.push(Edit::Keep);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            edits.push(if x == previous_x {
+                Edit::Insert
+            } else {
+                Edit::Delete
+            });
+        }
+        (x, y) = (previous_x, previous_y);
+    }
+    edits.reverse();
+    edits
+}
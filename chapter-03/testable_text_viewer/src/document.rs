@@ -0,0 +1,471 @@
+use memchr::memchr_iter;
+use memmap2::Mmap;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::cmp::{max, min};
+use std::fs::{self, File, Metadata};
+use std::io::{stdin, Error, ErrorKind, Read, Result};
+use std::ops::Range;
+use std::path::Path;
+use std::process;
+
+use crate::hex;
+use crate::layout::display_width;
+
+/// Number of bytes scanned at once when lines beyond the indexed ones are needed.
+const CHUNK_SIZE: usize = 1 << 20;
+
+enum Source {
+    Mapped(Mmap),
+    Buffered(Vec<u8>),
+}
+
+/// How the file changed since it was last read.
+#[derive(PartialEq)]
+pub enum Change {
+    None,
+    Appended,
+    Replaced,
+}
+
+/// The text shown by the viewer, mapped from a file or read from a pipe.
+/// Its lines are indexed only as far as they are needed, one chunk at a time,
+/// so that the beginning of a huge file is shown without scanning all of it.
+/// Once edited, the text is kept as decoded lines instead.
+/// In the hex view, its lines are rows of bytes, formatted like those of `hexdump -C`.
+pub struct Document {
+    source: Source,
+    path: String,
+    /// Kept open to detect the replacement of the file by another one at the same path.
+    file: Option<File>,
+    tab_width: usize,
+    /// Position of the end of every line indexed so far, excluding the newline.
+    line_ends: RefCell<Vec<usize>>,
+    n_scanned_bytes: Cell<usize>,
+    /// Greatest display width of the lines indexed so far.
+    max_line_width: Cell<usize>,
+    edited_lines: Option<Vec<String>>,
+    /// Ending of every edited line, written when it is saved:
+    /// empty, or a carriage return, for a last line without newline.
+    edited_line_endings: Vec<&'static str>,
+    /// Line ending of the first line of the file, ending the lines added by editing.
+    line_ending: &'static str,
+    /// Whether the edited text differs from the saved one.
+    modified: bool,
+    /// Number of bytes of each row, in the hex view.
+    bytes_per_row: Option<usize>,
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Self {
+            source: Source::Buffered(vec![]),
+            path: String::new(),
+            file: None,
+            tab_width: 8,
+            line_ends: RefCell::new(vec![]),
+            n_scanned_bytes: Cell::new(0),
+            max_line_width: Cell::new(0),
+            edited_lines: None,
+            edited_line_endings: vec![],
+            line_ending: "\n",
+            modified: false,
+            bytes_per_row: None,
+        }
+    }
+
+    /// Maps the file, or reads the whole standard input if the path is `-`.
+    pub fn open(path: &str, tab_width: usize) -> Result<Self> {
+        let (source, file) = if path == "-" {
+            let mut bytes = vec![];
+            stdin().read_to_end(&mut bytes)?;
+            (Source::Buffered(bytes), None)
+        } else {
+            let file = File::open(path)?;
+            (Source::Mapped(map(&file)?), Some(file))
+        };
+        let line_ending = match source_bytes(&source).split(|byte| *byte == b'\n').next() {
+            Some(line) if line.ends_with(b"\r") => "\r\n",
+            _ => "\n",
+        };
+        let document = Self {
+            source,
+            path: path.to_string(),
+            file,
+            tab_width,
+            line_ending,
+            ..Self::new()
+        };
+        document.index_up_to(0);
+        Ok(document)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn is_from_stdin(&self) -> bool {
+        self.path == "-"
+    }
+
+    /// Tells whether the file has a different size or is another file than the one read.
+    /// The changes of a file being edited are ignored.
+    pub fn has_changed(&self) -> Result<bool> {
+        if self.modified {
+            return Ok(false);
+        }
+        let Some(file) = &self.file else {
+            return Ok(false);
+        };
+        // While a log is rotated, its path may be missing for a moment.
+        let Ok(path_metadata) = fs::metadata(&self.path) else {
+            return Ok(false);
+        };
+        Ok(path_metadata.len() != self.bytes().len() as u64
+            || !is_same_file(&file.metadata()?, &path_metadata))
+    }
+
+    /// Maps what was appended to the file, or maps it again from the start
+    /// if it was truncated or replaced by another file, as when logs are rotated.
+    pub fn reload(&mut self) -> Result<Change> {
+        if self.modified {
+            return Ok(Change::None);
+        }
+        if self.edited_lines.is_some() {
+            self.reopen()?;
+            return Ok(Change::Replaced);
+        }
+        let n_bytes = self.bytes().len();
+        let Some(file) = &mut self.file else {
+            return Ok(Change::None);
+        };
+        let Ok(path_metadata) = fs::metadata(&self.path) else {
+            return Ok(Change::None);
+        };
+        if path_metadata.len() < n_bytes as u64 || !is_same_file(&file.metadata()?, &path_metadata)
+        {
+            self.reopen()?;
+            return Ok(Change::Replaced);
+        }
+        if path_metadata.len() == n_bytes as u64 {
+            return Ok(Change::None);
+        }
+        // The indexed lines stay valid, as the bytes before the end do not change.
+        self.source = Source::Mapped(map(file)?);
+        // A last line without newline may be continued by the new bytes.
+        if self.n_scanned_bytes.get() == n_bytes
+            && n_bytes > 0
+            && self.bytes()[n_bytes - 1] != b'\n'
+        {
+            self.line_ends.get_mut().pop();
+        }
+        Ok(Change::Appended)
+    }
+
+    /// Maps the file again, staying in the hex view if it is shown.
+    fn reopen(&mut self) -> Result<()> {
+        let bytes_per_row = self.bytes_per_row;
+        *self = Self::open(&self.path, self.tab_width)?;
+        self.bytes_per_row = bytes_per_row;
+        Ok(())
+    }
+
+    /// Tells whether the text looks binary rather than textual.
+    pub fn is_binary(&self) -> bool {
+        hex::is_binary(self.bytes())
+    }
+
+    pub fn is_hex(&self) -> bool {
+        self.bytes_per_row.is_some()
+    }
+
+    /// Shows the bytes in rows of the given length, or the lines again if there is none.
+    pub fn set_bytes_per_row(&mut self, bytes_per_row: Option<usize>) {
+        self.bytes_per_row = bytes_per_row;
+    }
+
+    pub fn bytes_per_row(&self) -> Option<usize> {
+        self.bytes_per_row
+    }
+
+    /// Returns the text as read, the edits excepted.
+    pub fn bytes(&self) -> &[u8] {
+        source_bytes(&self.source)
+    }
+
+    /// Scans the following chunks until the line is indexed or the end of the text is reached.
+    /// A last line without a newline is indexed too.
+    fn index_up_to(&self, line_index: usize) {
+        if self.edited_lines.is_some() {
+            return;
+        }
+        let bytes = self.bytes();
+        let mut line_ends = self.line_ends.borrow_mut();
+        while line_ends.len() <= line_index && self.n_scanned_bytes.get() < bytes.len() {
+            let begin = self.n_scanned_bytes.get();
+            let end = min(begin + CHUNK_SIZE, bytes.len());
+            let n_old_lines = line_ends.len();
+            line_ends.extend(memchr_iter(b'\n', &bytes[begin..end]).map(|index| begin + index));
+            if end == bytes.len() && bytes[end - 1] != b'\n' {
+                line_ends.push(end);
+            }
+            self.n_scanned_bytes.set(end);
+            for new_line_index in n_old_lines..line_ends.len() {
+                let text = decode(line_bytes(bytes, &line_ends, new_line_index));
+                self.max_line_width.set(max(
+                    self.max_line_width.get(),
+                    display_width(&text, self.tab_width),
+                ));
+            }
+        }
+    }
+
+    pub fn has_line(&self, line_index: usize) -> bool {
+        if let Some(bytes_per_row) = self.bytes_per_row {
+            return line_index.saturating_mul(bytes_per_row) < self.bytes().len();
+        }
+        self.index_up_to(line_index);
+        line_index < self.indexed_line_count()
+    }
+
+    /// Indexes the whole text to count its lines.
+    pub fn line_count(&self) -> usize {
+        self.index_up_to(usize::MAX);
+        self.indexed_line_count()
+    }
+
+    pub fn indexed_line_count(&self) -> usize {
+        if let Some(bytes_per_row) = self.bytes_per_row {
+            return self.bytes().len().div_ceil(bytes_per_row);
+        }
+        match &self.edited_lines {
+            Some(lines) => lines.len(),
+            None => self.line_ends.borrow().len(),
+        }
+    }
+
+    /// Tells whether all the lines are indexed, so that counting them is immediate.
+    pub fn is_fully_indexed(&self) -> bool {
+        self.is_hex()
+            || self.edited_lines.is_some()
+            || self.n_scanned_bytes.get() == self.bytes().len()
+    }
+
+    /// Returns the percentage of the text up to the end of the line, counted in lines
+    /// once they are all indexed, and in bytes before.
+    pub fn percent_at(&self, line_index: usize) -> usize {
+        if self.is_fully_indexed() {
+            let n_lines = self.indexed_line_count();
+            return (min(line_index + 1, n_lines) * 100)
+                .checked_div(n_lines)
+                .unwrap_or(100);
+        }
+        self.index_up_to(line_index);
+        let line_ends = self.line_ends.borrow();
+        let end = line_ends
+            .get(line_index)
+            .map_or(self.bytes().len(), |end| end + 1);
+        min(end, self.bytes().len()) * 100 / max(1, self.bytes().len())
+    }
+
+    /// Returns `\n`, or `\r\n` if the first line ends with it, but for the rows of the hex view.
+    pub fn line_ending(&self) -> &str {
+        if self.is_hex() {
+            return "\n";
+        }
+        self.line_ending
+    }
+
+    pub fn max_line_width(&self) -> usize {
+        if let Some(bytes_per_row) = self.bytes_per_row {
+            return hex::row_width(bytes_per_row);
+        }
+        self.max_line_width.get()
+    }
+
+    /// Returns the line without its CRLF or LF ending,
+    /// the invalid UTF-8 sequences being replaced by U+FFFD characters,
+    /// or the row of bytes in the hex view.
+    pub fn line(&self, line_index: usize) -> Cow<'_, str> {
+        if let Some(bytes_per_row) = self.bytes_per_row {
+            let offset = line_index * bytes_per_row;
+            let bytes = self.bytes();
+            let end = min(offset + bytes_per_row, bytes.len());
+            return Cow::Owned(hex::row(offset, &bytes[offset..end], bytes_per_row));
+        }
+        if let Some(lines) = &self.edited_lines {
+            return Cow::Borrowed(&lines[line_index]);
+        }
+        self.index_up_to(line_index);
+        decode(line_bytes(
+            self.bytes(),
+            &self.line_ends.borrow(),
+            line_index,
+        ))
+    }
+
+    /// Returns the bytes of the line as read, without its ending, and then its ending:
+    /// CRLF, LF, or else a carriage return or nothing at the end of the text.
+    /// Neither the rows of the hex view nor the edited lines are returned.
+    pub fn line_and_ending_bytes(&self, line_index: usize) -> (&[u8], &[u8]) {
+        self.index_up_to(line_index);
+        let bytes = self.bytes();
+        let line_ends = self.line_ends.borrow();
+        let line = line_bytes(bytes, &line_ends, line_index);
+        let line_end = line_ends[line_index];
+        let begin = line_end - line.len();
+        let text_end = line_end - usize::from(line.ends_with(b"\r"));
+        let ending_end = min(line_end + 1, bytes.len());
+        (&bytes[begin..text_end], &bytes[text_end..ending_end])
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// Decodes the lines, to edit them, keeping their endings to write them back unchanged.
+    /// An empty text has a single empty line once editable.
+    /// Fails if the text is not valid UTF-8, as saving the decoded lines would alter it.
+    pub fn make_editable(&mut self) -> Result<()> {
+        if self.edited_lines.is_some() {
+            return Ok(());
+        }
+        let n_lines = self.line_count();
+        let mut edited_lines = Vec::with_capacity(n_lines);
+        let mut edited_line_endings = Vec::with_capacity(n_lines);
+        for line_index in 0..n_lines {
+            let bytes = line_bytes(self.bytes(), &self.line_ends.borrow(), line_index);
+            let (bytes, ending) = match bytes.strip_suffix(b"\r") {
+                Some(bytes) => (bytes, "\r\n"),
+                None => (bytes, "\n"),
+            };
+            let line = std::str::from_utf8(bytes).map_err(|_| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("line {} is not valid UTF-8", line_index + 1),
+                )
+            })?;
+            edited_lines.push(line.to_string());
+            edited_line_endings.push(ending);
+        }
+        match edited_line_endings.last_mut() {
+            Some(ending) if self.bytes().last() != Some(&b'\n') => {
+                // A carriage return may end the text without a newline.
+                *ending = if *ending == "\r\n" { "\r" } else { "" };
+            }
+            Some(_) => {}
+            None => {
+                edited_lines.push(String::new());
+                edited_line_endings.push("");
+            }
+        }
+        self.edited_lines = Some(edited_lines);
+        self.edited_line_endings = edited_line_endings;
+        Ok(())
+    }
+
+    /// Replaces a range of lines of the editable text by others, returning the replaced ones.
+    /// The last inserted line keeps the ending of the last replaced one,
+    /// and the others the endings of the replaced lines before it, or else the usual one.
+    pub fn splice_lines(&mut self, range: Range<usize>, lines: Vec<String>) -> Vec<String> {
+        let mut endings: Vec<&str> = self.edited_line_endings[range.clone()].to_vec();
+        let last_ending = endings.pop().unwrap_or(self.line_ending);
+        endings.resize(lines.len().saturating_sub(1), self.line_ending);
+        if !lines.is_empty() {
+            endings.push(last_ending);
+        }
+        self.edited_line_endings.splice(range.clone(), endings);
+        for line in &lines {
+            self.max_line_width.set(max(
+                self.max_line_width.get(),
+                display_width(line, self.tab_width),
+            ));
+        }
+        self.modified = true;
+        self.edited_lines
+            .as_mut()
+            .unwrap()
+            .splice(range, lines)
+            .collect()
+    }
+
+    /// Writes the edited text to the file, every line with its own ending,
+    /// through a temporary file renamed over it so that a failure leaves it unchanged.
+    pub fn save(&mut self) -> Result<()> {
+        let Some(lines) = &self.edited_lines else {
+            return Ok(());
+        };
+        if self.is_from_stdin() {
+            return Err(Error::other("The standard input cannot be saved"));
+        }
+        let mut text = String::new();
+        for (line, ending) in lines.iter().zip(&self.edited_line_endings) {
+            text.push_str(line);
+            text.push_str(ending);
+        }
+        replace_file(Path::new(&self.path), text.as_bytes())?;
+        // The saved file is watched from now on, instead of the replaced one.
+        self.file = Some(File::open(&self.path)?);
+        self.source = Source::Buffered(text.into_bytes());
+        self.modified = false;
+        Ok(())
+    }
+}
+
+/// Writes the bytes to a temporary file beside the target of the path, with its permissions,
+/// and then renames it to the target.
+fn replace_file(path: &Path, bytes: &[u8]) -> Result<()> {
+    let path = fs::canonicalize(path)?;
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| Error::other("Not a file name"))?;
+    let temp_path = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        process::id()
+    ));
+    let result = fs::write(&temp_path, bytes)
+        .and_then(|()| fs::set_permissions(&temp_path, fs::metadata(&path)?.permissions()))
+        .and_then(|()| fs::rename(&temp_path, &path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn map(file: &File) -> Result<Mmap> {
+    // SAFETY: the mapping is only read; if another process truncates the file
+    // before it is mapped again, the program may crash, as many pagers do.
+    unsafe { Mmap::map(file) }
+}
+
+fn source_bytes(source: &Source) -> &[u8] {
+    match source {
+        Source::Mapped(mmap) => mmap,
+        Source::Buffered(bytes) => bytes,
+    }
+}
+
+fn line_bytes<'a>(bytes: &'a [u8], line_ends: &[usize], line_index: usize) -> &'a [u8] {
+    let line_begin = if line_index == 0 {
+        0
+    } else {
+        line_ends[line_index - 1] + 1
+    };
+    &bytes[line_begin..line_ends[line_index]]
+}
+
+#[cfg(unix)]
+fn is_same_file(metadata1: &Metadata, metadata2: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    (metadata1.dev(), metadata1.ino()) == (metadata2.dev(), metadata2.ino())
+}
+
+#[cfg(not(unix))]
+fn is_same_file(metadata1: &Metadata, metadata2: &Metadata) -> bool {
+    metadata1.created().ok() == metadata2.created().ok()
+}
+
+fn decode(line: &[u8]) -> Cow<'_, str> {
+    String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line))
+}
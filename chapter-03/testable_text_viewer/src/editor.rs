@@ -0,0 +1,71 @@
+//! The history of the changes made in edit mode, to undo and redo them.
+
+/// Line index and byte index in the line.
+pub type Position = (usize, usize);
+
+/// A change of the text: lines replaced by others, with the cursor positions around it.
+#[derive(Clone)]
+pub struct Step {
+    pub line_index: usize,
+    pub removed: Vec<String>,
+    pub inserted: Vec<String>,
+    pub cursor_before: Position,
+    pub cursor_after: Position,
+    /// Whether the change is a typed character, merged with the characters typed just before.
+    pub typing: bool,
+}
+
+impl Step {
+    /// Returns the change restoring the text and the cursor as they were before this one.
+    pub fn reversed(&self) -> Self {
+        Self {
+            line_index: self.line_index,
+            removed: self.inserted.clone(),
+            inserted: self.removed.clone(),
+            cursor_before: self.cursor_after,
+            cursor_after: self.cursor_before,
+            typing: false,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct History {
+    done: Vec<Step>,
+    undone: Vec<Step>,
+}
+
+impl History {
+    /// Records a change, which can no longer be followed by the undone ones.
+    /// Characters typed in a row on a line are undone together.
+    pub fn record(&mut self, step: Step) {
+        self.undone.clear();
+        if let Some(last) = self.done.last_mut() {
+            if step.typing
+                && last.typing
+                && last.line_index == step.line_index
+                && last.cursor_after == step.cursor_before
+            {
+                last.inserted = step.inserted;
+                last.cursor_after = step.cursor_after;
+                return;
+            }
+        }
+        self.done.push(step);
+    }
+
+    /// Returns the change undoing the last one done, if any.
+    pub fn undo(&mut self) -> Option<Step> {
+        let step = self.done.pop()?;
+        let reversed = step.reversed();
+        self.undone.push(step);
+        Some(reversed)
+    }
+
+    /// Returns the last change undone, if any, to do it again.
+    pub fn redo(&mut self) -> Option<Step> {
+        let step = self.undone.pop()?;
+        self.done.push(step.clone());
+        Some(step)
+    }
+}
@@ -0,0 +1,133 @@
+use std::cell::{Cell, RefCell};
+
+use crate::document::Document;
+use crate::search::Search;
+
+/// Number of lines checked at once when shown lines beyond the known ones are needed.
+const CHUNK_LINES: usize = 1024;
+
+/// A pattern which the lines must contain, or must not contain if it excludes them.
+struct Rule {
+    search: Search,
+    exclude: bool,
+}
+
+/// The patterns hiding lines of a document, applied together while the filter is enabled.
+/// The lines are checked only as far as they are needed, one chunk at a time.
+#[derive(Default)]
+pub struct Filter {
+    rules: Vec<Rule>,
+    enabled: bool,
+    /// Indexes of the lines shown, among the ones checked so far.
+    shown_lines: RefCell<Vec<usize>>,
+    n_checked_lines: Cell<usize>,
+}
+
+impl Filter {
+    /// Adds a rule, and enables the filter.
+    pub fn push(&mut self, search: Search, exclude: bool) {
+        self.rules.push(Rule { search, exclude });
+        self.enabled = true;
+        self.forget_from(0);
+    }
+
+    /// Removes the last rule added, returning whether there was one.
+    pub fn pop(&mut self) -> bool {
+        self.forget_from(0);
+        self.rules.pop().is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.rules.clear();
+        self.forget_from(0);
+    }
+
+    /// Suspends or resumes the filtering, keeping the rules; returns whether there are rules.
+    pub fn toggle(&mut self) -> bool {
+        self.enabled = !self.enabled;
+        !self.rules.is_empty()
+    }
+
+    /// Tells whether lines may be hidden.
+    pub fn is_active(&self) -> bool {
+        self.enabled && !self.rules.is_empty()
+    }
+
+    /// Returns the rules as they are typed after `:filter`, with `!` before the excluding ones.
+    pub fn describe(&self) -> Vec<String> {
+        self.rules
+            .iter()
+            .map(|rule| {
+                let pattern = if rule.search.is_regex {
+                    format!("/{}/", rule.search.pattern)
+                } else {
+                    rule.search.pattern.clone()
+                };
+                if rule.exclude {
+                    format!("!{pattern}")
+                } else {
+                    pattern
+                }
+            })
+            .collect()
+    }
+
+    /// Tells whether the line matches every including rule, and no excluding one.
+    fn accepts(&self, line: &str) -> bool {
+        self.rules
+            .iter()
+            .all(|rule| rule.search.find_in_line(line).is_empty() == rule.exclude)
+    }
+
+    /// Checks the following lines, up to the one before `end` or the end of the text.
+    fn check_up_to(&self, document: &Document, end: usize) {
+        let mut line_index = self.n_checked_lines.get();
+        let mut shown_lines = self.shown_lines.borrow_mut();
+        while line_index < end && document.has_line(line_index) {
+            if self.accepts(&document.line(line_index)) {
+                shown_lines.push(line_index);
+            }
+            line_index += 1;
+        }
+        self.n_checked_lines.set(line_index);
+    }
+
+    /// Returns the first line shown from the line included.
+    pub fn next_line(&self, document: &Document, line_index: usize) -> Option<usize> {
+        loop {
+            {
+                let shown_lines = self.shown_lines.borrow();
+                let position = shown_lines.partition_point(|shown| *shown < line_index);
+                if let Some(shown) = shown_lines.get(position) {
+                    return Some(*shown);
+                }
+            }
+            let n_checked_lines = self.n_checked_lines.get();
+            if !document.has_line(n_checked_lines) {
+                return None;
+            }
+            self.check_up_to(
+                document,
+                n_checked_lines.max(line_index).saturating_add(CHUNK_LINES),
+            );
+        }
+    }
+
+    /// Returns the last line shown before the line.
+    pub fn previous_line(&self, document: &Document, line_index: usize) -> Option<usize> {
+        self.check_up_to(document, line_index);
+        let shown_lines = self.shown_lines.borrow();
+        let position = shown_lines.partition_point(|shown| *shown < line_index);
+        position
+            .checked_sub(1)
+            .map(|position| shown_lines[position])
+    }
+
+    /// Forgets what was checked from the line, which may have changed.
+    pub fn forget_from(&mut self, line_index: usize) {
+        let shown_lines = self.shown_lines.get_mut();
+        shown_lines.truncate(shown_lines.partition_point(|shown| *shown < line_index));
+        self.n_checked_lines
+            .set(self.n_checked_lines.get().min(line_index));
+    }
+}
@@ -0,0 +1,60 @@
+//! Rows of the hex view, like those of `hexdump -C`: the offset of the first byte,
+//! the bytes in hexadecimal by groups of 8, and the bytes as ASCII characters.
+
+use std::fmt::Write;
+
+/// Number of bytes looked at to tell whether a file is binary.
+const SNIFFED_LENGTH: usize = 8192;
+
+/// Number of bytes of a group, separated from the next one by an extra space.
+const GROUP_LENGTH: usize = 8;
+
+/// Number of bytes per row when the width is unknown, as in the stream, like `hexdump -C`.
+pub const DEFAULT_BYTES_PER_ROW: usize = 16;
+
+/// A file is considered binary if it starts with a null byte in its first kilobytes, as Git does.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(SNIFFED_LENGTH)].contains(&0)
+}
+
+/// Width of a row showing the number of bytes.
+pub fn row_width(bytes_per_row: usize) -> usize {
+    // The offset, a space per group and 3 columns per byte,
+    // and the ASCII pane between bars, after 2 spaces.
+    8 + bytes_per_row / GROUP_LENGTH + bytes_per_row * 3 + 2 + 1 + bytes_per_row + 1
+}
+
+/// Returns the greatest number of bytes, by whole groups, whose rows fit in the width,
+/// and a group if none fits.
+pub fn bytes_per_row(width: usize) -> usize {
+    let mut bytes_per_row = GROUP_LENGTH;
+    while row_width(bytes_per_row + GROUP_LENGTH) <= width {
+        bytes_per_row += GROUP_LENGTH;
+    }
+    bytes_per_row
+}
+
+/// Formats the bytes of a row, which are fewer than the row can show on the last one,
+/// the ASCII pane being then aligned with the other rows.
+pub fn row(offset: usize, bytes: &[u8], bytes_per_row: usize) -> String {
+    let mut row = format!("{offset:08x}");
+    for index in 0..bytes_per_row {
+        if index % GROUP_LENGTH == 0 {
+            row.push(' ');
+        }
+        match bytes.get(index) {
+            Some(byte) => write!(row, " {byte:02x}").unwrap(),
+            None => row.push_str("   "),
+        }
+    }
+    row.push_str("  |");
+    row.extend(bytes.iter().map(|byte| {
+        if byte.is_ascii_graphic() || *byte == b' ' {
+            char::from(*byte)
+        } else {
+            '.'
+        }
+    }));
+    row.push('|');
+    row
+}
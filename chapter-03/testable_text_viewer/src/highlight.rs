@@ -0,0 +1,657 @@
+use crossterm::style::Color;
+use std::cell::RefCell;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::document::Document;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TokenKind {
+    Text,
+    Keyword,
+    Type,
+    Macro,
+    Attribute,
+    String,
+    /// Numbers, booleans, dates and `null`.
+    Literal,
+    Comment,
+    Punctuation,
+    Key,
+    Heading,
+    Emphasis,
+    Code,
+    Link,
+}
+
+/// Names of the token kinds in the theme files, the text out of any token excepted.
+const TOKEN_KIND_NAMES: [(TokenKind, &str); 13] = [
+    (TokenKind::Keyword, "keyword"),
+    (TokenKind::Type, "type"),
+    (TokenKind::Macro, "macro"),
+    (TokenKind::Attribute, "attribute"),
+    (TokenKind::String, "string"),
+    (TokenKind::Literal, "literal"),
+    (TokenKind::Comment, "comment"),
+    (TokenKind::Punctuation, "punctuation"),
+    (TokenKind::Key, "key"),
+    (TokenKind::Heading, "heading"),
+    (TokenKind::Emphasis, "emphasis"),
+    (TokenKind::Code, "code"),
+    (TokenKind::Link, "link"),
+];
+
+impl TokenKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        TOKEN_KIND_NAMES
+            .iter()
+            .find(|(_, kind_name)| *kind_name == name)
+            .map(|(kind, _)| *kind)
+    }
+}
+
+/// Maps the token kinds to colors, readable on the background of the text.
+pub struct TokenColors {
+    pub text: Color,
+    pub keyword: Color,
+    pub type_name: Color,
+    pub macro_name: Color,
+    pub attribute: Color,
+    pub string: Color,
+    pub literal: Color,
+    pub comment: Color,
+    pub punctuation: Color,
+    pub key: Color,
+    pub heading: Color,
+    pub emphasis: Color,
+    pub code: Color,
+    pub link: Color,
+}
+
+impl Default for TokenColors {
+    fn default() -> Self {
+        Self {
+            text: Color::Black,
+            keyword: Color::DarkBlue,
+            type_name: Color::DarkCyan,
+            macro_name: Color::DarkMagenta,
+            attribute: Color::DarkYellow,
+            string: Color::DarkRed,
+            literal: Color::DarkMagenta,
+            comment: Color::DarkGreen,
+            punctuation: Color::Blue,
+            key: Color::DarkBlue,
+            heading: Color::DarkRed,
+            emphasis: Color::DarkMagenta,
+            code: Color::DarkCyan,
+            link: Color::Blue,
+        }
+    }
+}
+
+impl TokenColors {
+    /// Black tokens, but for the comments, which are dark grey.
+    pub fn monochrome() -> Self {
+        Self {
+            text: Color::Black,
+            keyword: Color::Black,
+            type_name: Color::Black,
+            macro_name: Color::Black,
+            attribute: Color::Black,
+            string: Color::Black,
+            literal: Color::Black,
+            comment: Color::DarkGrey,
+            punctuation: Color::Black,
+            key: Color::Black,
+            heading: Color::Black,
+            emphasis: Color::Black,
+            code: Color::Black,
+            link: Color::Black,
+        }
+    }
+
+    pub fn color(&self, kind: TokenKind) -> Color {
+        match kind {
+            TokenKind::Text => self.text,
+            TokenKind::Keyword => self.keyword,
+            TokenKind::Type => self.type_name,
+            TokenKind::Macro => self.macro_name,
+            TokenKind::Attribute => self.attribute,
+            TokenKind::String => self.string,
+            TokenKind::Literal => self.literal,
+            TokenKind::Comment => self.comment,
+            TokenKind::Punctuation => self.punctuation,
+            TokenKind::Key => self.key,
+            TokenKind::Heading => self.heading,
+            TokenKind::Emphasis => self.emphasis,
+            TokenKind::Code => self.code,
+            TokenKind::Link => self.link,
+        }
+    }
+
+    pub fn color_mut(&mut self, kind: TokenKind) -> &mut Color {
+        match kind {
+            TokenKind::Text => &mut self.text,
+            TokenKind::Keyword => &mut self.keyword,
+            TokenKind::Type => &mut self.type_name,
+            TokenKind::Macro => &mut self.macro_name,
+            TokenKind::Attribute => &mut self.attribute,
+            TokenKind::String => &mut self.string,
+            TokenKind::Literal => &mut self.literal,
+            TokenKind::Comment => &mut self.comment,
+            TokenKind::Punctuation => &mut self.punctuation,
+            TokenKind::Key => &mut self.key,
+            TokenKind::Heading => &mut self.heading,
+            TokenKind::Emphasis => &mut self.emphasis,
+            TokenKind::Code => &mut self.code,
+            TokenKind::Link => &mut self.link,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Language {
+    Plain,
+    Rust,
+    Toml,
+    Json,
+    Markdown,
+}
+
+impl Language {
+    /// Chosen by the extension of the file.
+    pub fn from_path(path: &str) -> Self {
+        match Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("rs") => Language::Rust,
+            Some("toml") => Language::Toml,
+            Some("json") => Language::Json,
+            Some("md" | "markdown") => Language::Markdown,
+            _ => Language::Plain,
+        }
+    }
+}
+
+/// What is still open at the end of a line, and continues on the next one.
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Normal,
+    /// A Rust block comment, with its nesting depth.
+    BlockComment(usize),
+    /// A Rust string, closed by a quote followed by the hashes of a raw string.
+    RustString {
+        raw: bool,
+        n_hashes: usize,
+    },
+    /// A TOML multi-line string, closed by three of its quotes.
+    TomlString(char),
+    /// A Markdown fenced code block, closed by a fence at least as long.
+    CodeBlock {
+        fence: char,
+        length: usize,
+    },
+}
+
+/// Tokenizes the lines of a document, remembering the state at the start of every line seen.
+pub struct Highlighter {
+    language: Language,
+    line_states: RefCell<Vec<State>>,
+}
+
+impl Highlighter {
+    pub fn new(language: Language) -> Self {
+        Self {
+            language,
+            line_states: RefCell::new(vec![State::Normal]),
+        }
+    }
+
+    /// Tells whether the lines have tokens, their language not being plain text.
+    pub fn is_enabled(&self) -> bool {
+        self.language != Language::Plain
+    }
+
+    /// Must be called when the lines of the document change from this one.
+    pub fn forget_from(&mut self, line_index: usize) {
+        self.line_states.get_mut().truncate(line_index + 1);
+    }
+
+    /// Returns the byte ranges and the kinds of the tokens of the line; other bytes are text.
+    pub fn tokens(&self, document: &Document, line_index: usize) -> Vec<(Range<usize>, TokenKind)> {
+        if self.language == Language::Plain {
+            return vec![];
+        }
+        let mut line_states = self.line_states.borrow_mut();
+        while line_states.len() <= line_index {
+            let previous_index = line_states.len() - 1;
+            let (_, state) = tokenize(
+                self.language,
+                &document.line(previous_index),
+                line_states[previous_index],
+            );
+            line_states.push(state);
+        }
+        tokenize(
+            self.language,
+            &document.line(line_index),
+            line_states[line_index],
+        )
+        .0
+    }
+}
+
+fn tokenize(
+    language: Language,
+    line: &str,
+    state: State,
+) -> (Vec<(Range<usize>, TokenKind)>, State) {
+    let mut scanner = Scanner {
+        line,
+        position: 0,
+        tokens: vec![],
+    };
+    let state = match language {
+        Language::Plain => state,
+        Language::Rust => tokenize_rust(&mut scanner, state),
+        Language::Toml => tokenize_toml(&mut scanner, state),
+        Language::Json => tokenize_json(&mut scanner),
+        Language::Markdown => tokenize_markdown(&mut scanner, state),
+    };
+    (scanner.tokens, state)
+}
+
+struct Scanner<'a> {
+    line: &'a str,
+    position: usize,
+    tokens: Vec<(Range<usize>, TokenKind)>,
+}
+
+impl<'a> Scanner<'a> {
+    fn rest(&self) -> &'a str {
+        &self.line[self.position..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn advance(&mut self) {
+        self.position += self.peek().map_or(0, char::len_utf8);
+    }
+
+    fn skip_while(&mut self, predicate: impl Fn(char) -> bool) {
+        while self.peek().is_some_and(&predicate) {
+            self.advance();
+        }
+    }
+
+    fn skip_to_end(&mut self) {
+        self.position = self.line.len();
+    }
+
+    /// Adds the token from `begin` to the current position, unless it is empty.
+    fn push(&mut self, begin: usize, kind: TokenKind) {
+        if self.position > begin {
+            self.tokens.push((begin..self.position, kind));
+        }
+    }
+
+    /// Moves after the closing delimiter, if it is in the line, skipping the escaped characters.
+    fn skip_string(&mut self, closing: &str, escapes: bool) -> bool {
+        loop {
+            if self.rest().starts_with(closing) {
+                self.position += closing.len();
+                return true;
+            }
+            match self.peek() {
+                None => return false,
+                Some('\\') if escapes => {
+                    self.advance();
+                    self.advance();
+                }
+                _ => self.advance(),
+            }
+        }
+    }
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "type", "unsafe", "use",
+    "where", "while",
+];
+
+/// Scans the rest of a block comment or of a string, returning the state after it.
+fn continue_rust(scanner: &mut Scanner, state: State, begin: usize) -> State {
+    match state {
+        State::BlockComment(mut depth) => {
+            while depth > 0 {
+                if scanner.rest().starts_with("/*") {
+                    scanner.position += 2;
+                    depth += 1;
+                } else if scanner.rest().starts_with("*/") {
+                    scanner.position += 2;
+                    depth -= 1;
+                } else if scanner.peek().is_none() {
+                    break;
+                } else {
+                    scanner.advance();
+                }
+            }
+            scanner.push(begin, TokenKind::Comment);
+            if depth > 0 {
+                State::BlockComment(depth)
+            } else {
+                State::Normal
+            }
+        }
+        State::RustString { raw, n_hashes } => {
+            let closing = format!("\"{}", "#".repeat(n_hashes));
+            let closed = scanner.skip_string(&closing, !raw);
+            scanner.push(begin, TokenKind::String);
+            if closed {
+                State::Normal
+            } else {
+                state
+            }
+        }
+        _ => state,
+    }
+}
+
+fn tokenize_rust(scanner: &mut Scanner, state: State) -> State {
+    let mut state = continue_rust(scanner, state, 0);
+    while state == State::Normal {
+        let Some(c) = scanner.peek() else {
+            break;
+        };
+        let begin = scanner.position;
+        let rest = scanner.rest();
+        if rest.starts_with("//") {
+            scanner.skip_to_end();
+            scanner.push(begin, TokenKind::Comment);
+        } else if rest.starts_with("/*") {
+            scanner.position += 2;
+            state = continue_rust(scanner, State::BlockComment(1), begin);
+        } else if let Some((prefix_length, n_hashes)) = raw_string_prefix(rest) {
+            scanner.position += prefix_length;
+            let string_state = State::RustString {
+                raw: true,
+                n_hashes,
+            };
+            state = continue_rust(scanner, string_state, begin);
+        } else if rest.starts_with('"') || rest.starts_with("b\"") {
+            scanner.position += rest.find('"').unwrap_or(0) + 1;
+            let string_state = State::RustString {
+                raw: false,
+                n_hashes: 0,
+            };
+            state = continue_rust(scanner, string_state, begin);
+        } else if let Some(length) = char_literal_length(rest) {
+            scanner.position += length;
+            scanner.push(begin, TokenKind::String);
+        } else if rest.starts_with("#[") || rest.starts_with("#![") {
+            scanner.position += rest.find(']').map_or(rest.len(), |index| index + 1);
+            scanner.push(begin, TokenKind::Attribute);
+        } else if c.is_ascii_digit() {
+            scanner.skip_while(|c| c.is_ascii_alphanumeric() || c == '_');
+            if scanner.rest().starts_with('.')
+                && scanner.rest()[1..].starts_with(|c: char| c.is_ascii_digit())
+            {
+                scanner.advance();
+                scanner.skip_while(|c| c.is_ascii_alphanumeric() || c == '_');
+            }
+            scanner.push(begin, TokenKind::Literal);
+        } else if c.is_alphabetic() || c == '_' {
+            scanner.skip_while(|c| c.is_alphanumeric() || c == '_');
+            let word = &scanner.line[begin..scanner.position];
+            let kind = if scanner.peek() == Some('!') {
+                scanner.advance();
+                TokenKind::Macro
+            } else if RUST_KEYWORDS.contains(&word) {
+                TokenKind::Keyword
+            } else if word == "true" || word == "false" {
+                TokenKind::Literal
+            } else if word.starts_with(char::is_uppercase) {
+                TokenKind::Type
+            } else {
+                TokenKind::Text
+            };
+            scanner.push(begin, kind);
+        } else if c.is_whitespace() {
+            scanner.skip_while(char::is_whitespace);
+        } else {
+            scanner.advance();
+            scanner.push(begin, TokenKind::Punctuation);
+        }
+    }
+    state
+}
+
+/// Returns the length of the prefix of a raw string, up to its quote, and its number of hashes.
+fn raw_string_prefix(rest: &str) -> Option<(usize, usize)> {
+    let after_r = rest.strip_prefix("br").or_else(|| rest.strip_prefix('r'))?;
+    let n_hashes = after_r.len() - after_r.trim_start_matches('#').len();
+    after_r[n_hashes..]
+        .starts_with('"')
+        .then_some((rest.len() - after_r.len() + n_hashes + 1, n_hashes))
+}
+
+/// A quote not followed by a character and a quote starts a lifetime.
+fn char_literal_length(rest: &str) -> Option<usize> {
+    let inner = rest.strip_prefix('\'')?;
+    let length = if inner.starts_with('\\') {
+        2 + inner.get(2..)?.find('\'')?
+    } else {
+        inner.chars().next()?.len_utf8()
+    };
+    inner[length..].starts_with('\'').then_some(length + 2)
+}
+
+fn continue_toml(scanner: &mut Scanner, state: State, begin: usize) -> State {
+    if let State::TomlString(quote) = state {
+        let closed = scanner.skip_string(&quote.to_string().repeat(3), quote == '"');
+        scanner.push(begin, TokenKind::String);
+        if closed {
+            return State::Normal;
+        }
+    }
+    state
+}
+
+fn tokenize_toml(scanner: &mut Scanner, state: State) -> State {
+    let mut state = continue_toml(scanner, state, 0);
+    if state == State::Normal {
+        scanner.skip_while(char::is_whitespace);
+        let begin = scanner.position;
+        let rest = scanner.rest();
+        if rest.starts_with('[') {
+            let length = rest.find(']').map_or(rest.len(), |index| index + 1);
+            scanner.position += length + usize::from(rest[length..].starts_with(']'));
+            scanner.push(begin, TokenKind::Heading);
+        } else if let Some(length) = toml_key_length(rest) {
+            scanner.position += length;
+            scanner.push(begin, TokenKind::Key);
+        }
+    }
+    while state == State::Normal {
+        let Some(c) = scanner.peek() else {
+            break;
+        };
+        let begin = scanner.position;
+        let rest = scanner.rest();
+        if c == '#' {
+            scanner.skip_to_end();
+            scanner.push(begin, TokenKind::Comment);
+        } else if rest.starts_with("\"\"\"") || rest.starts_with("'''") {
+            scanner.position += 3;
+            state = continue_toml(scanner, State::TomlString(c), begin);
+        } else if c == '"' || c == '\'' {
+            scanner.advance();
+            scanner.skip_string(&c.to_string(), c == '"');
+            scanner.push(begin, TokenKind::String);
+        } else if c.is_ascii_digit()
+            || (c == '+' || c == '-') && rest[1..].starts_with(|c: char| c.is_ascii_digit())
+        {
+            scanner.advance();
+            scanner.skip_while(|c| c.is_ascii_alphanumeric() || "_.:+-".contains(c));
+            scanner.push(begin, TokenKind::Literal);
+        } else if c.is_alphabetic() {
+            scanner.skip_while(char::is_alphanumeric);
+            let word = &scanner.line[begin..scanner.position];
+            if ["true", "false", "inf", "nan"].contains(&word) {
+                scanner.push(begin, TokenKind::Literal);
+            }
+        } else if c.is_whitespace() {
+            scanner.skip_while(char::is_whitespace);
+        } else {
+            scanner.advance();
+            scanner.push(begin, TokenKind::Punctuation);
+        }
+    }
+    state
+}
+
+/// Returns the length of the key starting the line, if it is followed by `=`.
+fn toml_key_length(rest: &str) -> Option<usize> {
+    let mut quote = None;
+    for (index, c) in rest.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '=' => return Some(rest[..index].trim_end().len()).filter(|l| *l > 0),
+            None if c.is_alphanumeric() || "_-. ".contains(c) => {}
+            None => return None,
+        }
+    }
+    None
+}
+
+fn tokenize_json(scanner: &mut Scanner) -> State {
+    while let Some(c) = scanner.peek() {
+        let begin = scanner.position;
+        if c == '"' {
+            scanner.advance();
+            scanner.skip_string("\"", true);
+            let kind = if scanner.rest().trim_start().starts_with(':') {
+                TokenKind::Key
+            } else {
+                TokenKind::String
+            };
+            scanner.push(begin, kind);
+        } else if c.is_ascii_digit() || c == '-' {
+            scanner.skip_while(|c| c.is_ascii_digit() || ".eE+-".contains(c));
+            scanner.push(begin, TokenKind::Literal);
+        } else if c.is_alphabetic() {
+            scanner.skip_while(char::is_alphanumeric);
+            let word = &scanner.line[begin..scanner.position];
+            if ["true", "false", "null"].contains(&word) {
+                scanner.push(begin, TokenKind::Literal);
+            }
+        } else if c.is_whitespace() {
+            scanner.skip_while(char::is_whitespace);
+        } else {
+            scanner.advance();
+            scanner.push(begin, TokenKind::Punctuation);
+        }
+    }
+    State::Normal
+}
+
+/// Returns the character and the length of the fence starting the line, if any.
+fn code_fence(line: &str) -> Option<(char, usize)> {
+    let trimmed = line.trim_start();
+    let fence = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = trimmed.len() - trimmed.trim_start_matches(fence).len();
+    (length >= 3).then_some((fence, length))
+}
+
+fn tokenize_markdown(scanner: &mut Scanner, state: State) -> State {
+    let line = scanner.line;
+    let fence = code_fence(line);
+    if let State::CodeBlock { fence: c, length } = state {
+        scanner.skip_to_end();
+        let is_closing = fence.is_some_and(|(fence_c, fence_length)| {
+            fence_c == c && fence_length >= length && line.trim().len() == fence_length
+        });
+        if is_closing {
+            scanner.push(0, TokenKind::Punctuation);
+            return State::Normal;
+        }
+        scanner.push(0, TokenKind::Code);
+        return state;
+    }
+    if let Some((c, length)) = fence {
+        scanner.skip_to_end();
+        scanner.push(0, TokenKind::Punctuation);
+        return State::CodeBlock { fence: c, length };
+    }
+    scanner.skip_while(char::is_whitespace);
+    let begin = scanner.position;
+    let rest = scanner.rest();
+    let heading_level = rest.len() - rest.trim_start_matches('#').len();
+    if (1..=6).contains(&heading_level)
+        && (rest[heading_level..].is_empty() || rest[heading_level..].starts_with(' '))
+    {
+        scanner.skip_to_end();
+        scanner.push(begin, TokenKind::Heading);
+        return State::Normal;
+    }
+    if rest.starts_with('>') {
+        scanner.skip_to_end();
+        scanner.push(begin, TokenKind::Comment);
+        return State::Normal;
+    }
+    let n_digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if ["- ", "* ", "+ "]
+        .iter()
+        .any(|marker| rest.starts_with(marker))
+    {
+        scanner.advance();
+    } else if n_digits > 0 && rest[n_digits..].starts_with(". ") {
+        scanner.position += n_digits + 1;
+    }
+    scanner.push(begin, TokenKind::Punctuation);
+    while let Some(c) = scanner.peek() {
+        let begin = scanner.position;
+        let rest = scanner.rest();
+        let previous = line[..begin].chars().next_back();
+        if c == '`' || c == '*' || c == '_' && !previous.is_some_and(char::is_alphanumeric) {
+            // A span is closed by the same run of delimiters.
+            let length = rest.len() - rest.trim_start_matches(c).len();
+            let delimiter = &rest[..length];
+            scanner.position += length;
+            if scanner.skip_string(delimiter, c != '`') && scanner.position > begin + 2 * length {
+                let kind = if c == '`' {
+                    TokenKind::Code
+                } else {
+                    TokenKind::Emphasis
+                };
+                scanner.push(begin, kind);
+            } else {
+                scanner.position = begin + length;
+            }
+        } else if c == '[' {
+            match rest
+                .find("](")
+                .and_then(|index| Some(index + rest[index..].find(')')?))
+            {
+                Some(end) => {
+                    scanner.position += end + 1;
+                    scanner.push(begin, TokenKind::Link);
+                }
+                None => scanner.advance(),
+            }
+        } else if c == '\\' {
+            scanner.advance();
+            scanner.advance();
+        } else {
+            scanner.advance();
+        }
+    }
+    State::Normal
+}
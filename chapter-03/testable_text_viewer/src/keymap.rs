@@ -0,0 +1,312 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::io::{Error, Result};
+
+/// What the keys do, named as in the keymap files and in the event scripts.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    LineDown,
+    LineUp,
+    PageDown,
+    PageUp,
+    ScrollRight,
+    ScrollLeft,
+    LineStart,
+    LineEnd,
+    Top,
+    Bottom,
+    ToggleWrap,
+    ToggleFollow,
+    SearchForward,
+    SearchBackward,
+    NextMatch,
+    PreviousMatch,
+    CommandLine,
+    SwitchPane,
+    Edit,
+    Save,
+    Undo,
+    Redo,
+    SetBookmark,
+    GoToBookmark,
+    JumpBack,
+    JumpForward,
+    ToggleFilter,
+    Quit,
+}
+
+const ACTION_NAMES: [(Action, &str); 28] = [
+    (Action::LineDown, "line_down"),
+    (Action::LineUp, "line_up"),
+    (Action::PageDown, "page_down"),
+    (Action::PageUp, "page_up"),
+    (Action::ScrollRight, "scroll_right"),
+    (Action::ScrollLeft, "scroll_left"),
+    (Action::LineStart, "line_start"),
+    (Action::LineEnd, "line_end"),
+    (Action::Top, "top"),
+    (Action::Bottom, "bottom"),
+    (Action::ToggleWrap, "toggle_wrap"),
+    (Action::ToggleFollow, "toggle_follow"),
+    (Action::SearchForward, "search_forward"),
+    (Action::SearchBackward, "search_backward"),
+    (Action::NextMatch, "next_match"),
+    (Action::PreviousMatch, "previous_match"),
+    (Action::CommandLine, "command_line"),
+    (Action::SwitchPane, "switch_pane"),
+    (Action::Edit, "edit"),
+    (Action::Save, "save"),
+    (Action::Undo, "undo"),
+    (Action::Redo, "redo"),
+    (Action::SetBookmark, "set_bookmark"),
+    (Action::GoToBookmark, "go_to_bookmark"),
+    (Action::JumpBack, "jump_back"),
+    (Action::JumpForward, "jump_forward"),
+    (Action::ToggleFilter, "toggle_filter"),
+    (Action::Quit, "quit"),
+];
+
+impl Action {
+    pub fn name(self) -> &'static str {
+        ACTION_NAMES
+            .iter()
+            .find(|(action, _)| *action == self)
+            .map(|(_, name)| *name)
+            .unwrap()
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        ACTION_NAMES
+            .iter()
+            .find(|(_, action_name)| *action_name == name)
+            .map(|(action, _)| *action)
+    }
+}
+
+const DEFAULT_BINDINGS: &[(&str, Action)] = &[
+    ("down", Action::LineDown),
+    ("up", Action::LineUp),
+    ("page_down", Action::PageDown),
+    ("page_up", Action::PageUp),
+    ("right", Action::ScrollRight),
+    ("left", Action::ScrollLeft),
+    ("home", Action::LineStart),
+    ("end", Action::LineEnd),
+    ("w", Action::ToggleWrap),
+    ("F", Action::ToggleFollow),
+    ("/", Action::SearchForward),
+    ("?", Action::SearchBackward),
+    ("n", Action::NextMatch),
+    ("N", Action::PreviousMatch),
+    (":", Action::CommandLine),
+    ("C w", Action::SwitchPane),
+    ("i", Action::Edit),
+    ("insert", Action::Edit),
+    ("C s", Action::Save),
+    ("C z", Action::Undo),
+    ("C y", Action::Redo),
+    ("m", Action::SetBookmark),
+    ("'", Action::GoToBookmark),
+    ("C o", Action::JumpBack),
+    ("A left", Action::JumpBack),
+    ("A right", Action::JumpForward),
+    ("&", Action::ToggleFilter),
+    ("esc", Action::Quit),
+    ("C q", Action::Quit),
+];
+
+/// Added to the default bindings by the `vi` preset.
+const VI_BINDINGS: &[(&str, Action)] = &[
+    ("j", Action::LineDown),
+    ("k", Action::LineUp),
+    ("C f", Action::PageDown),
+    ("C b", Action::PageUp),
+    ("l", Action::ScrollRight),
+    ("h", Action::ScrollLeft),
+    ("0", Action::LineStart),
+    ("$", Action::LineEnd),
+    ("g", Action::Top),
+    ("G", Action::Bottom),
+    ("u", Action::Undo),
+    ("C r", Action::Redo),
+];
+
+/// Added to the default bindings by the `less` preset.
+const LESS_BINDINGS: &[(&str, Action)] = &[
+    ("j", Action::LineDown),
+    ("e", Action::LineDown),
+    ("enter", Action::LineDown),
+    ("k", Action::LineUp),
+    ("y", Action::LineUp),
+    ("space", Action::PageDown),
+    ("f", Action::PageDown),
+    ("C f", Action::PageDown),
+    ("b", Action::PageUp),
+    ("C b", Action::PageUp),
+    ("g", Action::Top),
+    ("<", Action::Top),
+    ("G", Action::Bottom),
+    (">", Action::Bottom),
+    ("q", Action::Quit),
+    ("Q", Action::Quit),
+];
+
+/// Parses a key as written in the event scripts and in the keymap files: a name,
+/// like `page_down`, `f5`, `n`, or `'#'`, preceded by any modifiers among `C` for Ctrl,
+/// `A` for Alt and `S` for Shift, like `C q`.
+pub fn parse_key(text: &str) -> std::result::Result<KeyEvent, String> {
+    let mut words: Vec<&str> = text.split(' ').filter(|word| !word.is_empty()).collect();
+    let name = words.pop().ok_or("Missing key")?;
+    let mut modifiers = KeyModifiers::empty();
+    for word in words {
+        modifiers |= match word {
+            "C" => KeyModifiers::CONTROL,
+            "A" => KeyModifiers::ALT,
+            "S" => KeyModifiers::SHIFT,
+            _ => return Err(format!("Unknown key modifier: {word}")),
+        };
+    }
+    let key_code = match name {
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "page_up" => KeyCode::PageUp,
+        "page_down" => KeyCode::PageDown,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "tab" => KeyCode::Tab,
+        "backtab" => KeyCode::BackTab,
+        "delete" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        "esc" => KeyCode::Esc,
+        "enter" => KeyCode::Enter,
+        "backspace" => KeyCode::Backspace,
+        "space" => KeyCode::Char(' '),
+        "hash" => KeyCode::Char('#'),
+        _ => {
+            let chars: Vec<char> = name.chars().collect();
+            match chars[..] {
+                [c] | ['\'', c, '\''] => KeyCode::Char(c),
+                _ => match name
+                    .strip_prefix('f')
+                    .and_then(|number| number.parse().ok())
+                {
+                    Some(number @ 1..=12) => KeyCode::F(number),
+                    _ => return Err(format!("Unknown key: {name}")),
+                },
+            }
+        }
+    };
+    Ok(KeyEvent::new(key_code, modifiers))
+}
+
+/// Returns how the key is written in the event scripts and in the keymap files,
+/// the Shift modifier of characters being left out.
+pub fn key_name(event: &KeyEvent) -> String {
+    let mut name = String::new();
+    let (code, modifiers) = binding_key(event);
+    for (modifier, letter) in [
+        (KeyModifiers::CONTROL, "C "),
+        (KeyModifiers::ALT, "A "),
+        (KeyModifiers::SHIFT, "S "),
+    ] {
+        if modifiers.contains(modifier) {
+            name.push_str(letter);
+        }
+    }
+    name.push_str(&match code {
+        KeyCode::Up => "up".to_string(),
+        KeyCode::Down => "down".to_string(),
+        KeyCode::PageUp => "page_up".to_string(),
+        KeyCode::PageDown => "page_down".to_string(),
+        KeyCode::Left => "left".to_string(),
+        KeyCode::Right => "right".to_string(),
+        KeyCode::Home => "home".to_string(),
+        KeyCode::End => "end".to_string(),
+        KeyCode::Tab => "tab".to_string(),
+        KeyCode::BackTab => "backtab".to_string(),
+        KeyCode::Delete => "delete".to_string(),
+        KeyCode::Insert => "insert".to_string(),
+        KeyCode::Esc => "esc".to_string(),
+        KeyCode::Enter => "enter".to_string(),
+        KeyCode::Backspace => "backspace".to_string(),
+        KeyCode::Char(' ') => "space".to_string(),
+        KeyCode::Char('#') => "hash".to_string(),
+        KeyCode::Char(c) => format!("'{c}'"),
+        KeyCode::F(number) => format!("f{number}"),
+        KeyCode::Null => "null".to_string(),
+    });
+    name
+}
+
+pub struct Keymap {
+    bindings: HashMap<(KeyCode, KeyModifiers), Action>,
+}
+
+impl Keymap {
+    /// Returns the `default`, `vi` or `less` keymap.
+    pub fn preset(name: &str) -> Option<Self> {
+        let extra_bindings = match name {
+            "default" => &[][..],
+            "vi" => VI_BINDINGS,
+            "less" => LESS_BINDINGS,
+            _ => return None,
+        };
+        let mut keymap = Self {
+            bindings: HashMap::new(),
+        };
+        for (key, action) in DEFAULT_BINDINGS.iter().chain(extra_bindings) {
+            keymap
+                .bindings
+                .insert(binding_key(&parse_key(key).unwrap()), *action);
+        }
+        Some(keymap)
+    }
+
+    /// Reads a keymap file, made of lines like `preset less`, `bind C d page_down` or `unbind q`,
+    /// applied in order to the default keymap. Comments start with `#`.
+    pub fn load(path: &str) -> Result<Self> {
+        let mut keymap = Self::preset("default").unwrap();
+        for (line_index, line) in read_to_string(path)?.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let error =
+                |message: &str| Error::other(format!("{path}:{}: {message}", line_index + 1));
+            let (command, arguments) = line.split_once(' ').unwrap_or((line, ""));
+            match command {
+                "" => {}
+                "preset" => {
+                    keymap = Self::preset(arguments).ok_or_else(|| error("Unknown preset"))?;
+                }
+                "bind" => {
+                    let (key, action_name) = arguments
+                        .rsplit_once(' ')
+                        .ok_or_else(|| error("Missing key or action"))?;
+                    let key = parse_key(key).map_err(|message| error(&message))?;
+                    let action =
+                        Action::from_name(action_name).ok_or_else(|| error("Unknown action"))?;
+                    keymap.bindings.insert(binding_key(&key), action);
+                }
+                "unbind" => {
+                    let key = parse_key(arguments).map_err(|message| error(&message))?;
+                    keymap.bindings.remove(&binding_key(&key));
+                }
+                _ => return Err(error("Unknown command")),
+            }
+        }
+        Ok(keymap)
+    }
+
+    pub fn action(&self, event: &KeyEvent) -> Option<Action> {
+        self.bindings.get(&binding_key(event)).copied()
+    }
+}
+
+/// The Shift modifier of characters is ignored, as it is already in the character case.
+fn binding_key(event: &KeyEvent) -> (KeyCode, KeyModifiers) {
+    match event.code {
+        KeyCode::Char(_) => (event.code, event.modifiers - KeyModifiers::SHIFT),
+        _ => (event.code, event.modifiers),
+    }
+}
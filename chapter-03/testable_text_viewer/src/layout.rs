@@ -0,0 +1,107 @@
+use std::borrow::Cow;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// What is shown for a grapheme of a line.
+pub struct Cell {
+    pub text: String,
+    /// Number of terminal columns.
+    pub width: usize,
+    /// Position of the grapheme in the line.
+    pub bytes: Range<usize>,
+}
+
+/// Splits the line into graphemes, expanding the tabs up to the next tab stop,
+/// and replacing the control characters with visible escapes.
+pub fn layout(line: &str, tab_width: usize) -> Vec<Cell> {
+    let mut cells: Vec<Cell> = vec![];
+    let mut column = 0;
+    for (begin, grapheme) in line.grapheme_indices(true) {
+        let bytes = begin..begin + grapheme.len();
+        let (text, width) = grapheme_text(grapheme, column, tab_width);
+        column += width;
+        cells.push(Cell {
+            text: text.into_owned(),
+            width,
+            bytes,
+        });
+    }
+    cells
+}
+
+/// Same as the width of the layout, without building it.
+pub fn display_width(line: &str, tab_width: usize) -> usize {
+    line.graphemes(true).fold(0, |column, grapheme| {
+        column + grapheme_text(grapheme, column, tab_width).1
+    })
+}
+
+/// Returns what is shown for the grapheme starting at the column, and its width.
+fn grapheme_text(grapheme: &str, column: usize, tab_width: usize) -> (Cow<'_, str>, usize) {
+    if grapheme == "\t" {
+        let width = tab_width - column % tab_width;
+        (Cow::Owned(" ".repeat(width)), width)
+    } else if grapheme.chars().any(char::is_control) {
+        let text: String = grapheme.chars().map(escape).collect();
+        let width = text.width();
+        (Cow::Owned(text), width)
+    } else if grapheme.width() == 0 {
+        // A combining mark without base character is shown on a dotted circle.
+        let text = format!("\u{25CC}{grapheme}");
+        let width = text.width();
+        (Cow::Owned(text), width)
+    } else {
+        (Cow::Borrowed(grapheme), grapheme.width())
+    }
+}
+
+/// Control characters are shown in caret notation, like `^M` for a carriage return,
+/// or as code points, like `<85>`.
+fn escape(c: char) -> String {
+    match c as u32 {
+        0..=0x1F => format!("^{}", char::from(c as u8 + b'@')),
+        0x7F => "^?".to_string(),
+        code if c.is_control() => format!("<{code:X}>"),
+        _ => c.to_string(),
+    }
+}
+
+/// Returns the ranges of cells shown in each row when the line is wrapped.
+/// A wide cell not fitting at the end of a row goes to the next one.
+pub fn wrap(cells: &[Cell], width: usize) -> Vec<Range<usize>> {
+    let mut rows = vec![];
+    let mut row_begin = 0;
+    let mut row_width = 0;
+    for (index, cell) in cells.iter().enumerate() {
+        if row_width + cell.width > width && index > row_begin {
+            rows.push(row_begin..index);
+            row_begin = index;
+            row_width = 0;
+        }
+        row_width += cell.width;
+    }
+    rows.push(row_begin..cells.len());
+    rows
+}
+
+/// Returns the column where the cell containing the byte begins.
+pub fn column_of_byte(cells: &[Cell], byte_index: usize) -> usize {
+    cells
+        .iter()
+        .take_while(|cell| cell.bytes.end <= byte_index)
+        .map(|cell| cell.width)
+        .sum()
+}
+
+/// Returns where the cell shown at the column begins, or the end of the line if it is beyond.
+pub fn byte_of_column(cells: &[Cell], column: usize) -> usize {
+    let mut cell_column = 0;
+    for cell in cells {
+        cell_column += cell.width;
+        if column < cell_column {
+            return cell.bytes.start;
+        }
+    }
+    cells.last().map_or(0, |cell| cell.bytes.end)
+}
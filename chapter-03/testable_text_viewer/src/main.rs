@@ -1,135 +1,82 @@
 use crossterm::{
-    cursor::Show,
-    event::{read, Event, KeyCode, KeyEvent, KeyModifiers},
-    style::{Color, SetBackgroundColor, SetForegroundColor},
-    terminal::{
-        disable_raw_mode, enable_raw_mode, is_raw_mode_enabled, Clear, ClearType,
-        EnterAlternateScreen, LeaveAlternateScreen,
-    },
-    QueueableCommand, Result,
+    event::{poll, read},
+    Result,
 };
-use std::io::{stdin, stdout, Error, ErrorKind, Write};
-use std::panic::catch_unwind;
+use std::time::Duration;
+use terminal_guard::{Modes, TerminalGuard};
 
+mod clipboard;
+mod diff;
+mod document;
+mod editor;
+mod filter;
+mod hex;
+mod highlight;
+mod keymap;
+mod layout;
 mod model;
+mod options;
+mod pager;
+mod script;
+mod search;
+mod session;
+mod theme;
 
-fn main() -> Result<()> {
-    let result = catch_unwind(run);
-    cleanup_tty()?;
-    match result {
-        Ok(run_value) => run_value,
-        Err(err) => {
-            if let Some(string) = err.downcast_ref::<String>() {
-                Err(Error::new(ErrorKind::Other, string.as_str()))
-            } else if let Some(string_slice) = err.downcast_ref::<&str>() {
-                Err(Error::new(ErrorKind::Other, *string_slice))
-            } else {
-                Err(Error::new(ErrorKind::Other, format!("{:?}", err)))
-            }
-        }
-    }
-}
+use clap::Parser;
+use model::Input;
+use options::Options;
+use script::Script;
 
-fn cleanup_tty() -> Result<()> {
-    if is_raw_mode_enabled()? {
-        disable_raw_mode()?;
-        stdout()
-            .queue(SetForegroundColor(Color::White))?
-            .queue(SetBackgroundColor(Color::Black))?
-            .queue(Clear(ClearType::All))?
-            .queue(LeaveAlternateScreen)?
-            .queue(Show)?
-            .flush()?;
-    }
-    Ok(())
-}
+/// How often the followed file is checked for changes.
+const FOLLOW_PERIOD: Duration = Duration::from_millis(250);
 
-fn run() -> Result<()> {
+fn main() -> Result<()> {
     let mut model = model::Model::new();
-    model.interpret_arguments()?;
-    model.set_initial_size(&process_next_command)?;
-    enable_raw_mode()?;
-    stdout().queue(EnterAlternateScreen)?;
+    let options = Options::parse();
+    let (replay_path, real_time) = (options.replay.clone(), options.real_time);
+    model.interpret_options(options)?;
+    if model.is_streaming() {
+        return model.stream();
+    }
+    let mut script = replay_path
+        .map(|path| Script::open(&path, real_time))
+        .transpose()?;
+    model.set_initial_size(script.as_mut())?;
+    // The mouse events are captured only from a terminal, as the scripts contain them already.
+    // The cursor is hidden by every refresh.
+    let _guard = TerminalGuard::enter(Modes {
+        raw: true,
+        alternate_screen: true,
+        mouse_capture: model.is_interactive(),
+        ..Modes::NONE
+    })?;
     loop {
         model.refresh()?;
-        let event = if model::is_input_tty() {
-            read()
-        } else {
-            process_next_command()
+        let input = match &mut script {
+            Some(script) => script.next_input(&model),
+            None => next_terminal_input(&model),
         }?;
-        match model.interpret_event(event)? {
+        match model.interpret_input(input)? {
             model::EventOutcome::Terminate => {
-                if !model::is_input_tty() {
-                    check_no_more_commands()?;
+                if let Some(script) = &mut script {
+                    script.check_end()?;
                 }
                 break;
             }
             model::EventOutcome::Continue => {}
         }
     }
-    Ok(())
+    model.save_session()
 }
 
-fn process_next_command() -> Result<Event> {
-    let next_command_line = get_next_command_line()?;
-    let mut key_code = KeyCode::Null;
-    let mut ctrl = false;
-    let mut new_cols = 0;
-    let mut new_rows = 0;
-    for word in next_command_line.split(' ') {
-        match word {
-            "up" => key_code = KeyCode::Up,
-            "down" => key_code = KeyCode::Down,
-            "page_up" => key_code = KeyCode::PageUp,
-            "page_down" => key_code = KeyCode::PageDown,
-            "esc" => key_code = KeyCode::Esc,
-            "C" => ctrl = true,
-            "q" => key_code = KeyCode::Char('q'),
-            _ => {
-                if let Some(cols_str) = word.strip_prefix("cols") {
-                    new_cols = cols_str.parse().unwrap();
-                } else if let Some(rows_str) = word.strip_prefix("rows") {
-                    new_rows = rows_str.parse().unwrap();
-                } else {
-                    panic!("Unexpected command: {word}");
-                }
+/// Waits for a terminal event, checking periodically for file changes in follow mode.
+fn next_terminal_input(model: &model::Model) -> Result<Input> {
+    if model.is_following() {
+        while !poll(FOLLOW_PERIOD)? {
+            if model.file_has_changed()? {
+                return Ok(Input::FileChange);
             }
         }
     }
-    Ok(if new_cols > 0 && new_rows > 0 {
-        Event::Resize(new_cols, new_rows)
-    } else {
-        let mut modifiers = KeyModifiers::empty();
-        if ctrl {
-            modifiers |= KeyModifiers::CONTROL;
-        }
-        Event::Key(KeyEvent::new(key_code, modifiers))
-    })
-}
-
-fn get_next_command_line() -> Result<String> {
-    let mut line = String::new();
-    while line.is_empty() {
-        stdin().read_line(&mut line)?;
-        if line.is_empty() {
-            return Err(Error::new(ErrorKind::Other, "Unexpected end of input file"));
-        }
-        if let Some(comment_index) = line.find('#') {
-            line = line[0..comment_index].to_string();
-        }
-        line = line.trim_end().to_string();
-    }
-    Ok(line)
-}
-
-fn check_no_more_commands() -> Result<()> {
-    let mut owned_line = String::new();
-    stdin().read_line(&mut owned_line)?;
-    if !owned_line.is_empty() {
-        return Err(Error::new(
-            ErrorKind::Other,
-            "Input file continuing after exit command",
-        ));
-    }
-    Ok(())
+    Ok(Input::Event(read()?))
 }
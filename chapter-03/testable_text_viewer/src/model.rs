@@ -1,87 +1,506 @@
 use crossterm::{
     cursor::{Hide, MoveTo},
-    event::{Event, KeyCode, KeyModifiers},
-    style::{Color, Print, SetBackgroundColor, SetForegroundColor},
+    event::{Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind},
+    style::{Print, SetBackgroundColor, SetForegroundColor},
     terminal::size,
     tty::IsTty,
     QueueableCommand, Result,
 };
+use std::borrow::Cow;
 use std::cmp::{max, min};
-use std::env::args;
-use std::fs::File;
-use std::io::{stdin, stdout, Error, ErrorKind, Read, Write};
+use std::io::{stdout, Error, Stdout, Write};
+use std::iter::once;
+use std::mem::replace;
+use std::ops::Range;
+
+use crate::clipboard::copy_sequence;
+use crate::diff::{align, DiffRow};
+use crate::document::{Change, Document};
+use crate::editor::{History, Position, Step};
+use crate::filter::Filter;
+use crate::hex;
+use crate::highlight::{Highlighter, Language, TokenKind};
+use crate::keymap::{key_name, Action, Keymap};
+use crate::layout::{byte_of_column, column_of_byte, display_width, layout, wrap, Cell};
+use crate::options::Options;
+use crate::pager::{ignore_broken_pipe, stream};
+use crate::script::{Recorder, Script};
+use crate::search::{Direction, Search};
+use crate::session::{Entry, Places, StateFile};
+use crate::theme::{Style, Theme};
 
 pub enum EventOutcome {
     Continue,
     Terminate,
 }
 
+/// Number of lines scrolled by a step of the mouse wheel.
+const WHEEL_LINES: usize = 3;
+
+/// What the model reacts to.
+#[derive(Clone)]
+pub enum Input {
+    Event(Event),
+    /// An action named in an event script, whatever the key bound to it.
+    Action(Action),
+    /// The followed file may have grown, or have been truncated or replaced.
+    FileChange,
+}
+
+/// A document opened by the viewer, with the state of its view,
+/// kept aside while the document is not in the focused pane.
+struct Buffer {
+    document: Document,
+    highlighter: Highlighter,
+    first_line_index: usize,
+    first_column_index: usize,
+    current_match: Option<(usize, Range<usize>)>,
+    selection: Option<(Position, Position)>,
+    cursor: Position,
+    history: History,
+    places: Places,
+    filter: Filter,
+}
+
+/// Two documents shown side by side, or one above the other.
+struct Split {
+    vertical: bool,
+    /// Index of the buffer shown in the pane not focused.
+    other_buffer_index: usize,
+    /// Whether the focused pane is the left or the top one.
+    focus_first: bool,
+    diff: Option<Diff>,
+}
+
+/// The lines of the documents of the first and of the second pane, aligned on rows.
+struct Diff {
+    rows: Vec<DiffRow>,
+    /// Index of the row shown at the top of both panes.
+    first_row: usize,
+}
+
+/// What a row of a pane shows, besides the rest of a wrapped line.
+enum PaneLine {
+    Line(usize),
+    /// A line removed or added, or modified, in a diff.
+    ChangedLine(usize),
+    /// A filler facing a line of the other pane, in a diff.
+    Filler,
+}
+
 pub struct Model {
-    contents: String,
-    lines: Vec<usize>,
+    /// The document in the focused pane, whose view is described by the following fields.
+    document: Document,
     first_line_index: usize,
-    save_events_stream: Option<File>,
+    first_column_index: usize,
+    wrap: bool,
+    show_line_numbers: bool,
+    tab_width: usize,
+    keymap: Keymap,
+    highlighter: Highlighter,
+    theme: Theme,
+    /// Whether a status bar describes the focused document, above a row for the messages.
+    status_bar: bool,
+    /// Whether the documents are written to the output, instead of being paged.
+    streaming: bool,
+    following: bool,
+    search: Option<Search>,
+    prompt: Option<Search>,
+    /// Position to restore if the prompt is cancelled, and from which the typed pattern is searched.
+    prompt_origin: (usize, usize, Option<(usize, Range<usize>)>),
+    /// Text typed after `:`, while the command line is shown.
+    command_line: Option<String>,
+    /// Information shown on the last row until the next key.
+    message: Option<String>,
+    /// Same as the message, for what failed.
+    error: Option<String>,
+    /// Line index and byte range of the last match found.
+    current_match: Option<(usize, Range<usize>)>,
+    /// Positions of the cells where the selection started and where it ends, both included.
+    selection: Option<(Position, Position)>,
+    /// Whether the selection is being dragged.
+    selecting: bool,
+    /// Whether the keys edit the text at the cursor.
+    editing: bool,
+    /// Position of the cell where the typed text is inserted, or of the end of the line.
+    cursor: Position,
+    history: History,
+    places: Places,
+    filter: Filter,
+    /// The bookmark action waiting for the digit of the bookmark.
+    pending_bookmark: Option<Action>,
+    /// Where the positions and the bookmarks are kept between sessions, if anywhere.
+    state_file: Option<StateFile>,
+    /// Whether the user is asked to save the modified documents before quitting.
+    confirming_quit: bool,
+    /// The documents opened, in the order of the arguments; the slot of the focused one is empty.
+    buffers: Vec<Option<Buffer>>,
+    buffer_index: usize,
+    split: Option<Split>,
+    /// Whether the panes of a split scroll together.
+    scroll_bind: bool,
+    save_events_stream: Option<Recorder>,
+    /// Whether the events come from a script instead of the terminal.
+    replaying: bool,
+    /// Text of the rows shown by the last refresh, checked by the event scripts.
+    screen: Vec<String>,
+    /// Size of the terminal.
+    screen_size: (u16, u16),
+    /// Position and size of the focused pane.
+    origin: (u16, u16),
     n_cols: u16,
     n_rows: u16,
-    n_digits: usize,
 }
 
 impl Model {
     pub fn new() -> Self {
         Self {
-            contents: String::new(),
-            lines: vec![],
+            document: Document::new(),
             first_line_index: 0,
+            first_column_index: 0,
+            wrap: false,
+            show_line_numbers: true,
+            tab_width: 8,
+            keymap: Keymap::preset("default").unwrap(),
+            highlighter: Highlighter::new(Language::Plain),
+            theme: Theme::preset("default").unwrap(),
+            status_bar: false,
+            streaming: false,
+            following: false,
+            search: None,
+            prompt: None,
+            prompt_origin: (0, 0, None),
+            command_line: None,
+            message: None,
+            error: None,
+            current_match: None,
+            selection: None,
+            selecting: false,
+            editing: false,
+            cursor: (0, 0),
+            history: History::default(),
+            places: Places::default(),
+            filter: Filter::default(),
+            pending_bookmark: None,
+            state_file: None,
+            confirming_quit: false,
+            buffers: vec![],
+            buffer_index: 0,
+            split: None,
+            scroll_bind: true,
             save_events_stream: None,
+            replaying: false,
+            screen: vec![],
+            screen_size: (0, 0),
+            origin: (0, 0),
             n_cols: 0,
             n_rows: 0,
-            n_digits: 0,
         }
     }
 
-    pub fn interpret_arguments(&mut self) -> Result<()> {
-        let filepath = args()
-            .nth(1)
-            .ok_or_else(|| Error::new(ErrorKind::Other, "Missing first command-line argument"))?;
-        let mut file = File::open(&filepath)?;
-        file.read_to_string(&mut self.contents)?;
-        self.lines = self
-            .contents
-            .match_indices('\n')
-            .map(|(index, _)| index)
-            .collect();
-        self.n_digits = format!("{}", self.lines.len()).len();
-        if let Some(arg2) = args().nth(2) {
-            if &arg2 == "--save-events" {
-                self.save_events_stream =
-                    Some(File::create(args().nth(3).ok_or_else(|| {
-                        Error::new(ErrorKind::Other, "Missing save-file argument")
-                    })?)?);
+    /// Applies the options, and opens the documents.
+    pub fn interpret_options(&mut self, options: Options) -> Result<()> {
+        self.tab_width = options.tab_width as usize;
+        self.wrap = options.wrap;
+        self.status_bar = options.status;
+        self.following = options.follow;
+        self.replaying = options.replay.is_some();
+        if options.replay.as_deref() == Some("-") && options.paths.iter().any(|path| path == "-") {
+            return Err(Error::other(
+                "The standard input cannot hold both a document and the script",
+            ));
+        }
+        if let Some(path) = &options.save_events {
+            self.save_events_stream = Some(Recorder::create(path, !self.replaying)?);
+        }
+        if let Some(path) = &options.keymap {
+            self.keymap = Keymap::load(path)?;
+        }
+        if let Some(path) = options.state {
+            self.state_file = Some(StateFile::load(path.into())?);
+        }
+        if let Some(theme) = &options.theme {
+            self.theme = match Theme::preset(theme) {
+                Some(theme) => theme,
+                None => Theme::load(theme)?,
+            };
+        }
+        // Syntax highlighting and colors are disabled when the output is not a terminal,
+        // unless forced.
+        let highlighting = options.highlight.forced().unwrap_or(stdout().is_tty());
+        // The documents are paged when the output is a terminal, and streamed otherwise,
+        // with line numbers only when paged, unless forced.
+        let line_numbers = options.line_numbers.forced();
+        // The hex view is shown for the binary documents only, unless forced.
+        let hex = options.hex.forced();
+        for path in &options.paths {
+            let mut document = Document::open(path, self.tab_width)?;
+            // The rows are fitted to the width of the pane once it is known.
+            if hex.unwrap_or_else(|| document.is_binary()) {
+                document.set_bytes_per_row(Some(hex::DEFAULT_BYTES_PER_ROW));
+            }
+            let language = if highlighting && !document.is_hex() {
+                Language::from_path(path)
+            } else {
+                Language::Plain
+            };
+            self.buffers.push(Some(Buffer {
+                document,
+                highlighter: Highlighter::new(language),
+                first_line_index: 0,
+                first_column_index: 0,
+                current_match: None,
+                selection: None,
+                cursor: (0, 0),
+                history: History::default(),
+                places: Places::default(),
+                filter: Filter::default(),
+            }));
+        }
+        // The scripts replay the same way whatever the previous sessions,
+        // unless they name a state file.
+        if self.state_file.is_none() && self.is_interactive() {
+            if let Some(path) = StateFile::default_path() {
+                self.state_file = Some(StateFile::load(path)?);
+            }
+        }
+        if let Some(state_file) = &self.state_file {
+            for buffer in self.buffers.iter_mut().flatten() {
+                if let Some(entry) = state_file.entry(buffer.document.path()) {
+                    buffer.first_line_index = entry.first_line_index;
+                    buffer.first_column_index = entry.first_column_index;
+                    buffer.places.bookmarks = entry.bookmarks.clone();
+                }
             }
         }
+        let first_buffer = self.buffers[0].take().unwrap();
+        self.replace_buffer(first_buffer);
+        if let Some(line) = options.line {
+            self.first_line_index = line as usize - 1;
+        }
+        // When the events come from a script, the output is always paged, for the tests.
+        self.streaming = !options
+            .paging
+            .forced()
+            .unwrap_or(stdout().is_tty() || !self.is_interactive());
+        if !self.streaming && options.quit_if_one_screen && self.is_interactive() {
+            let (cols, rows) = size()?;
+            self.streaming = self.fits_on_screen(cols, rows, line_numbers.unwrap_or(false));
+        }
+        self.show_line_numbers = line_numbers.unwrap_or(!self.streaming);
+        // Without colors, the monochrome theme replaces the chosen one.
+        // The replayed scripts keep them, as their screens are checked with their colors,
+        // and so does the stream, whose colors depend on the highlighting only.
+        if !options
+            .color
+            .forced()
+            .unwrap_or(stdout().is_tty() || !self.is_interactive() || self.streaming)
+        {
+            self.theme = Theme::preset("monochrome").unwrap();
+        }
         Ok(())
     }
 
-    pub fn set_initial_size(
-        &mut self,
-        command_processor: &dyn Fn() -> Result<Event>,
-    ) -> Result<()> {
-        if is_input_tty() {
-            (self.n_cols, self.n_rows) = size()?;
+    pub fn is_streaming(&self) -> bool {
+        self.streaming
+    }
+
+    /// Tells whether the only document, with its line numbers if any, fits in the screen size.
+    fn fits_on_screen(&self, cols: u16, rows: u16, line_numbers: bool) -> bool {
+        // Once it is known that the lines fit, they are all indexed.
+        if self.buffers.len() > 1 || self.document.has_line(rows as usize) {
+            return false;
+        }
+        let gutter_width = if line_numbers { self.n_digits() + 1 } else { 0 };
+        gutter_width + self.document.max_line_width() <= cols as usize
+    }
+
+    /// Writes the documents one after the other to the output, instead of paging them.
+    pub fn stream(&self) -> Result<()> {
+        let mut out = stdout().lock();
+        let buffers = self
+            .buffers
+            .iter()
+            .flatten()
+            .map(|buffer| (&buffer.document, &buffer.highlighter));
+        ignore_broken_pipe(
+            once((&self.document, &self.highlighter))
+                .chain(buffers)
+                .try_for_each(|(document, highlighter)| {
+                    stream(
+                        &mut out,
+                        document,
+                        highlighter,
+                        &self.theme.tokens,
+                        self.show_line_numbers,
+                    )
+                }),
+        )
+    }
+
+    /// Writes the positions and the bookmarks of the documents to the state file, if any.
+    pub fn save_session(&mut self) -> Result<()> {
+        let Some(state_file) = &mut self.state_file else {
+            return Ok(());
+        };
+        let focused = (
+            &self.document,
+            self.first_line_index,
+            self.first_column_index,
+            &self.places,
+        );
+        let others = self.buffers.iter().flatten().map(|buffer| {
+            (
+                &buffer.document,
+                buffer.first_line_index,
+                buffer.first_column_index,
+                &buffer.places,
+            )
+        });
+        for (document, first_line_index, first_column_index, places) in once(focused).chain(others)
+        {
+            state_file.set_entry(
+                document.path(),
+                Entry {
+                    first_line_index,
+                    first_column_index,
+                    bookmarks: places.bookmarks.clone(),
+                },
+            );
+        }
+        state_file.save()
+    }
+
+    /// Makes the buffer the focused one, returning the previous one.
+    fn replace_buffer(&mut self, buffer: Buffer) -> Buffer {
+        Buffer {
+            document: replace(&mut self.document, buffer.document),
+            highlighter: replace(&mut self.highlighter, buffer.highlighter),
+            first_line_index: replace(&mut self.first_line_index, buffer.first_line_index),
+            first_column_index: replace(&mut self.first_column_index, buffer.first_column_index),
+            current_match: replace(&mut self.current_match, buffer.current_match),
+            selection: replace(&mut self.selection, buffer.selection),
+            cursor: replace(&mut self.cursor, buffer.cursor),
+            history: replace(&mut self.history, buffer.history),
+            places: replace(&mut self.places, buffer.places),
+            filter: replace(&mut self.filter, buffer.filter),
+        }
+    }
+
+    /// Puts the focused buffer back in its slot, and focuses another one.
+    fn swap_buffer(&mut self, buffer_index: usize) {
+        let buffer = self.buffers[buffer_index].take().unwrap();
+        let previous_buffer = self.replace_buffer(buffer);
+        self.buffers[self.buffer_index] = Some(previous_buffer);
+        self.buffer_index = buffer_index;
+        self.fit_hex_rows();
+    }
+
+    /// Focuses the other pane of the split, if any.
+    fn toggle_focus(&mut self) {
+        if let Some(split) = &mut self.split {
+            split.focus_first = !split.focus_first;
+            let other_buffer_index = replace(&mut split.other_buffer_index, self.buffer_index);
+            self.swap_buffer(other_buffer_index);
+            self.layout_panes();
+        }
+    }
+
+    fn set_screen_size(&mut self, cols: u16, rows: u16) {
+        self.screen_size = (cols, rows);
+        self.layout_panes();
+    }
+
+    /// Sets the position and the size of the focused pane. The panes of a split
+    /// share the screen, but for the column or the row separating them.
+    fn layout_panes(&mut self) {
+        let (cols, rows) = (self.screen_size.0, self.panes_rows());
+        (self.origin, (self.n_cols, self.n_rows)) = match &self.split {
+            None => ((0, 0), (cols, rows)),
+            Some(split) if split.vertical => {
+                let first_cols = first_pane_length(cols);
+                if split.focus_first {
+                    ((0, 0), (first_cols, rows))
+                } else {
+                    (
+                        (first_cols + 1, 0),
+                        (cols.saturating_sub(first_cols + 1), rows),
+                    )
+                }
+            }
+            Some(split) => {
+                let first_rows = first_pane_length(rows);
+                if split.focus_first {
+                    ((0, 0), (cols, first_rows))
+                } else {
+                    (
+                        (0, first_rows + 1),
+                        (cols, rows.saturating_sub(first_rows + 1)),
+                    )
+                }
+            }
+        };
+        self.fit_hex_rows();
+    }
+
+    /// Shows as many bytes per row of the hex view as the pane is wide enough for,
+    /// keeping the offset of the first row shown.
+    fn fit_hex_rows(&mut self) {
+        let Some(old_bytes_per_row) = self.document.bytes_per_row() else {
+            return;
+        };
+        let bytes_per_row = hex::bytes_per_row(self.text_width());
+        if bytes_per_row == old_bytes_per_row {
+            return;
+        }
+        self.document.set_bytes_per_row(Some(bytes_per_row));
+        self.highlighter.forget_from(0);
+        self.filter.forget_from(0);
+        self.selection = None;
+        self.current_match = None;
+        self.first_line_index =
+            self.clamp_first_line_index(self.first_line_index * old_bytes_per_row / bytes_per_row);
+        self.first_column_index = min(self.first_column_index, self.last_first_column_index());
+    }
+
+    /// Number of rows shared by the panes, above the status bar and the message row if shown.
+    fn panes_rows(&self) -> u16 {
+        if self.status_bar {
+            self.screen_size.1.saturating_sub(2)
         } else {
-            let event = command_processor()?;
-            self.interpret_event(event)?;
+            self.screen_size.1
         }
-        if self.save_events_stream.is_some() {
-            write_resize_command(&self.save_events_stream, self.n_cols, self.n_rows)?;
+    }
+
+    /// Reads the size from the first command of the script if there is one,
+    /// or else from the terminal.
+    pub fn set_initial_size(&mut self, script: Option<&mut Script>) -> Result<()> {
+        match script {
+            Some(script) => {
+                let input = script.next_input(self)?;
+                self.interpret_input(input)?;
+            }
+            None => {
+                let (cols, rows) = size()?;
+                self.set_screen_size(cols, rows);
+            }
+        }
+        // The position restored from the state file may be past the end of a shortened file.
+        self.first_line_index = if self.following {
+            self.clamp_first_line_index(usize::MAX)
+        } else {
+            self.clamp_first_line_index(self.first_line_index)
+        };
+        self.first_column_index = min(self.first_column_index, self.last_first_column_index());
+        // The size read from a script is recorded with the other events.
+        if self.is_interactive() {
+            let (cols, rows) = self.screen_size;
+            write_resize_command(&self.save_events_stream, cols, rows)?;
         }
         if self.n_rows == 0 {
-            Err(Error::new(ErrorKind::Other, "The terminal has no rows"))
-        } else if (self.n_cols as usize) < self.n_digits {
-            Err(Error::new(
-                ErrorKind::Other,
-                "The terminal has not enough columns",
-            ))
+            Err(Error::other("The terminal has no rows"))
+        } else if (self.n_cols as usize) < self.n_digits() {
+            Err(Error::other("The terminal has not enough columns"))
         } else {
             Ok(())
         }
@@ -89,127 +508,1898 @@ impl Model {
 
     pub fn refresh(&mut self) -> Result<()> {
         let mut out = stdout();
-        for row in 0..self.n_rows {
-            let current_line_index = self.first_line_index + row as usize;
-            out.queue(MoveTo(0, row))?
-                .queue(SetForegroundColor(Color::Cyan))?
-                .queue(SetBackgroundColor(Color::DarkBlue))?;
-            if current_line_index >= self.lines.len() {
-                let width = self.n_cols as usize;
-                out.queue(Print(format!("{:width$}", "")))?;
+        let screen_cols = self.screen_size.0;
+        let screen_rows = self.panes_rows();
+        let mut screen = self.render_pane(&mut out, true)?;
+        if let Some((vertical, focus_first)) = self
+            .split
+            .as_ref()
+            .map(|split| (split.vertical, split.focus_first))
+        {
+            self.toggle_focus();
+            let other_pane = self.render_pane(&mut out, false);
+            self.toggle_focus();
+            let (first_pane, second_pane) = if focus_first {
+                (screen, other_pane?)
             } else {
-                let n_digits = self.n_digits;
-                out.queue(Print(format!("{:>n_digits$} ", current_line_index + 1)))?
-                    .queue(SetForegroundColor(Color::Black))?
-                    .queue(SetBackgroundColor(Color::Grey))?;
-                let text_width = self.n_cols as usize - self.n_digits - 1;
-                let line_begin = if current_line_index == 0 {
-                    0
-                } else {
-                    self.lines[current_line_index - 1] + 1
-                };
-                let line_end = self.lines[current_line_index];
-                out.queue(Print(format!(
-                    "{:text_width$}",
-                    self.contents[line_begin..line_end]
-                        .chars()
-                        .take(text_width)
-                        .collect::<String>(),
-                )))?;
+                (other_pane?, screen)
+            };
+            set_style(&mut out, self.theme.separator)?;
+            screen = if vertical {
+                let separator_column = first_pane_length(screen_cols);
+                for row in 0..screen_rows {
+                    out.queue(MoveTo(separator_column, row))?
+                        .queue(Print('│'))?;
+                }
+                first_pane
+                    .into_iter()
+                    .zip(second_pane)
+                    .map(|(first_row, second_row)| format!("{first_row}│{second_row}"))
+                    .collect()
+            } else {
+                let separator = "─".repeat(screen_cols as usize);
+                out.queue(MoveTo(0, first_pane_length(screen_rows)))?
+                    .queue(Print(&separator))?;
+                first_pane
+                    .into_iter()
+                    .chain(once(separator))
+                    .chain(second_pane)
+                    .collect()
+            };
+        }
+        // The question before quitting, the search prompt, the command line, an error, a message,
+        // or the edit mode is shown on the last row.
+        let status = if let Some(action) = self.pending_bookmark {
+            Some(if action == Action::SetBookmark {
+                "Bookmark to set (0-9)".to_string()
+            } else {
+                "Bookmark to go to (0-9)".to_string()
+            })
+        } else if self.confirming_quit {
+            Some(format!(
+                "Save the changes to {}? (y/n, Esc to cancel)",
+                self.modified_paths().join(", ")
+            ))
+        } else {
+            match (&self.prompt, &self.command_line) {
+                (Some(prompt), _) => Some(prompt.prompt()),
+                (None, Some(command_line)) => Some(format!(":{command_line}")),
+                (None, None) if self.error.is_some() => self.error.clone(),
+                (None, None) if self.message.is_some() => self.message.clone(),
+                (None, None) => self.editing.then(|| "-- EDIT --".to_string()),
             }
+        };
+        let status_style = if self.error.is_some() && status == self.error {
+            self.theme.error
+        } else {
+            self.theme.message
+        };
+        let width = screen_cols as usize;
+        let fit = |text: &str| format!("{:width$}", text.chars().take(width).collect::<String>());
+        // Without the status bar, the last row of the panes is covered when there is a status.
+        if self.status_bar {
+            let rows = [
+                (fit(&self.status_bar_text()), self.theme.status),
+                (fit(status.as_deref().unwrap_or("")), status_style),
+            ];
+            for (row, (text, style)) in rows.into_iter().enumerate() {
+                out.queue(MoveTo(0, screen_rows + row as u16))?;
+                set_style(&mut out, style)?.queue(Print(&text))?;
+                screen.push(text);
+            }
+        } else if let (Some(status), Some(last_row)) = (status, screen.last_mut()) {
+            *last_row = fit(&status);
+            out.queue(MoveTo(0, screen_rows - 1))?;
+            set_style(&mut out, status_style)?.queue(Print(&last_row))?;
         }
         out.queue(Hide)?.flush()?;
+        self.screen = screen;
         Ok(())
     }
 
-    pub fn interpret_event(&mut self, event: Event) -> Result<EventOutcome> {
+    /// Returns the name of the focused document, whether it is modified, the line at the top,
+    /// or that of the cursor in edit mode, the percentage of the text up to the last line shown,
+    /// the search, and the encoding of the text.
+    fn status_bar_text(&self) -> String {
+        let name = if self.document.is_from_stdin() {
+            "(standard input)"
+        } else {
+            self.document.path()
+        };
+        let modified = if self.document.is_modified() {
+            " [+]"
+        } else {
+            ""
+        };
+        let left = format!(" {name}{modified}");
+        let line_index = if self.editing {
+            self.cursor.0
+        } else {
+            self.first_line_index
+        };
+        // Counting the lines of a huge file would index all of it.
+        let n_lines = if self.document.is_fully_indexed() {
+            self.document.indexed_line_count().to_string()
+        } else {
+            "?".to_string()
+        };
+        let line_ending = if self.document.line_ending() == "\r\n" {
+            "CRLF"
+        } else {
+            "LF"
+        };
+        let mut parts = vec![];
+        if self.filter.is_active() {
+            parts.push("filtered".to_string());
+        }
+        if let Some(search) = &self.search {
+            parts.push(search.prompt());
+        }
+        parts.push(format!("line {} of {n_lines}", line_index + 1));
+        parts.push(format!(
+            "{}%",
+            self.document.percent_at(self.last_shown_line_index())
+        ));
+        if self.document.is_hex() {
+            parts.push("hex".to_string());
+        } else {
+            parts.push(format!("UTF-8 {line_ending}"));
+        }
+        let right = format!("{} ", parts.join("  "));
+        let padding = (self.screen_size.0 as usize)
+            .saturating_sub(display_width(&left, 1) + display_width(&right, 1));
+        format!("{left}{:padding$}{right}", "")
+    }
+
+    fn last_shown_line_index(&self) -> usize {
+        let mut line_index = self.first_line_index;
+        let mut n_rows = self.line_height(line_index);
+        while n_rows < self.n_rows as usize {
+            let Some(next_line_index) = self.next_shown_line(line_index + 1) else {
+                break;
+            };
+            line_index = next_line_index;
+            n_rows += self.line_height(line_index);
+        }
+        line_index
+    }
+
+    /// Returns what the rows of the focused pane show, from the top,
+    /// as many as the pane has rows at most.
+    fn pane_lines(&self) -> Vec<PaneLine> {
+        let n_rows = self.n_rows as usize;
+        match &self.split {
+            Some(Split {
+                diff: Some(diff),
+                focus_first,
+                ..
+            }) => {
+                let side = usize::from(!focus_first);
+                diff.rows
+                    .iter()
+                    .skip(diff.first_row)
+                    .take(n_rows)
+                    .map(|row| match row.lines[side] {
+                        Some(line_index) if row.changed => PaneLine::ChangedLine(line_index),
+                        Some(line_index) => PaneLine::Line(line_index),
+                        None => PaneLine::Filler,
+                    })
+                    .collect()
+            }
+            _ => {
+                let mut pane_lines = vec![];
+                let mut line_index = self.first_line_index;
+                while pane_lines.len() < n_rows {
+                    let Some(shown_line_index) = self.next_shown_line(line_index) else {
+                        break;
+                    };
+                    pane_lines.push(PaneLine::Line(shown_line_index));
+                    line_index = shown_line_index + 1;
+                }
+                pane_lines
+            }
+        }
+    }
+
+    /// Draws the focused pane, returning the text of its rows;
+    /// the cursor is shown only in the pane which has the focus for the user.
+    fn render_pane(&self, out: &mut Stdout, has_focus: bool) -> Result<Vec<String>> {
+        let (origin_column, origin_row) = self.origin;
+        let gutter_width = self.gutter_width();
+        let text_width = self.text_width();
+        // In a diff, the lines of the first pane are the old ones, and those of the second the new ones.
+        let change_style = match &self.split {
+            Some(split) if split.focus_first => self.theme.removed,
+            _ => self.theme.added,
+        };
+        let mut pane_lines = self.pane_lines().into_iter();
+        let mut screen = vec![String::new(); self.n_rows as usize];
+        let mut row = 0;
+        while row < self.n_rows {
+            let (current_line_index, changed) = match pane_lines.next() {
+                Some(PaneLine::Line(line_index)) => (line_index, false),
+                Some(PaneLine::ChangedLine(line_index)) => (line_index, true),
+                Some(PaneLine::Filler) => {
+                    screen[row as usize] =
+                        format!("{:gutter_width$}{}", "", "-".repeat(text_width));
+                    out.queue(MoveTo(origin_column, origin_row + row))?;
+                    set_style(out, self.theme.gutter)?
+                        .queue(Print(&screen[row as usize][..gutter_width]))?;
+                    set_style(out, self.theme.filler)?
+                        .queue(Print(&screen[row as usize][gutter_width..]))?;
+                    row += 1;
+                    continue;
+                }
+                None => {
+                    let width = self.n_cols as usize;
+                    screen[row as usize] = format!("{:width$}", "");
+                    out.queue(MoveTo(origin_column, origin_row + row))?;
+                    set_style(out, self.theme.gutter)?.queue(Print(&screen[row as usize]))?;
+                    row += 1;
+                    continue;
+                }
+            };
+            let cells = self.line_cells(current_line_index);
+            let highlights = self.highlights(&self.line_text(current_line_index));
+            let selected = self.selected_range(current_line_index);
+            let tokens = self.highlighter.tokens(&self.document, current_line_index);
+            let cursor = (has_focus && self.editing && self.cursor.0 == current_line_index)
+                .then_some(self.cursor.1);
+            // The cursor comes first, then the selection, the matches,
+            // the changed lines of a diff, and the rest is colored by token kind.
+            let base_style = if changed {
+                change_style
+            } else {
+                self.theme.text
+            };
+            let style = |cell: &Cell| {
+                if cursor == Some(cell.bytes.start) {
+                    self.theme.cursor
+                } else if selected
+                    .as_ref()
+                    .is_some_and(|s| s.start < cell.bytes.end && cell.bytes.start < s.end)
+                {
+                    self.theme.selection
+                } else if highlights
+                    .iter()
+                    .any(|h| h.start < cell.bytes.end && cell.bytes.start < h.end)
+                {
+                    self.theme.matched
+                } else if changed {
+                    base_style
+                } else {
+                    let kind = tokens
+                        .iter()
+                        .find(|(range, _)| range.contains(&cell.bytes.start))
+                        .map_or(TokenKind::Text, |(_, kind)| *kind);
+                    (self.theme.tokens.color(kind), self.theme.text.1)
+                }
+            };
+            // A row is made of pieces, each one being the text of a cell with its width,
+            // or spaces replacing the part of a wide cell cut by the edge of the screen.
+            // In wrap mode, a line spans several rows,
+            // and only the first one shows the line number.
+            let mut rows: Vec<Vec<(Style, String, usize)>> = if self.wrap {
+                wrap(&cells, max(1, text_width))
+                    .into_iter()
+                    .map(|range| {
+                        cells[range]
+                            .iter()
+                            .map(|cell| (style(cell), cell.text.clone(), cell.width))
+                            .collect()
+                    })
+                    .collect()
+            } else {
+                let first_column = self.first_column_index;
+                let last_column = first_column + text_width;
+                let mut pieces = vec![];
+                let mut column = 0;
+                for cell in &cells {
+                    let (begin, end) = (column, column + cell.width);
+                    column = end;
+                    if end <= first_column || begin >= last_column {
+                        continue;
+                    }
+                    if first_column <= begin && end <= last_column {
+                        pieces.push((style(cell), cell.text.clone(), cell.width));
+                    } else {
+                        let width = min(end, last_column) - max(begin, first_column);
+                        pieces.push((style(cell), " ".repeat(width), width));
+                    }
+                }
+                vec![pieces]
+            };
+            // The cursor at the end of the line is shown on a space.
+            if cursor == Some(self.line_text(current_line_index).len()) {
+                let line_width: usize = cells.iter().map(|cell| cell.width).sum();
+                let piece = (self.theme.cursor, " ".to_string(), 1);
+                let last_row = rows.last_mut().unwrap();
+                if !self.wrap {
+                    let first_column = self.first_column_index;
+                    if (first_column..first_column + text_width).contains(&line_width) {
+                        last_row.push(piece);
+                    }
+                } else if last_row.iter().map(|(_, _, width)| width).sum::<usize>() < text_width {
+                    last_row.push(piece);
+                } else {
+                    rows.push(vec![piece]);
+                }
+            }
+            for (segment_index, pieces) in rows.into_iter().enumerate() {
+                if row >= self.n_rows {
+                    break;
+                }
+                out.queue(MoveTo(origin_column, origin_row + row))?;
+                set_style(out, self.theme.gutter)?;
+                let gutter = if segment_index == 0 && gutter_width > 0 {
+                    let n_digits = gutter_width - 1;
+                    format!("{:>n_digits$} ", current_line_index + 1)
+                } else {
+                    format!("{:gutter_width$}", "")
+                };
+                out.queue(Print(&gutter))?;
+                screen[row as usize] = gutter;
+                // The row is printed as runs of text of the same style,
+                // the last one, on the background of the line, being padded up to the text width.
+                let mut runs: Vec<(Style, String)> = vec![];
+                let mut row_width = 0;
+                for (piece_style, text, width) in pieces {
+                    row_width += width;
+                    match runs.last_mut() {
+                        Some((run_style, run_text)) if *run_style == piece_style => {
+                            run_text.push_str(&text)
+                        }
+                        _ => runs.push((piece_style, text)),
+                    }
+                }
+                if runs
+                    .last()
+                    .is_none_or(|((_, background), _)| *background != base_style.1)
+                {
+                    runs.push((base_style, String::new()));
+                }
+                if let Some((_, text)) = runs.last_mut() {
+                    text.push_str(&" ".repeat(text_width.saturating_sub(row_width)));
+                }
+                for (style, text) in runs {
+                    set_style(out, style)?.queue(Print(&text))?;
+                    screen[row as usize].push_str(&text);
+                }
+                row += 1;
+            }
+        }
+        Ok(screen)
+    }
+
+    pub fn screen_row(&self, row: usize) -> Option<&str> {
+        self.screen.get(row).map(String::as_str)
+    }
+
+    /// Returns the byte ranges of the line to highlight,
+    /// which are the matches of the search being typed or else of the last search.
+    fn highlights(&self, line_text: &str) -> Vec<Range<usize>> {
+        match self.prompt.as_ref().or(self.search.as_ref()) {
+            Some(search) => search.find_in_line(line_text),
+            None => vec![],
+        }
+    }
+
+    /// Records a delay read from the replayed script.
+    pub fn record_wait(&self, delay: u64) -> Result<()> {
+        match &self.save_events_stream {
+            Some(recorder) => recorder.write_wait(delay),
+            None => Ok(()),
+        }
+    }
+
+    /// Events are read from the terminal, even if the standard input is not one,
+    /// unless a script is replayed.
+    pub fn is_interactive(&self) -> bool {
+        !self.replaying
+    }
+
+    /// The line numbers column is as wide as the greatest number of the lines indexed so far,
+    /// so it may widen when a large document is scrolled.
+    fn n_digits(&self) -> usize {
+        format!("{}", self.document.indexed_line_count()).len()
+    }
+
+    /// Width of the line numbers column and of the space following it, if they are shown.
+    /// The rows of the hex view show their offsets instead.
+    fn gutter_width(&self) -> usize {
+        if self.show_line_numbers && !self.document.is_hex() {
+            self.n_digits() + 1
+        } else {
+            0
+        }
+    }
+
+    fn text_width(&self) -> usize {
+        (self.n_cols as usize).saturating_sub(self.gutter_width())
+    }
+
+    fn line_text(&self, line_index: usize) -> Cow<'_, str> {
+        self.document.line(line_index)
+    }
+
+    fn line_cells(&self, line_index: usize) -> Vec<Cell> {
+        layout(&self.line_text(line_index), self.tab_width)
+    }
+
+    /// Number of screen rows used by the line.
+    fn line_height(&self, line_index: usize) -> usize {
+        if self.wrap {
+            wrap(&self.line_cells(line_index), max(1, self.text_width())).len()
+        } else {
+            1
+        }
+    }
+
+    /// Returns the line index, or the greatest value of `first_line_index` if it is smaller,
+    /// which shows the end of the text.
+    /// When lines are filtered, the line index is first moved to a line shown.
+    fn clamp_first_line_index(&self, first_line_index: usize) -> usize {
+        let Some(first_line_index) = self
+            .next_shown_line(first_line_index)
+            .or_else(|| self.previous_shown_line(first_line_index))
+        else {
+            return 0;
+        };
+        // As every line uses at least a row, if more than a screenful of lines follows,
+        // the end of the text is not reached, and the rest of the document needs no indexing.
+        let mut line_index = first_line_index;
+        let mut n_following_lines = 0;
+        while n_following_lines < self.n_rows {
+            match self.next_shown_line(line_index + 1) {
+                Some(next_line_index) => line_index = next_line_index,
+                None => break,
+            }
+            n_following_lines += 1;
+        }
+        if n_following_lines == self.n_rows {
+            return first_line_index;
+        }
+        let mut rows = 0;
+        let mut line_index = usize::MAX;
+        while let Some(previous_line_index) = self.previous_shown_line(line_index) {
+            rows += self.line_height(previous_line_index);
+            if rows > self.n_rows as usize {
+                break;
+            }
+            line_index = previous_line_index;
+        }
+        min(first_line_index, line_index)
+    }
+
+    /// Returns the first line shown from the line included, which is the line itself
+    /// unless lines are filtered.
+    fn next_shown_line(&self, line_index: usize) -> Option<usize> {
+        if self.filter.is_active() {
+            self.filter.next_line(&self.document, line_index)
+        } else {
+            self.document.has_line(line_index).then_some(line_index)
+        }
+    }
+
+    /// Returns the last line shown before the line.
+    fn previous_shown_line(&self, line_index: usize) -> Option<usize> {
+        if self.filter.is_active() {
+            self.filter.previous_line(&self.document, line_index)
+        } else if line_index == 0 {
+            None
+        } else if self.document.has_line(line_index - 1) {
+            Some(line_index - 1)
+        } else {
+            self.document.line_count().checked_sub(1)
+        }
+    }
+
+    /// The greatest value of `first_column_index`,
+    /// which shows the end of the longest line indexed so far.
+    /// In edit mode, the cursor may be after the end of the longest line.
+    fn last_first_column_index(&self) -> usize {
+        (self.document.max_line_width() + usize::from(self.editing))
+            .saturating_sub(self.text_width())
+    }
+
+    pub fn is_following(&self) -> bool {
+        self.following
+    }
+
+    pub fn file_has_changed(&self) -> Result<bool> {
+        self.document.has_changed()
+    }
+
+    pub fn interpret_input(&mut self, input: Input) -> Result<EventOutcome> {
+        let previous_view = (self.buffer_index, self.first_line_index);
+        let outcome = match input {
+            Input::Event(event) => self.interpret_event(event),
+            Input::Action(action) => self.perform(action),
+            Input::FileChange => self.handle_file_change(),
+        }?;
+        self.synchronize_panes(previous_view);
+        Ok(outcome)
+    }
+
+    /// Scrolls the other pane of a split along with the focused one: in a diff,
+    /// to keep the aligned lines on the same rows, and otherwise, if the scrolling is bound,
+    /// by as many lines as the focused pane scrolled since the previous view,
+    /// given as the buffer index and the first line index.
+    fn synchronize_panes(&mut self, previous_view: (usize, usize)) {
+        let Some(split) = &mut self.split else {
+            return;
+        };
+        let other_first_line_index = if let Some(diff) = &mut split.diff {
+            let side = usize::from(!split.focus_first);
+            // The first row is the one following the line preceding the first line,
+            // so that the fillers before the first line are shown.
+            diff.first_row = match self.first_line_index.checked_sub(1) {
+                None => 0,
+                Some(previous_line_index) => diff
+                    .rows
+                    .iter()
+                    .position(|row| row.lines[side] == Some(previous_line_index))
+                    .map_or(diff.rows.len(), |row_index| row_index + 1),
+            };
+            diff.rows[diff.first_row..]
+                .iter()
+                .find_map(|row| row.lines[1 - side])
+        } else if self.scroll_bind && previous_view.0 == self.buffer_index {
+            let offset = self.first_line_index as isize - previous_view.1 as isize;
+            let other_buffer = self.buffers[split.other_buffer_index].as_ref().unwrap();
+            Some(other_buffer.first_line_index.saturating_add_signed(offset))
+        } else {
+            None
+        };
+        let first_column_index = self.first_column_index;
+        self.toggle_focus();
+        if let Some(first_line_index) = other_first_line_index {
+            self.first_line_index = self.clamp_first_line_index(first_line_index);
+        }
+        if self.scroll_bind
+            || self
+                .split
+                .as_ref()
+                .is_some_and(|split| split.diff.is_some())
+        {
+            self.first_column_index = min(first_column_index, self.last_first_column_index());
+        }
+        self.toggle_focus();
+    }
+
+    fn interpret_event(&mut self, event: Event) -> Result<EventOutcome> {
         match event {
-            Event::Key(event) => match event.code {
-                KeyCode::Down => self.handle_down(),
-                KeyCode::Up => self.handle_up(),
-                KeyCode::PageDown => self.handle_page_down(),
-                KeyCode::PageUp => self.handle_page_up(),
-                KeyCode::Esc => self.handle_esc(),
-                KeyCode::Char('q') if event.modifiers.contains(KeyModifiers::CONTROL) => {
-                    self.handle_ctrl_q()
-                }
-                _ => Ok(EventOutcome::Continue),
-            },
+            Event::Key(event) if self.confirming_quit => self.interpret_quit_key(event),
+            Event::Key(event) if self.pending_bookmark.is_some() => {
+                self.interpret_bookmark_key(event)
+            }
+            Event::Key(event) if self.prompt.is_some() => self.interpret_prompt_key(event),
+            Event::Key(event) if self.command_line.is_some() => self.interpret_command_key(event),
+            Event::Key(event) => {
+                self.message = None;
+                self.error = None;
+                if self.editing && self.interpret_edit_key(event)? {
+                    return Ok(EventOutcome::Continue);
+                }
+                match self.keymap.action(&event) {
+                    Some(action) => self.perform(action),
+                    None => Ok(EventOutcome::Continue),
+                }
+            }
+            Event::Mouse(event) => self.interpret_mouse(event),
             Event::Resize(cols, rows) => self.handle_resize(cols, rows),
-            _ => Ok(EventOutcome::Continue),
         }
     }
 
-    fn handle_down(&mut self) -> Result<EventOutcome> {
-        write_command(&self.save_events_stream, "down")?;
-        if self.lines.len() > self.first_line_index + self.n_rows as usize {
-            self.first_line_index += 1;
+    /// The wheel scrolls, a click on a line number selects the line,
+    /// and a drag over the text selects it; a completed selection is copied to the clipboard.
+    fn interpret_mouse(&mut self, event: MouseEvent) -> Result<EventOutcome> {
+        let Some(command) = mouse_command(&event) else {
+            return Ok(EventOutcome::Continue);
+        };
+        write_command(&self.save_events_stream, &command)?;
+        // A press or a wheel step in the other pane of a split focuses it.
+        if matches!(
+            event.kind,
+            MouseEventKind::Down(_) | MouseEventKind::ScrollDown | MouseEventKind::ScrollUp
+        ) && self.pane_position(event.column, event.row).is_none()
+        {
+            self.toggle_focus();
+            if self.pane_position(event.column, event.row).is_none() {
+                self.toggle_focus();
+            }
+        }
+        let position = self
+            .pane_position(event.column, event.row)
+            .and_then(|(column, row)| self.position_at(column, row));
+        match event.kind {
+            MouseEventKind::ScrollDown => {
+                for _ in 0..WHEEL_LINES {
+                    self.handle_down()?;
+                }
+            }
+            MouseEventKind::ScrollUp => {
+                for _ in 0..WHEEL_LINES {
+                    self.handle_up()?;
+                }
+            }
+            MouseEventKind::Down(_) => match position {
+                Some(((line_index, _), true)) => {
+                    let line_end = self.line_text(line_index).len();
+                    self.selection = Some(((line_index, 0), (line_index, line_end)));
+                    self.copy_selection()?;
+                }
+                Some((position, false)) => {
+                    if self.editing {
+                        self.cursor = position;
+                    }
+                    self.selection = Some((position, position));
+                    self.selecting = true;
+                }
+                None => self.selection = None,
+            },
+            MouseEventKind::Drag(_) => {
+                if let (Some((position, _)), Some((_, head)), true) =
+                    (position, &mut self.selection, self.selecting)
+                {
+                    *head = position;
+                }
+            }
+            _ => {
+                if self.selecting {
+                    self.selecting = false;
+                    // A click without drag only clears the selection.
+                    match self.selection {
+                        Some((anchor, head)) if anchor == head => self.selection = None,
+                        _ => self.copy_selection()?,
+                    }
+                }
+            }
+        }
+        Ok(EventOutcome::Continue)
+    }
+
+    /// Returns the position of the screen cell relative to the focused pane, if it is in it.
+    fn pane_position(&self, column: u16, row: u16) -> Option<(u16, u16)> {
+        let (origin_column, origin_row) = self.origin;
+        let (column, row) = (
+            column.checked_sub(origin_column)?,
+            row.checked_sub(origin_row)?,
+        );
+        (column < self.n_cols && row < self.n_rows).then_some((column, row))
+    }
+
+    /// Returns the position of the cell shown at the pane position,
+    /// or the end of the row if it is beyond the text,
+    /// and whether the screen position is on the line numbers.
+    fn position_at(&self, column: u16, row: u16) -> Option<(Position, bool)> {
+        let (column, row) = (column as usize, row as usize);
+        let gutter_width = self.gutter_width();
+        let mut first_row = 0;
+        for pane_line in self.pane_lines() {
+            let line_index = match pane_line {
+                PaneLine::Line(line_index) | PaneLine::ChangedLine(line_index) => line_index,
+                PaneLine::Filler if row == first_row => return None,
+                PaneLine::Filler => {
+                    first_row += 1;
+                    continue;
+                }
+            };
+            let cells = self.line_cells(line_index);
+            let segments = if self.wrap {
+                wrap(&cells, max(1, self.text_width()))
+            } else {
+                once(0..cells.len()).collect()
+            };
+            if row < first_row + segments.len() {
+                let segment = segments[row - first_row].clone();
+                let segment_end = cells
+                    .get(segment.end)
+                    .map_or(self.line_text(line_index).len(), |cell| cell.bytes.start);
+                if column < gutter_width {
+                    let segment_begin = cells
+                        .get(segment.start)
+                        .map_or(segment_end, |cell| cell.bytes.start);
+                    return Some(((line_index, segment_begin), true));
+                }
+                let mut x = column - gutter_width;
+                if !self.wrap {
+                    x += self.first_column_index;
+                }
+                for cell in &cells[segment] {
+                    if x < cell.width {
+                        return Some(((line_index, cell.bytes.start), false));
+                    }
+                    x -= cell.width;
+                }
+                return Some(((line_index, segment_end), false));
+            }
+            first_row += segments.len();
+        }
+        None
+    }
+
+    /// Returns the first and the last selected positions,
+    /// the last one being moved past the end of its cell.
+    fn selection_bounds(&self) -> Option<(Position, Position)> {
+        let (anchor, head) = self.selection?;
+        let (first, last) = (min(anchor, head), max(anchor, head));
+        let last_end = self
+            .line_cells(last.0)
+            .iter()
+            .find(|cell| cell.bytes.start == last.1)
+            .map_or(last.1, |cell| cell.bytes.end);
+        Some((first, (last.0, last_end)))
+    }
+
+    /// Returns the selected byte range of the line, if any.
+    fn selected_range(&self, line_index: usize) -> Option<Range<usize>> {
+        let (first, last) = self.selection_bounds()?;
+        if line_index < first.0 || line_index > last.0 {
+            return None;
+        }
+        let begin = if line_index == first.0 { first.1 } else { 0 };
+        let end = if line_index == last.0 {
+            last.1
+        } else {
+            self.line_text(line_index).len()
+        };
+        Some(begin..end)
+    }
+
+    fn copy_selection(&self) -> Result<()> {
+        if let Some((first, last)) = self.selection_bounds() {
+            let text = (first.0..=last.0)
+                .map(|line_index| {
+                    let range = self.selected_range(line_index).unwrap();
+                    self.line_text(line_index)[range].to_string()
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            stdout().queue(Print(copy_sequence(&text)))?;
+        }
+        Ok(())
+    }
+
+    /// Does what the action names, recording it by name, so that the event scripts
+    /// do not depend on the keymap.
+    fn perform(&mut self, action: Action) -> Result<EventOutcome> {
+        write_command(
+            &self.save_events_stream,
+            &format!("action {}", action.name()),
+        )?;
+        match action {
+            Action::LineDown => self.handle_down(),
+            Action::LineUp => self.handle_up(),
+            Action::PageDown => self.handle_page_down(),
+            Action::PageUp => self.handle_page_up(),
+            Action::ScrollRight => self.handle_right(),
+            Action::ScrollLeft => self.handle_left(),
+            Action::LineStart => self.handle_home(),
+            Action::LineEnd => self.handle_end(),
+            Action::Top => self.handle_top(),
+            Action::Bottom => self.handle_bottom(),
+            Action::ToggleWrap => self.handle_w(),
+            Action::ToggleFollow => self.handle_f(),
+            Action::SearchForward => self.handle_search_start(Direction::Forward),
+            Action::SearchBackward => self.handle_search_start(Direction::Backward),
+            Action::NextMatch => self.handle_n(false),
+            Action::PreviousMatch => self.handle_n(true),
+            Action::CommandLine => self.handle_colon(),
+            Action::SwitchPane => self.handle_switch_pane(),
+            Action::Edit => self.handle_edit(),
+            Action::Save => self.handle_save(),
+            Action::Undo => self.handle_undo(false),
+            Action::Redo => self.handle_undo(true),
+            Action::SetBookmark | Action::GoToBookmark => {
+                self.pending_bookmark = Some(action);
+                Ok(EventOutcome::Continue)
+            }
+            Action::JumpBack => self.handle_jump(false),
+            Action::JumpForward => self.handle_jump(true),
+            Action::ToggleFilter => self.handle_toggle_filter(),
+            Action::Quit => self.handle_quit(),
         }
+    }
+
+    fn handle_down(&mut self) -> Result<EventOutcome> {
+        self.first_line_index = max(
+            self.first_line_index,
+            self.clamp_first_line_index(self.first_line_index + 1),
+        );
         Ok(EventOutcome::Continue)
     }
 
     fn handle_up(&mut self) -> Result<EventOutcome> {
-        write_command(&self.save_events_stream, "up")?;
-        self.first_line_index -= min(1, self.first_line_index);
+        if let Some(line_index) = self.previous_shown_line(self.first_line_index) {
+            self.first_line_index = line_index;
+        }
         Ok(EventOutcome::Continue)
     }
 
     fn handle_page_down(&mut self) -> Result<EventOutcome> {
-        write_command(&self.save_events_stream, "page_down")?;
-        if self.lines.len() > self.first_line_index + self.n_rows as usize {
-            self.first_line_index += min(
-                self.n_rows as usize,
-                self.lines.len() - (self.first_line_index + self.n_rows as usize),
-            );
+        // The new first line is the first one not completely shown.
+        let mut rows = 0;
+        let mut line_index = self.first_line_index;
+        while let Some(shown_line_index) = self.next_shown_line(line_index) {
+            line_index = shown_line_index;
+            rows += self.line_height(line_index);
+            if rows > self.n_rows as usize {
+                break;
+            }
+            line_index += 1;
         }
+        self.first_line_index = max(
+            self.first_line_index,
+            self.clamp_first_line_index(max(self.first_line_index + 1, line_index)),
+        );
         Ok(EventOutcome::Continue)
     }
 
     fn handle_page_up(&mut self) -> Result<EventOutcome> {
-        write_command(&self.save_events_stream, "page_up")?;
-        self.first_line_index -= min(self.n_rows as usize, self.first_line_index);
+        // The new last line is the one preceding the current first line.
+        let mut rows = 0;
+        let mut line_index = self.first_line_index;
+        while let Some(previous_line_index) = self.previous_shown_line(line_index) {
+            rows += self.line_height(previous_line_index);
+            if rows > self.n_rows as usize && line_index < self.first_line_index {
+                break;
+            }
+            line_index = previous_line_index;
+        }
+        self.first_line_index = line_index;
         Ok(EventOutcome::Continue)
     }
 
-    fn handle_esc(&self) -> Result<EventOutcome> {
-        write_command(&self.save_events_stream, "esc")?;
-        Ok(EventOutcome::Terminate)
+    fn handle_top(&mut self) -> Result<EventOutcome> {
+        let origin = self.first_line_index;
+        self.first_line_index = self.clamp_first_line_index(0);
+        self.record_jump(origin);
+        Ok(EventOutcome::Continue)
     }
 
-    fn handle_ctrl_q(&self) -> Result<EventOutcome> {
-        write_command(&self.save_events_stream, "C q")?;
-        Ok(EventOutcome::Terminate)
+    fn handle_bottom(&mut self) -> Result<EventOutcome> {
+        let origin = self.first_line_index;
+        self.first_line_index = self.clamp_first_line_index(usize::MAX);
+        self.record_jump(origin);
+        Ok(EventOutcome::Continue)
     }
 
-    fn handle_resize(&mut self, cols: u16, rows: u16) -> Result<EventOutcome> {
-        if is_input_tty() {
-            (self.n_cols, self.n_rows) = size()?;
+    /// Remembers the first line shown before a jump, to go back to it, if the jump moved.
+    fn record_jump(&mut self, origin: usize) {
+        if origin != self.first_line_index {
+            self.places.record_jump(origin);
+        }
+    }
+
+    fn handle_jump(&mut self, forward: bool) -> Result<EventOutcome> {
+        let line_index = if forward {
+            self.places.go_forward(self.first_line_index)
+        } else {
+            self.places.go_back(self.first_line_index)
+        };
+        match line_index {
+            Some(line_index) => self.first_line_index = self.clamp_first_line_index(line_index),
+            None if forward => self.message = Some("No next position".to_string()),
+            None => self.message = Some("No previous position".to_string()),
+        }
+        Ok(EventOutcome::Continue)
+    }
+
+    /// After `m` or `'`, a digit sets the bookmark at the first line shown,
+    /// or goes to the line of the bookmark; any other key cancels.
+    fn interpret_bookmark_key(&mut self, event: KeyEvent) -> Result<EventOutcome> {
+        write_command(&self.save_events_stream, &key_name(&event))?;
+        let action = self.pending_bookmark.take();
+        let Some(digit) = (match event.code {
+            KeyCode::Char(c) => c.to_digit(10),
+            _ => None,
+        }) else {
+            return Ok(EventOutcome::Continue);
+        };
+        let digit = digit as u8;
+        if action == Some(Action::SetBookmark) {
+            self.places.bookmarks.insert(digit, self.first_line_index);
+            self.message = Some(format!(
+                "Bookmark {digit} set at line {}",
+                self.first_line_index + 1
+            ));
+        } else if let Some(line_index) = self.places.bookmarks.get(&digit).copied() {
+            let origin = self.first_line_index;
+            self.first_line_index = self.clamp_first_line_index(line_index);
+            self.record_jump(origin);
+        } else {
+            self.error = Some(format!("Bookmark {digit} is not set"));
+        }
+        Ok(EventOutcome::Continue)
+    }
+
+    /// Adds a rule to the filter of the focused document: the lines shown must contain
+    /// the text, or not contain it if the rule excludes them, or match the regular expression
+    /// if it is written between slashes.
+    fn add_filter(&mut self, pattern: &str, exclude: bool) -> std::result::Result<(), String> {
+        if self.editing {
+            return Err("No filtering in edit mode".to_string());
+        }
+        if self.is_diff_shown() {
+            return Err("No filtering in a diff".to_string());
+        }
+        let mut search = Search::new(Direction::Forward);
+        match pattern
+            .strip_prefix('/')
+            .and_then(|pattern| pattern.strip_suffix('/'))
+        {
+            Some(regex) => {
+                search.pattern = regex.to_string();
+                search.is_regex = true;
+            }
+            None => search.pattern = pattern.to_string(),
+        }
+        search.compile();
+        if search.pattern.is_empty() {
+            return Err("Missing filter pattern".to_string());
+        }
+        if !search.is_valid() {
+            return Err(format!("Invalid filter pattern: {pattern}"));
+        }
+        self.filter.push(search, exclude);
+        Ok(())
+    }
+
+    fn handle_toggle_filter(&mut self) -> Result<EventOutcome> {
+        if self.editing || self.is_diff_shown() {
+            return Ok(EventOutcome::Continue);
+        }
+        if !self.filter.toggle() {
+            self.error = Some("No filter".to_string());
+        }
+        self.first_line_index = self.clamp_first_line_index(self.first_line_index);
+        Ok(EventOutcome::Continue)
+    }
+
+    /// Returns the rules of the filter of the focused document.
+    fn filter_list(&self) -> String {
+        let rules = self.filter.describe();
+        if rules.is_empty() {
+            "No filter".to_string()
+        } else if self.filter.is_active() {
+            format!("Filters: {}", rules.join(", "))
         } else {
-            self.n_cols = cols;
-            self.n_rows = rows;
+            format!("Filters, off: {}", rules.join(", "))
+        }
+    }
+
+    /// Returns the bookmarks of the focused document with their line numbers.
+    fn bookmark_list(&self) -> String {
+        if self.places.bookmarks.is_empty() {
+            return "No bookmarks".to_string();
+        }
+        let entries: Vec<String> = self
+            .places
+            .bookmarks
+            .iter()
+            .map(|(digit, line_index)| format!("{digit} line {}", line_index + 1))
+            .collect();
+        format!("Bookmarks: {}", entries.join(", "))
+    }
+
+    fn handle_right(&mut self) -> Result<EventOutcome> {
+        if !self.wrap && self.first_column_index < self.last_first_column_index() {
+            self.first_column_index += 1;
+        }
+        Ok(EventOutcome::Continue)
+    }
+
+    fn handle_left(&mut self) -> Result<EventOutcome> {
+        self.first_column_index -= min(1, self.first_column_index);
+        Ok(EventOutcome::Continue)
+    }
+
+    fn handle_end(&mut self) -> Result<EventOutcome> {
+        if !self.wrap {
+            self.first_column_index = self.last_first_column_index();
         }
-        write_resize_command(&self.save_events_stream, self.n_cols, self.n_rows)?;
-        self.first_line_index = min(
+        Ok(EventOutcome::Continue)
+    }
+
+    fn handle_home(&mut self) -> Result<EventOutcome> {
+        self.first_column_index = 0;
+        Ok(EventOutcome::Continue)
+    }
+
+    fn handle_w(&mut self) -> Result<EventOutcome> {
+        // The rows of a diff show a line each.
+        if self.is_diff_shown() {
+            self.error = Some("No wrapping in a diff".to_string());
+            return Ok(EventOutcome::Continue);
+        }
+        self.wrap = !self.wrap;
+        self.first_column_index = 0;
+        self.first_line_index = self.clamp_first_line_index(self.first_line_index);
+        Ok(EventOutcome::Continue)
+    }
+
+    /// Toggles the follow mode, which starts at the end of the text.
+    fn handle_f(&mut self) -> Result<EventOutcome> {
+        self.following = !self.following;
+        if self.following {
+            self.first_line_index = self.clamp_first_line_index(usize::MAX);
+        }
+        Ok(EventOutcome::Continue)
+    }
+
+    /// Enters the edit mode, with the cursor where it was if it is shown, or else at the top.
+    /// The followed file no longer changes the text.
+    fn handle_edit(&mut self) -> Result<EventOutcome> {
+        if self.document.is_hex() {
+            self.error = Some("No editing in the hex view".to_string());
+            return Ok(EventOutcome::Continue);
+        }
+        if self.filter.is_active() {
+            self.error = Some("No editing while lines are filtered".to_string());
+            return Ok(EventOutcome::Continue);
+        }
+        if let Err(error) = self.document.make_editable() {
+            self.error = Some(format!("No editing: {error}"));
+            return Ok(EventOutcome::Continue);
+        }
+        self.following = false;
+        self.editing = true;
+        if !self.is_line_shown(self.cursor.0) {
+            self.cursor = (self.first_line_index, 0);
+        }
+        self.clamp_cursor();
+        Ok(EventOutcome::Continue)
+    }
+
+    /// Moves the cursor to the nearest position of the text, at the start of a cell.
+    fn clamp_cursor(&mut self) {
+        let line_index = min(self.cursor.0, self.document.line_count().saturating_sub(1));
+        let byte_index = if self.document.has_line(line_index) {
+            let line_end = self.line_text(line_index).len();
+            self.line_cells(line_index)
+                .iter()
+                .map(|cell| cell.bytes.start)
+                .take_while(|start| *start <= self.cursor.1)
+                .last()
+                .map_or(0, |start| {
+                    if self.cursor.1 >= line_end {
+                        line_end
+                    } else {
+                        start
+                    }
+                })
+        } else {
+            0
+        };
+        self.cursor = (line_index, byte_index);
+    }
+
+    /// In edit mode, the characters, Tab, Enter, Backspace and Delete edit the text,
+    /// the arrows and the Home, End, Page Up and Page Down keys move the cursor,
+    /// and Esc leaves the edit mode. Returns whether the key was one of them,
+    /// the others doing what they do in view mode.
+    fn interpret_edit_key(&mut self, event: KeyEvent) -> Result<bool> {
+        if event
+            .modifiers
+            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+        {
+            return Ok(false);
+        }
+        let line_index = self.cursor.0;
+        let page_offset = max(1, self.n_rows as isize - 1);
+        match event.code {
+            KeyCode::Char(c) => self.insert_text(&c.to_string()),
+            KeyCode::Tab => self.insert_text("\t"),
+            KeyCode::Enter => self.break_line(),
+            KeyCode::Backspace => self.delete_cell(false),
+            KeyCode::Delete => self.delete_cell(true),
+            KeyCode::Left => self.move_cursor_horizontally(false),
+            KeyCode::Right => self.move_cursor_horizontally(true),
+            KeyCode::Up => self.move_cursor_vertically(-1),
+            KeyCode::Down => self.move_cursor_vertically(1),
+            KeyCode::PageUp => self.move_cursor_vertically(-page_offset),
+            KeyCode::PageDown => self.move_cursor_vertically(page_offset),
+            KeyCode::Home => self.cursor = (line_index, 0),
+            KeyCode::End => self.cursor = (line_index, self.line_text(line_index).len()),
+            KeyCode::Esc => self.editing = false,
+            _ => return Ok(false),
+        }
+        write_command(&self.save_events_stream, &key_name(&event))?;
+        if self.editing {
+            self.scroll_to_cursor();
+        } else {
+            // The column after the end of the longest line is no longer reachable.
+            self.first_column_index = min(self.first_column_index, self.last_first_column_index());
+        }
+        Ok(true)
+    }
+
+    fn insert_text(&mut self, text: &str) {
+        let (line_index, byte_index) = self.cursor;
+        let mut line = self.line_text(line_index).into_owned();
+        line.insert_str(byte_index, text);
+        let cursor_after = (line_index, byte_index + text.len());
+        self.edit(line_index, 1, vec![line], cursor_after, true);
+    }
+
+    fn break_line(&mut self) {
+        let (line_index, byte_index) = self.cursor;
+        let line = self.line_text(line_index).into_owned();
+        let (head, tail) = line.split_at(byte_index);
+        let lines = vec![head.to_string(), tail.to_string()];
+        self.edit(line_index, 1, lines, (line_index + 1, 0), false);
+    }
+
+    /// Deletes the cell after, or before, the cursor; at the end, or at the start, of the line,
+    /// joins it with the next, or the previous, one.
+    fn delete_cell(&mut self, forward: bool) {
+        let (line_index, byte_index) = self.cursor;
+        let mut line = self.line_text(line_index).into_owned();
+        let cell_bytes = self
+            .line_cells(line_index)
+            .into_iter()
+            .map(|cell| cell.bytes)
+            .find(|bytes| {
+                if forward {
+                    bytes.start == byte_index
+                } else {
+                    bytes.end == byte_index
+                }
+            });
+        match cell_bytes {
+            Some(bytes) => {
+                let cursor_after = (line_index, bytes.start);
+                line.replace_range(bytes, "");
+                self.edit(line_index, 1, vec![line], cursor_after, false);
+            }
+            None if forward && self.document.has_line(line_index + 1) => {
+                line.push_str(&self.line_text(line_index + 1));
+                self.edit(line_index, 2, vec![line], self.cursor, false);
+            }
+            None if !forward && line_index > 0 => {
+                let mut previous_line = self.line_text(line_index - 1).into_owned();
+                let cursor_after = (line_index - 1, previous_line.len());
+                previous_line.push_str(&line);
+                self.edit(line_index - 1, 2, vec![previous_line], cursor_after, false);
+            }
+            None => {}
+        }
+    }
+
+    /// Replaces lines of the focused document, recording the change to undo it,
+    /// and moves the cursor.
+    fn edit(
+        &mut self,
+        line_index: usize,
+        n_removed: usize,
+        inserted: Vec<String>,
+        cursor_after: Position,
+        typing: bool,
+    ) {
+        let removed = self
+            .document
+            .splice_lines(line_index..line_index + n_removed, inserted.clone());
+        self.history.record(Step {
+            line_index,
+            removed,
+            inserted,
+            cursor_before: self.cursor,
+            cursor_after,
+            typing,
+        });
+        self.after_change(line_index, cursor_after);
+    }
+
+    /// Does again, or undoes, a change.
+    fn apply_step(&mut self, step: &Step) {
+        self.document.splice_lines(
+            step.line_index..step.line_index + step.removed.len(),
+            step.inserted.clone(),
+        );
+        self.after_change(step.line_index, step.cursor_after);
+    }
+
+    fn after_change(&mut self, line_index: usize, cursor: Position) {
+        self.cursor = cursor;
+        // The following lines may be highlighted differently, as after an unclosed comment.
+        self.highlighter.forget_from(line_index);
+        self.current_match = None;
+        self.selection = None;
+        if self.is_diff_shown() {
+            self.compute_diff();
+        }
+    }
+
+    /// Moves the cursor to the next, or the previous, cell, going to the next,
+    /// or the previous, line at the end, or at the start, of a line.
+    fn move_cursor_horizontally(&mut self, forward: bool) {
+        let (line_index, byte_index) = self.cursor;
+        let cells = self.line_cells(line_index);
+        self.cursor = if forward {
+            match cells.iter().find(|cell| cell.bytes.start == byte_index) {
+                Some(cell) => (line_index, cell.bytes.end),
+                None if self.document.has_line(line_index + 1) => (line_index + 1, 0),
+                None => self.cursor,
+            }
+        } else {
+            match cells.iter().find(|cell| cell.bytes.end == byte_index) {
+                Some(cell) => (line_index, cell.bytes.start),
+                None if line_index > 0 => (line_index - 1, self.line_text(line_index - 1).len()),
+                None => self.cursor,
+            }
+        };
+    }
+
+    /// Moves the cursor up, or down, by lines, keeping its column as far as possible.
+    fn move_cursor_vertically(&mut self, offset: isize) {
+        let (line_index, byte_index) = self.cursor;
+        let column = column_of_byte(&self.line_cells(line_index), byte_index);
+        let new_line_index = min(
+            line_index.saturating_add_signed(offset),
+            self.document.line_count() - 1,
+        );
+        self.cursor = (
+            new_line_index,
+            byte_of_column(&self.line_cells(new_line_index), column),
+        );
+    }
+
+    /// Scrolls as little as possible to show the cursor.
+    fn scroll_to_cursor(&mut self) {
+        let (line_index, byte_index) = self.cursor;
+        if line_index < self.first_line_index {
+            self.first_line_index = line_index;
+        }
+        // Below the last line shown, the cursor line becomes the last one,
+        // above the last row of the screen, which shows the edit mode.
+        let mut n_rows = self.n_rows as usize;
+        if self.origin.1 + self.n_rows == self.screen_size.1 {
+            n_rows = max(1, n_rows.saturating_sub(1));
+        }
+        let mut rows = self.line_height(line_index);
+        let mut first_line_index = line_index;
+        while first_line_index > self.first_line_index
+            && rows + self.line_height(first_line_index - 1) <= n_rows
+        {
+            first_line_index -= 1;
+            rows += self.line_height(first_line_index);
+        }
+        self.first_line_index = first_line_index;
+        if !self.wrap {
+            let column = column_of_byte(&self.line_cells(line_index), byte_index);
+            let text_width = max(1, self.text_width());
+            if column < self.first_column_index {
+                self.first_column_index = column;
+            } else if column >= self.first_column_index + text_width {
+                self.first_column_index = column + 1 - text_width;
+            }
+        }
+    }
+
+    fn handle_save(&mut self) -> Result<EventOutcome> {
+        let path = self.document.path().to_string();
+        match self.document.save() {
+            Ok(()) => self.message = Some(format!("Saved {path}")),
+            Err(error) => self.error = Some(format!("Cannot save {path}: {error}")),
+        }
+        Ok(EventOutcome::Continue)
+    }
+
+    /// Saves the modified documents, stopping at the first failure.
+    fn save_all(&mut self) -> std::result::Result<(), String> {
+        let documents = once(&mut self.document).chain(
+            self.buffers
+                .iter_mut()
+                .flatten()
+                .map(|buffer| &mut buffer.document),
+        );
+        for document in documents.filter(|document| document.is_modified()) {
+            document
+                .save()
+                .map_err(|error| format!("Cannot save {}: {error}", document.path()))?;
+        }
+        Ok(())
+    }
+
+    fn modified_paths(&self) -> Vec<&str> {
+        once(&self.document)
+            .chain(self.buffers.iter().flatten().map(|buffer| &buffer.document))
+            .filter(|document| document.is_modified())
+            .map(Document::path)
+            .collect()
+    }
+
+    fn handle_undo(&mut self, redo: bool) -> Result<EventOutcome> {
+        let step = if redo {
+            self.history.redo()
+        } else {
+            self.history.undo()
+        };
+        match step {
+            Some(step) => {
+                self.apply_step(&step);
+                self.scroll_to_cursor();
+            }
+            None if redo => self.message = Some("Nothing to redo".to_string()),
+            None => self.message = Some("Nothing to undo".to_string()),
+        }
+        Ok(EventOutcome::Continue)
+    }
+
+    /// Quits, unless documents are modified, in which case the user is asked to save them first.
+    fn handle_quit(&mut self) -> Result<EventOutcome> {
+        if self.modified_paths().is_empty() {
+            return Ok(EventOutcome::Terminate);
+        }
+        self.confirming_quit = true;
+        Ok(EventOutcome::Continue)
+    }
+
+    /// Before quitting, Y saves the modified documents, N leaves them unsaved,
+    /// and Esc cancels; the other keys are ignored.
+    fn interpret_quit_key(&mut self, event: KeyEvent) -> Result<EventOutcome> {
+        if !matches!(
+            event.code,
+            KeyCode::Char('y' | 'Y' | 'n' | 'N') | KeyCode::Esc
+        ) {
+            return Ok(EventOutcome::Continue);
+        }
+        write_command(&self.save_events_stream, &key_name(&event))?;
+        self.confirming_quit = false;
+        match event.code {
+            KeyCode::Char('y' | 'Y') => match self.save_all() {
+                Ok(()) => Ok(EventOutcome::Terminate),
+                Err(message) => {
+                    self.error = Some(message);
+                    Ok(EventOutcome::Continue)
+                }
+            },
+            KeyCode::Char(_) => Ok(EventOutcome::Terminate),
+            _ => Ok(EventOutcome::Continue),
+        }
+    }
+
+    /// Reads the changes of the file; in follow mode, if the end of the text was shown,
+    /// scrolls to show the new end.
+    fn handle_file_change(&mut self) -> Result<EventOutcome> {
+        write_command(&self.save_events_stream, "file_changed")?;
+        let was_at_end = self.first_line_index >= self.clamp_first_line_index(usize::MAX);
+        match self.document.reload()? {
+            Change::None => {}
+            // The lines indexed before the new bytes, except an unterminated last one, are unchanged.
+            Change::Appended => {
+                let line_count = self.document.indexed_line_count();
+                self.highlighter.forget_from(line_count);
+                self.filter.forget_from(line_count);
+            }
+            Change::Replaced => {
+                self.highlighter.forget_from(0);
+                self.filter.forget_from(0);
+                self.current_match = None;
+                self.selection = None;
+            }
+        }
+        if self.is_diff_shown() {
+            self.compute_diff();
+        }
+        self.first_line_index = if self.following && was_at_end {
+            self.clamp_first_line_index(usize::MAX)
+        } else {
+            self.clamp_first_line_index(self.first_line_index)
+        };
+        self.first_column_index = min(self.first_column_index, self.last_first_column_index());
+        self.clamp_cursor();
+        Ok(EventOutcome::Continue)
+    }
+
+    fn handle_search_start(&mut self, direction: Direction) -> Result<EventOutcome> {
+        let mut prompt = Search::new(direction);
+        if let Some(search) = &self.search {
+            prompt.is_regex = search.is_regex;
+            prompt.ignore_case = search.ignore_case;
+        }
+        self.prompt = Some(prompt);
+        self.prompt_origin = (
             self.first_line_index,
-            max(0, self.lines.len() as isize - self.n_rows as isize) as usize,
+            self.first_column_index,
+            self.current_match.clone(),
+        );
+        Ok(EventOutcome::Continue)
+    }
+
+    /// While the search prompt is shown, the keys edit the pattern,
+    /// and the first match is shown while typing.
+    fn interpret_prompt_key(&mut self, event: KeyEvent) -> Result<EventOutcome> {
+        let ctrl = event.modifiers.contains(KeyModifiers::CONTROL);
+        let prompt = self.prompt.as_mut().unwrap();
+        match event.code {
+            KeyCode::Char('q') if ctrl => return self.perform(Action::Quit),
+            KeyCode::Char('r') if ctrl => {
+                write_command(&self.save_events_stream, "C r")?;
+                prompt.is_regex = !prompt.is_regex;
+            }
+            KeyCode::Char('a') if ctrl => {
+                write_command(&self.save_events_stream, "C a")?;
+                prompt.ignore_case = !prompt.ignore_case;
+            }
+            KeyCode::Char(c) if !ctrl => {
+                write_command(&self.save_events_stream, &key_name(&event))?;
+                prompt.pattern.push(c);
+            }
+            KeyCode::Backspace => {
+                write_command(&self.save_events_stream, "backspace")?;
+                prompt.pattern.pop();
+            }
+            KeyCode::Enter => {
+                write_command(&self.save_events_stream, "enter")?;
+                let mut search = self.prompt.take().unwrap();
+                // An empty pattern repeats the last search in the new direction.
+                if search.pattern.is_empty() {
+                    if let Some(last_search) = &self.search {
+                        let direction = search.direction;
+                        search = last_search.clone();
+                        search.direction = direction;
+                        self.search = Some(search);
+                        self.go_to_next_match(false);
+                    }
+                } else {
+                    self.search = Some(search);
+                    self.record_jump(self.prompt_origin.0);
+                }
+                return Ok(EventOutcome::Continue);
+            }
+            KeyCode::Esc => {
+                write_command(&self.save_events_stream, "esc")?;
+                self.prompt = None;
+                (
+                    self.first_line_index,
+                    self.first_column_index,
+                    self.current_match,
+                ) = self.prompt_origin.clone();
+                return Ok(EventOutcome::Continue);
+            }
+            _ => return Ok(EventOutcome::Continue),
+        }
+        prompt.compile();
+        // Every change of the pattern searches again from the origin.
+        let prompt = prompt.clone();
+        (
+            self.first_line_index,
+            self.first_column_index,
+            self.current_match,
+        ) = self.prompt_origin.clone();
+        let (line_index, byte_index) = match &self.prompt_origin.2 {
+            Some((line_index, range)) => (*line_index, range.start),
+            None => (self.first_line_index, 0),
+        };
+        if let Some(found) =
+            self.find_match(&prompt, line_index, byte_index, prompt.direction, true)
+        {
+            self.show_match(found);
+        }
+        Ok(EventOutcome::Continue)
+    }
+
+    fn handle_n(&mut self, reversed: bool) -> Result<EventOutcome> {
+        self.go_to_next_match(reversed);
+        Ok(EventOutcome::Continue)
+    }
+
+    /// Shows the match following the current one in the direction of the last search.
+    fn go_to_next_match(&mut self, reversed: bool) {
+        if let Some(search) = self.search.clone() {
+            let direction = if reversed {
+                search.direction.reversed()
+            } else {
+                search.direction
+            };
+            let found = match &self.current_match {
+                Some((line_index, range)) => {
+                    self.find_match(&search, *line_index, range.start, direction, false)
+                }
+                None => self.find_match(&search, self.first_line_index, 0, direction, true),
+            };
+            if let Some(found) = found {
+                let origin = self.first_line_index;
+                self.show_match(found);
+                self.record_jump(origin);
+            }
+        }
+    }
+
+    /// Looks for a match starting after, or before, the specified position,
+    /// continuing from the other end of the text when an end is reached.
+    fn find_match(
+        &self,
+        search: &Search,
+        line_index: usize,
+        byte_index: usize,
+        direction: Direction,
+        inclusive: bool,
+    ) -> Option<(usize, Range<usize>)> {
+        let n_lines = self.document.line_count();
+        if n_lines == 0 {
+            return None;
+        }
+        for step in 0..=n_lines {
+            let current_line_index = match direction {
+                Direction::Forward => (line_index + step) % n_lines,
+                Direction::Backward => (line_index + n_lines - step % n_lines) % n_lines,
+            };
+            if self.is_line_filtered_out(current_line_index) {
+                continue;
+            }
+            let mut matches = search.find_in_line(&self.line_text(current_line_index));
+            // On the starting line, only the part after, or before, the position is considered;
+            // when it is reached again, only the other part.
+            if step == 0 {
+                matches.retain(|found| match direction {
+                    Direction::Forward => {
+                        found.start > byte_index || inclusive && found.start == byte_index
+                    }
+                    Direction::Backward => {
+                        found.start < byte_index || inclusive && found.start == byte_index
+                    }
+                });
+            }
+            let found = match direction {
+                Direction::Forward => matches.into_iter().next(),
+                Direction::Backward => matches.into_iter().last(),
+            };
+            if let Some(found) = found {
+                return Some((current_line_index, found));
+            }
+        }
+        None
+    }
+
+    /// Tells whether the line is entirely shown.
+    fn is_line_shown(&self, line_index: usize) -> bool {
+        line_index >= self.first_line_index
+            && !self.is_line_filtered_out(line_index)
+            && (self.first_line_index..=line_index)
+                .filter(|i| !self.is_line_filtered_out(*i))
+                .map(|i| self.line_height(i))
+                .sum::<usize>()
+                <= self.n_rows as usize
+    }
+
+    fn is_line_filtered_out(&self, line_index: usize) -> bool {
+        self.filter.is_active() && self.next_shown_line(line_index) != Some(line_index)
+    }
+
+    /// Scrolls, if needed, to make the match visible, and makes it the current one.
+    fn show_match(&mut self, found: (usize, Range<usize>)) {
+        let (line_index, range) = &found;
+        if !self.is_line_shown(*line_index) {
+            self.first_line_index = self.clamp_first_line_index(*line_index);
+        }
+        if !self.wrap {
+            let cells = self.line_cells(*line_index);
+            let begin = column_of_byte(&cells, range.start);
+            let end = column_of_byte(&cells, range.end);
+            if begin < self.first_column_index || end > self.first_column_index + self.text_width()
+            {
+                self.first_column_index = min(begin, self.last_first_column_index());
+            }
+        }
+        self.current_match = Some(found);
+    }
+
+    fn handle_colon(&mut self) -> Result<EventOutcome> {
+        self.command_line = Some(String::new());
+        Ok(EventOutcome::Continue)
+    }
+
+    /// While the command line is shown, the keys edit the command,
+    /// which is executed by Enter.
+    fn interpret_command_key(&mut self, event: KeyEvent) -> Result<EventOutcome> {
+        let ctrl = event.modifiers.contains(KeyModifiers::CONTROL);
+        let command_line = self.command_line.as_mut().unwrap();
+        match event.code {
+            KeyCode::Char('q') if ctrl => return self.perform(Action::Quit),
+            KeyCode::Char(c) if !ctrl => {
+                write_command(&self.save_events_stream, &key_name(&event))?;
+                command_line.push(c);
+            }
+            // Erasing past the colon leaves the command line, as in vi.
+            KeyCode::Backspace => {
+                write_command(&self.save_events_stream, "backspace")?;
+                if command_line.pop().is_none() {
+                    self.command_line = None;
+                }
+            }
+            KeyCode::Enter => {
+                write_command(&self.save_events_stream, "enter")?;
+                let command = self.command_line.take().unwrap();
+                return self.execute_command(command.trim());
+            }
+            KeyCode::Esc => {
+                write_command(&self.save_events_stream, "esc")?;
+                self.command_line = None;
+            }
+            _ => {}
+        }
+        Ok(EventOutcome::Continue)
+    }
+
+    /// Executes `:N`, which shows the line N at the top, `:q`, `:q!`, `:w`, `:wq`,
+    /// `:set number` and `:set nonumber`, which show or hide the line numbers,
+    /// or one of the commands on the documents and the panes.
+    fn execute_command(&mut self, command: &str) -> Result<EventOutcome> {
+        if let Ok(line_number) = command.parse::<usize>() {
+            let origin = self.first_line_index;
+            self.first_line_index = self.clamp_first_line_index(line_number.saturating_sub(1));
+            self.record_jump(origin);
+            return Ok(EventOutcome::Continue);
+        }
+        let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+        let n_buffers = self.buffers.len();
+        let executed = match (name, argument.trim()) {
+            ("", "") => Ok(()),
+            ("q" | "quit", "") => return self.handle_quit(),
+            ("q!" | "quit!", "") => return Ok(EventOutcome::Terminate),
+            ("w" | "write", "") => return self.handle_save(),
+            ("wq" | "x" | "xit", "") => {
+                self.handle_save()?;
+                if self.document.is_modified() {
+                    return Ok(EventOutcome::Continue);
+                }
+                return self.handle_quit();
+            }
+            ("set", "number" | "nu") => {
+                self.show_line_numbers = true;
+                Ok(())
+            }
+            ("set", "nonumber" | "nonu") => {
+                self.show_line_numbers = false;
+                Ok(())
+            }
+            ("set", "status") => {
+                self.status_bar = true;
+                self.layout_panes();
+                Ok(())
+            }
+            ("set", "nostatus") => {
+                self.status_bar = false;
+                self.layout_panes();
+                Ok(())
+            }
+            ("set", "scrollbind" | "scb") => {
+                self.scroll_bind = true;
+                Ok(())
+            }
+            ("set", "noscrollbind" | "noscb") => {
+                self.scroll_bind = false;
+                Ok(())
+            }
+            ("filter" | "filter!", pattern) => self.add_filter(pattern, name == "filter!"),
+            ("unfilter", "") => {
+                if self.filter.pop() {
+                    Ok(())
+                } else {
+                    Err("No filter".to_string())
+                }
+            }
+            ("nofilter", "") => {
+                self.filter.clear();
+                Ok(())
+            }
+            ("filters", "") => {
+                self.message = Some(self.filter_list());
+                Ok(())
+            }
+            ("marks", "") => {
+                self.message = Some(self.bookmark_list());
+                Ok(())
+            }
+            ("ls" | "buffers", "") => {
+                self.message = Some(self.buffer_list());
+                Ok(())
+            }
+            ("bn" | "bnext", "") => {
+                self.show_buffer((self.buffer_index + 1) % n_buffers);
+                Ok(())
+            }
+            ("bp" | "bprevious", "") => {
+                self.show_buffer((self.buffer_index + n_buffers - 1) % n_buffers);
+                Ok(())
+            }
+            ("b" | "buffer", number) => self
+                .parse_buffer_number(number)
+                .map(|buffer_index| self.show_buffer(buffer_index)),
+            ("sp" | "split", number) => self.split_panes(false, number, false),
+            ("vs" | "vsplit", number) => self.split_panes(true, number, false),
+            ("diff", number) => self.split_panes(true, number, true),
+            ("only" | "on", "") => {
+                self.split = None;
+                self.layout_panes();
+                Ok(())
+            }
+            _ => Err(format!("Unknown command: {command}")),
+        };
+        if let Err(message) = executed {
+            self.error = Some(message);
+        }
+        // The text width changes with the line numbers and the panes,
+        // and so do the heights of wrapped lines.
+        self.first_line_index = self.clamp_first_line_index(self.first_line_index);
+        self.first_column_index = min(self.first_column_index, self.last_first_column_index());
+        Ok(EventOutcome::Continue)
+    }
+
+    /// Returns the numbers and paths of the documents, the focused one being between brackets.
+    fn buffer_list(&self) -> String {
+        let entries: Vec<String> = self
+            .buffers
+            .iter()
+            .enumerate()
+            .map(|(buffer_index, buffer)| match buffer {
+                Some(buffer) => format!("{} {}", buffer_index + 1, buffer.document.path()),
+                None => format!("[{} {}]", buffer_index + 1, self.document.path()),
+            })
+            .collect();
+        format!("Documents: {}", entries.join(", "))
+    }
+
+    /// Returns the index of the buffer with the number, counted from 1.
+    fn parse_buffer_number(&self, number: &str) -> std::result::Result<usize, String> {
+        number
+            .parse::<usize>()
+            .ok()
+            .filter(|number| (1..=self.buffers.len()).contains(number))
+            .map(|number| number - 1)
+            .ok_or_else(|| format!("No document {number}"))
+    }
+
+    /// Shows the buffer in the focused pane; if it is shown in the other pane,
+    /// the panes exchange their documents.
+    fn show_buffer(&mut self, buffer_index: usize) {
+        let previous_buffer_index = self.buffer_index;
+        if buffer_index == previous_buffer_index {
+            return;
+        }
+        self.swap_buffer(buffer_index);
+        self.selecting = false;
+        if let Some(split) = &mut self.split {
+            if split.other_buffer_index == buffer_index {
+                split.other_buffer_index = previous_buffer_index;
+            }
+        }
+        if self.is_diff_shown() {
+            self.compute_diff();
+        }
+    }
+
+    /// Splits the screen, the focused document staying in the left or the top pane,
+    /// and the other pane showing the document with the number, or else the next one.
+    /// In a diff, the lines of the focused document are the old ones.
+    fn split_panes(
+        &mut self,
+        vertical: bool,
+        number: &str,
+        diff: bool,
+    ) -> std::result::Result<(), String> {
+        if self.buffers.len() < 2 {
+            return Err("Only one document is open".to_string());
+        }
+        let other_buffer_index = if number.is_empty() {
+            (self.buffer_index + 1) % self.buffers.len()
+        } else {
+            self.parse_buffer_number(number)?
+        };
+        if other_buffer_index == self.buffer_index {
+            return Err(format!("Document {number} is already shown"));
+        }
+        if diff
+            && (self.filter.is_active()
+                || self.buffers[other_buffer_index]
+                    .as_ref()
+                    .is_some_and(|buffer| buffer.filter.is_active()))
+        {
+            return Err("No diff of filtered lines".to_string());
+        }
+        self.split = Some(Split {
+            vertical,
+            other_buffer_index,
+            focus_first: true,
+            diff: None,
+        });
+        self.layout_panes();
+        if diff {
+            self.wrap = false;
+            self.compute_diff();
+        }
+        Ok(())
+    }
+
+    fn is_diff_shown(&self) -> bool {
+        self.split
+            .as_ref()
+            .is_some_and(|split| split.diff.is_some())
+    }
+
+    /// Aligns the lines of the documents of the two panes.
+    fn compute_diff(&mut self) {
+        let Some(split) = &self.split else {
+            return;
+        };
+        let lines = |document: &Document| -> Vec<String> {
+            (0..document.line_count())
+                .map(|line_index| document.line(line_index).into_owned())
+                .collect()
+        };
+        let focused_lines = lines(&self.document);
+        let other_lines = lines(
+            &self.buffers[split.other_buffer_index]
+                .as_ref()
+                .unwrap()
+                .document,
         );
+        let rows = if split.focus_first {
+            align(&focused_lines, &other_lines)
+        } else {
+            align(&other_lines, &focused_lines)
+        };
+        self.split.as_mut().unwrap().diff = Some(Diff { rows, first_row: 0 });
+    }
+
+    fn handle_switch_pane(&mut self) -> Result<EventOutcome> {
+        self.toggle_focus();
+        self.selecting = false;
+        Ok(EventOutcome::Continue)
+    }
+
+    fn handle_resize(&mut self, cols: u16, rows: u16) -> Result<EventOutcome> {
+        let (cols, rows) = if self.is_interactive() {
+            size()?
+        } else {
+            (cols, rows)
+        };
+        self.set_screen_size(cols, rows);
+        write_resize_command(&self.save_events_stream, cols, rows)?;
+        self.first_line_index = self.clamp_first_line_index(self.first_line_index);
+        self.first_column_index = min(self.first_column_index, self.last_first_column_index());
         Ok(EventOutcome::Continue)
     }
 }
 
-fn write_resize_command(save_events_stream: &Option<File>, n_cols: u16, n_rows: u16) -> Result<()> {
+fn set_style(out: &mut Stdout, (foreground, background): Style) -> Result<&mut Stdout> {
+    out.queue(SetForegroundColor(foreground))?
+        .queue(SetBackgroundColor(background))
+}
+
+/// Returns the width, or the height, of the first pane of a split of the screen length.
+fn first_pane_length(length: u16) -> u16 {
+    length.saturating_sub(1) / 2
+}
+
+fn write_resize_command(
+    save_events_stream: &Option<Recorder>,
+    n_cols: u16,
+    n_rows: u16,
+) -> Result<()> {
     write_command(save_events_stream, &format!("cols{n_cols} rows{n_rows}"))
 }
 
-fn write_command(save_events_stream: &Option<File>, command: &str) -> Result<()> {
-    if save_events_stream.is_some() {
-        let mut stream = save_events_stream.as_ref().unwrap();
-        stream.write_all(command.as_bytes())?;
-        stream.write_all(b"\n")?;
-    }
-    Ok(())
+/// Returns how a mouse event is written in the event scripts, like `mouse drag 12 3`,
+/// where 12 is the column and 3 the row, or `None` for the ignored events.
+fn mouse_command(event: &MouseEvent) -> Option<String> {
+    let kind = match event.kind {
+        MouseEventKind::Down(MouseButton::Left) => "press",
+        MouseEventKind::Drag(MouseButton::Left) => "drag",
+        MouseEventKind::Up(MouseButton::Left) => "release",
+        MouseEventKind::ScrollDown => "scroll_down",
+        MouseEventKind::ScrollUp => "scroll_up",
+        _ => return None,
+    };
+    Some(format!("mouse {kind} {} {}", event.column, event.row))
 }
 
-pub fn is_input_tty() -> bool {
-    stdin().is_tty()
+fn write_command(save_events_stream: &Option<Recorder>, command: &str) -> Result<()> {
+    match save_events_stream {
+        Some(recorder) => recorder.write(command),
+        None => Ok(()),
+    }
 }
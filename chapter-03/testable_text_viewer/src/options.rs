@@ -0,0 +1,102 @@
+//! Command line of the viewer.
+
+use clap::{Parser, ValueEnum};
+
+/// Whether a feature is used, or decided from the terminal and the documents.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Mode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl Mode {
+    /// Returns `None` for `auto`, to be decided later.
+    pub fn forced(self) -> Option<bool> {
+        match self {
+            Self::Auto => None,
+            Self::Always => Some(true),
+            Self::Never => Some(false),
+        }
+    }
+}
+
+/// Pager and viewer of text files, which can record and replay its events.
+#[derive(Parser)]
+#[command(version)]
+pub struct Options {
+    /// Documents to show, `-` standing for the standard input
+    #[arg(value_name = "FILE", required = true)]
+    pub paths: Vec<String>,
+
+    /// Line shown at the top of the first document, counted from 1
+    #[arg(long, value_name = "LINE", value_parser = clap::value_parser!(u64).range(1..))]
+    pub line: Option<u64>,
+
+    /// Number of columns between tab stops
+    #[arg(long, value_name = "WIDTH", default_value_t = 8,
+        value_parser = clap::value_parser!(u64).range(1..))]
+    pub tab_width: u64,
+
+    /// Wrap the long lines instead of scrolling them horizontally
+    #[arg(long)]
+    pub wrap: bool,
+
+    /// Show the colors of the theme, or the monochrome one
+    #[arg(long, value_name = "WHEN", default_value = "auto")]
+    pub color: Mode,
+
+    /// Color the syntax of the documents, recognized by their extensions
+    #[arg(long, value_name = "WHEN", default_value = "auto")]
+    pub highlight: Mode,
+
+    /// Page the documents, or write them to the output like `cat`
+    #[arg(long, value_name = "WHEN", default_value = "auto")]
+    pub paging: Mode,
+
+    /// Show the numbers of the lines
+    #[arg(long, value_name = "WHEN", default_value = "auto")]
+    pub line_numbers: Mode,
+
+    /// Show the bytes in hexadecimal, as for the binary documents
+    #[arg(long, value_name = "WHEN", default_value = "auto")]
+    pub hex: Mode,
+
+    /// Write the document to the output if it fits in the screen
+    #[arg(long)]
+    pub quit_if_one_screen: bool,
+
+    /// Show a status bar describing the document
+    #[arg(long)]
+    pub status: bool,
+
+    /// Show what is appended to the documents, and reload the replaced ones
+    #[arg(long)]
+    pub follow: bool,
+
+    /// Preset theme, `default` or `monochrome`, or theme file
+    #[arg(long, value_name = "NAME|FILE")]
+    pub theme: Option<String>,
+
+    /// File binding keys to actions
+    #[arg(long, value_name = "FILE")]
+    pub keymap: Option<String>,
+
+    /// File keeping the positions and the bookmarks between sessions
+    #[arg(long, value_name = "FILE")]
+    pub state: Option<String>,
+
+    /// Record the events in a script, which can be replayed
+    #[arg(long, value_name = "FILE")]
+    pub save_events: Option<String>,
+
+    /// Replay the events of a script, `-` standing for the standard input,
+    /// instead of reading them from the terminal
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<String>,
+
+    /// Wait between the replayed events as long as when they were recorded,
+    /// instead of replaying them as fast as possible
+    #[arg(long, requires = "replay")]
+    pub real_time: bool,
+}
@@ -0,0 +1,76 @@
+//! Output of the documents as a stream, like `cat` does, when the viewer does not page them.
+
+use crossterm::{
+    style::{Print, ResetColor, SetForegroundColor},
+    QueueableCommand, Result,
+};
+use std::io::{ErrorKind, Write};
+
+use crate::document::Document;
+use crate::highlight::{Highlighter, TokenColors, TokenKind};
+
+/// Writes the bytes of the document unchanged, or its lines with their own endings,
+/// preceded by their numbers if asked, and with the colors of their tokens if they are highlighted.
+/// The lines which are not valid UTF-8 are written unchanged, without colors.
+/// The rows of the hex view start with their offsets instead of numbers.
+pub fn stream(
+    out: &mut impl Write,
+    document: &Document,
+    highlighter: &Highlighter,
+    token_colors: &TokenColors,
+    line_numbers: bool,
+) -> Result<()> {
+    // Counting the lines is needed only for the width of their numbers.
+    let line_numbers = line_numbers && !document.is_hex();
+    let n_digits = if line_numbers {
+        document.line_count().to_string().len()
+    } else {
+        0
+    };
+    if !document.is_hex() && !line_numbers && !highlighter.is_enabled() {
+        out.write_all(document.bytes())?;
+        return out.flush();
+    }
+    let mut line_index = 0;
+    while document.has_line(line_index) {
+        if line_numbers {
+            out.queue(Print(format!("{:>n_digits$} ", line_index + 1)))?;
+        }
+        if document.is_hex() {
+            out.queue(Print(document.line(line_index)))?
+                .queue(Print(document.line_ending()))?;
+            line_index += 1;
+            continue;
+        }
+        let (line_bytes, ending) = document.line_and_ending_bytes(line_index);
+        match std::str::from_utf8(line_bytes) {
+            Ok(line) if highlighter.is_enabled() => {
+                let mut end = 0;
+                for (range, kind) in highlighter.tokens(document, line_index) {
+                    out.queue(Print(&line[end..range.start]))?;
+                    if kind == TokenKind::Text {
+                        out.queue(Print(&line[range.clone()]))?;
+                    } else {
+                        out.queue(SetForegroundColor(token_colors.color(kind)))?
+                            .queue(Print(&line[range.clone()]))?
+                            .queue(ResetColor)?;
+                    }
+                    end = range.end;
+                }
+                out.queue(Print(&line[end..]))?;
+            }
+            _ => out.write_all(line_bytes)?,
+        }
+        out.write_all(ending)?;
+        line_index += 1;
+    }
+    out.flush()
+}
+
+/// A reader of the stream which stops early, like `head`, is not a failure.
+pub fn ignore_broken_pipe(result: Result<()>) -> Result<()> {
+    match result {
+        Err(error) if error.kind() == ErrorKind::BrokenPipe => Ok(()),
+        _ => result,
+    }
+}
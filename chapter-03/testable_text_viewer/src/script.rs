@@ -0,0 +1,310 @@
+//! Event scripts, replayed by the viewer from the file of the `--replay` option,
+//! or from its standard input if the file is `-`, and written by the `--save-events` option.
+//!
+//! A script has a command per line. A `#` at the start of a line or after a space,
+//! and outside double quotes, starts a comment. The commands are:
+//!
+//! - `cols80 rows24`: the terminal is resized to 80 columns and 24 rows.
+//! - A key: a name preceded by any modifiers among `C` for Ctrl, `A` for Alt and `S` for Shift,
+//!   like `C q`. The names are `up`, `down`, `left`, `right`, `home`, `end`, `page_up`,
+//!   `page_down`, `tab`, `backtab`, `delete`, `insert`, `backspace`, `enter`, `esc`,
+//!   `f1` to `f12`, `space`, `hash`, and any character, alone or between single quotes,
+//!   like `n` or `'#'`.
+//! - `action NAME`: an action of the keymap, like `action page_down`, whatever its key.
+//! - `mouse KIND COLUMN ROW`: a mouse event, where the kind is `press`, `drag`, `release`,
+//!   `scroll_down` or `scroll_up`, and the column and the row are counted from 0.
+//! - `paste "TEXT"`: the characters of the text, typed one after the other.
+//! - `file_changed`: the followed file may have grown, or have been truncated or replaced.
+//! - `wait MS`: the next command came MS milliseconds after the previous one. The delays are
+//!   recorded by `--save-events`, copied from the replayed script if there is one,
+//!   and waited for only if the script is replayed in real time.
+//! - `expect-line ROW "TEXT"`: checks that the screen row, counted from 0,
+//!   shows the text, trailing spaces excepted; the replay fails otherwise.
+//!
+//! In the texts between double quotes, `\"`, `\\`, `\n` and `\t` are escapes.
+//! Any command but `expect-line` may be followed by `*N` to be repeated N times, like `down*20`,
+//! up to 100000 times.
+
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{stdin, BufRead, BufReader, Error, Result, Write};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crate::keymap::{parse_key, Action};
+use crate::model::{Input, Model};
+
+/// Shortest delay between two commands recorded, the shorter ones being unnoticed.
+const MIN_RECORDED_DELAY: Duration = Duration::from_millis(10);
+
+/// Largest number of times a command may be repeated, its inputs being queued at once.
+const MAX_REPEAT_COUNT: usize = 100_000;
+
+/// Reader of an event script.
+pub struct Script {
+    input: Box<dyn BufRead>,
+    /// Whether the `wait` commands are waited for.
+    real_time: bool,
+    line_number: usize,
+    /// Inputs remaining from the last command, when it was repeated or pasted.
+    pending: VecDeque<Input>,
+}
+
+impl Script {
+    /// Reads the script from the file, or from the standard input if the path is `-`.
+    pub fn open(path: &str, real_time: bool) -> Result<Self> {
+        let input: Box<dyn BufRead> = if path == "-" {
+            Box::new(stdin().lock())
+        } else {
+            Box::new(BufReader::new(File::open(path)?))
+        };
+        Ok(Self {
+            input,
+            real_time,
+            line_number: 0,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Returns the next input, checking the screen against the assertions preceding it.
+    pub fn next_input(&mut self, model: &Model) -> Result<Input> {
+        while self.pending.is_empty() {
+            let line = self
+                .next_line()?
+                .ok_or_else(|| Error::other("Unexpected end of input file"))?;
+            self.interpret_line(&line, model)
+                .map_err(|message| self.error(&message))?;
+        }
+        Ok(self.pending.pop_front().unwrap())
+    }
+
+    /// Fails if a command follows the one which terminated the program.
+    pub fn check_end(&mut self) -> Result<()> {
+        if self.pending.is_empty() && self.next_line()?.is_none() {
+            Ok(())
+        } else {
+            Err(self.error("Input file continuing after exit command"))
+        }
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error::other(format!("Event script line {}: {message}", self.line_number))
+    }
+
+    /// Returns the next line not blank once its comment is removed, or `None` at the end.
+    fn next_line(&mut self) -> Result<Option<String>> {
+        loop {
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            self.line_number += 1;
+            let command = strip_comment(&line).trim();
+            if !command.is_empty() {
+                return Ok(Some(command.to_string()));
+            }
+        }
+    }
+
+    fn interpret_line(&mut self, line: &str, model: &Model) -> std::result::Result<(), String> {
+        if let Some(arguments) = line.strip_prefix("expect-line ") {
+            return check_row(arguments, model);
+        }
+        if let Some(delay) = line.strip_prefix("wait ") {
+            let delay = delay
+                .parse()
+                .map_err(|_| format!("Invalid delay: {delay}"))?;
+            model
+                .record_wait(delay)
+                .map_err(|error| error.to_string())?;
+            if self.real_time {
+                sleep(Duration::from_millis(delay));
+            }
+            return Ok(());
+        }
+        let (command, count) = match line.rsplit_once('*') {
+            Some((command, count)) if !command.is_empty() => match count.parse() {
+                Ok(count) => (command.trim_end(), count),
+                Err(_) => (line, 1),
+            },
+            _ => (line, 1),
+        };
+        if count > MAX_REPEAT_COUNT {
+            return Err("repeat count too large".to_string());
+        }
+        let inputs = parse_command(command)?;
+        for _ in 0..count {
+            self.pending.extend(inputs.iter().cloned());
+        }
+        Ok(())
+    }
+}
+
+/// Writer of an event script, recording the delays between the commands.
+pub struct Recorder {
+    file: File,
+    /// Whether the delays are measured, rather than copied from the replayed script,
+    /// so that recording a replay gives the same script however fast it is.
+    measuring_delays: bool,
+    last_command_time: Cell<Instant>,
+}
+
+impl Recorder {
+    pub fn create(path: &str, measuring_delays: bool) -> Result<Self> {
+        Ok(Self {
+            file: File::create(path)?,
+            measuring_delays,
+            last_command_time: Cell::new(Instant::now()),
+        })
+    }
+
+    /// Writes the command, after a `wait` command if it comes late enough after the previous one.
+    pub fn write(&self, command: &str) -> Result<()> {
+        let now = Instant::now();
+        let delay = now - self.last_command_time.replace(now);
+        let mut file = &self.file;
+        if self.measuring_delays && delay >= MIN_RECORDED_DELAY {
+            writeln!(file, "wait {}", delay.as_millis())?;
+        }
+        writeln!(file, "{command}")
+    }
+
+    /// Writes a `wait` command of the replayed script.
+    pub fn write_wait(&self, delay: u64) -> Result<()> {
+        if self.measuring_delays {
+            return Ok(());
+        }
+        writeln!(&self.file, "wait {delay}")
+    }
+}
+
+fn parse_command(command: &str) -> std::result::Result<Vec<Input>, String> {
+    if command == "file_changed" {
+        return Ok(vec![Input::FileChange]);
+    }
+    if let Some(name) = command.strip_prefix("action ") {
+        let action = Action::from_name(name).ok_or_else(|| format!("Unknown action: {name}"))?;
+        return Ok(vec![Input::Action(action)]);
+    }
+    if let Some(arguments) = command.strip_prefix("mouse ") {
+        return Ok(vec![Input::Event(Event::Mouse(parse_mouse(arguments)?))]);
+    }
+    if let Some(arguments) = command.strip_prefix("paste ") {
+        let text = parse_whole_string(arguments)?;
+        return Ok(text
+            .chars()
+            .map(|c| {
+                let key_code = match c {
+                    '\n' => KeyCode::Enter,
+                    '\t' => KeyCode::Tab,
+                    _ => KeyCode::Char(c),
+                };
+                Input::Event(Event::Key(KeyEvent::new(key_code, KeyModifiers::empty())))
+            })
+            .collect());
+    }
+    if let Some(size) = command.strip_prefix("cols") {
+        let (cols, rows) = size
+            .split_once(" rows")
+            .and_then(|(cols, rows)| Some((cols.parse().ok()?, rows.parse().ok()?)))
+            .ok_or_else(|| format!("Invalid size: {command}"))?;
+        return Ok(vec![Input::Event(Event::Resize(cols, rows))]);
+    }
+    Ok(vec![Input::Event(Event::Key(parse_key(command)?))])
+}
+
+/// Parses a mouse event written like `drag 12 3`, where 12 is the column and 3 the row.
+fn parse_mouse(arguments: &str) -> std::result::Result<MouseEvent, String> {
+    let words: Vec<&str> = arguments.split(' ').collect();
+    let kind = match words[0] {
+        "press" => MouseEventKind::Down(MouseButton::Left),
+        "drag" => MouseEventKind::Drag(MouseButton::Left),
+        "release" => MouseEventKind::Up(MouseButton::Left),
+        "scroll_down" => MouseEventKind::ScrollDown,
+        "scroll_up" => MouseEventKind::ScrollUp,
+        kind => return Err(format!("Unknown mouse event: {kind}")),
+    };
+    let (Some(column), Some(row), 3) = (
+        words.get(1).and_then(|word| word.parse().ok()),
+        words.get(2).and_then(|word| word.parse().ok()),
+        words.len(),
+    ) else {
+        return Err(format!("Invalid mouse position: {arguments}"));
+    };
+    Ok(MouseEvent {
+        kind,
+        column,
+        row,
+        modifiers: KeyModifiers::empty(),
+    })
+}
+
+/// Checks an assertion written like `3 "text"`.
+fn check_row(arguments: &str, model: &Model) -> std::result::Result<(), String> {
+    let (row, text) = arguments.split_once(' ').ok_or("Missing row or text")?;
+    let row: usize = row.parse().map_err(|_| format!("Invalid row: {row}"))?;
+    let expected = parse_whole_string(text)?;
+    let shown = model
+        .screen_row(row)
+        .ok_or_else(|| format!("No row {row} on the screen"))?;
+    if shown.trim_end() != expected.trim_end() {
+        return Err(format!(
+            "Row {row} shows {:?} instead of {:?}",
+            shown.trim_end(),
+            expected.trim_end()
+        ));
+    }
+    Ok(())
+}
+
+/// Parses a text between double quotes, with nothing after it.
+fn parse_whole_string(text: &str) -> std::result::Result<String, String> {
+    let mut chars = text
+        .strip_prefix('"')
+        .ok_or("Missing opening quote")?
+        .chars();
+    let mut string = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if chars.as_str().trim().is_empty() => return Ok(string),
+            '"' => return Err(format!("Unexpected text after quote: {}", chars.as_str())),
+            '\\' => string.push(match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some(c @ ('"' | '\\')) => c,
+                _ => return Err("Invalid escape sequence".to_string()),
+            }),
+            _ => string.push(c),
+        }
+    }
+    Err("Missing closing quote".to_string())
+}
+
+/// Removes the comment, starting with a `#` at the start of the line or after a space,
+/// outside double quotes.
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    let mut after_space = true;
+    for (index, c) in line.char_indices() {
+        if in_quotes {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_quotes = false;
+            }
+        } else if c == '"' {
+            in_quotes = true;
+        } else if c == '#' && after_space {
+            return &line[..index];
+        }
+        after_space = c.is_whitespace();
+    }
+    line
+}
@@ -0,0 +1,91 @@
+use regex::{Regex, RegexBuilder};
+use std::ops::Range;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+impl Direction {
+    pub fn reversed(self) -> Self {
+        match self {
+            Direction::Forward => Direction::Backward,
+            Direction::Backward => Direction::Forward,
+        }
+    }
+}
+
+/// A pattern, as typed in the prompt, and how it is interpreted.
+#[derive(Clone)]
+pub struct Search {
+    pub pattern: String,
+    pub direction: Direction,
+    pub is_regex: bool,
+    pub ignore_case: bool,
+    regex: Option<Regex>,
+}
+
+impl Search {
+    pub fn new(direction: Direction) -> Self {
+        Self {
+            pattern: String::new(),
+            direction,
+            is_regex: false,
+            ignore_case: false,
+            regex: None,
+        }
+    }
+
+    /// Must be called after changing the pattern or the flags.
+    pub fn compile(&mut self) {
+        self.regex = if self.pattern.is_empty() {
+            None
+        } else {
+            RegexBuilder::new(&if self.is_regex {
+                self.pattern.clone()
+            } else {
+                regex::escape(&self.pattern)
+            })
+            .case_insensitive(self.ignore_case)
+            .build()
+            .ok()
+        };
+    }
+
+    /// An empty pattern is valid, but it matches nothing.
+    pub fn is_valid(&self) -> bool {
+        self.pattern.is_empty() || self.regex.is_some()
+    }
+
+    /// Returns the byte ranges of the non-empty matches in the line.
+    pub fn find_in_line(&self, line: &str) -> Vec<Range<usize>> {
+        match &self.regex {
+            Some(regex) => regex
+                .find_iter(line)
+                .filter(|found| !found.is_empty())
+                .map(|found| found.range())
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Returns the text shown in the prompt line.
+    pub fn prompt(&self) -> String {
+        format!(
+            "{}{}{}{}{}",
+            match self.direction {
+                Direction::Forward => '/',
+                Direction::Backward => '?',
+            },
+            self.pattern,
+            if self.is_regex { "  [regex]" } else { "" },
+            if self.ignore_case { "  [any case]" } else { "" },
+            if self.is_valid() {
+                ""
+            } else {
+                "  (invalid pattern)"
+            },
+        )
+    }
+}
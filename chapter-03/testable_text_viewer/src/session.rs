@@ -0,0 +1,159 @@
+//! The places remembered in a document, and the state file keeping them between sessions.
+//!
+//! The state file has a line per document, like `120 4 1:96,3:2000 /home/me/big.log`,
+//! giving the index of the first line shown, the index of the first column shown,
+//! the bookmarks as digits with line indexes, or `-` if there is none, and the path.
+
+use std::collections::BTreeMap;
+use std::env::var_os;
+use std::fs::{self, canonicalize};
+use std::io::{ErrorKind, Result};
+use std::path::PathBuf;
+
+/// Greatest number of positions kept to go back to.
+const MAX_JUMPS: usize = 100;
+
+/// The bookmarks of a document, and the positions before and after the jumps made in it.
+#[derive(Default)]
+pub struct Places {
+    /// Index of the first line shown when each bookmark was set, by digit.
+    pub bookmarks: BTreeMap<u8, usize>,
+    back: Vec<usize>,
+    forward: Vec<usize>,
+}
+
+impl Places {
+    /// Records the index of the first line shown before a jump, forgetting the jumps undone.
+    pub fn record_jump(&mut self, line_index: usize) {
+        self.forward.clear();
+        if self.back.last() != Some(&line_index) {
+            self.back.push(line_index);
+        }
+        if self.back.len() > MAX_JUMPS {
+            self.back.remove(0);
+        }
+    }
+
+    /// Returns the position before the last jump, remembering the current one to go forward.
+    pub fn go_back(&mut self, line_index: usize) -> Option<usize> {
+        let previous = self.back.pop()?;
+        self.forward.push(line_index);
+        Some(previous)
+    }
+
+    /// Returns the position before the last jump back, remembering the current one to go back.
+    pub fn go_forward(&mut self, line_index: usize) -> Option<usize> {
+        let next = self.forward.pop()?;
+        self.back.push(line_index);
+        Some(next)
+    }
+}
+
+/// What is remembered of a document between sessions.
+pub struct Entry {
+    pub first_line_index: usize,
+    pub first_column_index: usize,
+    pub bookmarks: BTreeMap<u8, usize>,
+}
+
+pub struct StateFile {
+    path: PathBuf,
+    /// Entries by canonical path of the documents.
+    entries: BTreeMap<String, Entry>,
+}
+
+impl StateFile {
+    /// Returns `$XDG_STATE_HOME/testable_text_viewer/state`,
+    /// or else `$HOME/.local/state/testable_text_viewer/state`.
+    pub fn default_path() -> Option<PathBuf> {
+        let state_home = match var_os("XDG_STATE_HOME") {
+            Some(state_home) if !state_home.is_empty() => PathBuf::from(state_home),
+            _ => PathBuf::from(var_os("HOME")?).join(".local/state"),
+        };
+        Some(state_home.join("testable_text_viewer/state"))
+    }
+
+    /// Reads the state file, which may not exist yet. Invalid lines are ignored.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error),
+        };
+        let entries = text.lines().filter_map(parse_entry).collect();
+        Ok(Self { path, entries })
+    }
+
+    pub fn entry(&self, document_path: &str) -> Option<&Entry> {
+        self.entries.get(&key(document_path)?)
+    }
+
+    /// Remembers the entry of the document, unless it is the standard input.
+    pub fn set_entry(&mut self, document_path: &str, entry: Entry) {
+        if let Some(key) = key(document_path) {
+            self.entries.insert(key, entry);
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let mut text = String::new();
+        for (document_path, entry) in &self.entries {
+            let bookmarks: Vec<String> = entry
+                .bookmarks
+                .iter()
+                .map(|(digit, line_index)| format!("{digit}:{line_index}"))
+                .collect();
+            let bookmarks = if bookmarks.is_empty() {
+                "-".to_string()
+            } else {
+                bookmarks.join(",")
+            };
+            text += &format!(
+                "{} {} {bookmarks} {document_path}\n",
+                entry.first_line_index, entry.first_column_index
+            );
+        }
+        fs::write(&self.path, text)
+    }
+}
+
+/// Returns the canonical path of the document, under which its entry is kept,
+/// or `None` for the standard input and the paths which cannot be written on a line.
+fn key(document_path: &str) -> Option<String> {
+    if document_path == "-" {
+        return None;
+    }
+    let path = canonicalize(document_path)
+        .ok()
+        .and_then(|path| path.to_str().map(str::to_string))
+        .unwrap_or_else(|| document_path.to_string());
+    (!path.contains('\n')).then_some(path)
+}
+
+fn parse_entry(line: &str) -> Option<(String, Entry)> {
+    let mut fields = line.splitn(4, ' ');
+    let first_line_index = fields.next()?.parse().ok()?;
+    let first_column_index = fields.next()?.parse().ok()?;
+    let bookmarks = match fields.next()? {
+        "-" => BTreeMap::new(),
+        bookmarks => bookmarks
+            .split(',')
+            .map(|bookmark| {
+                let (digit, line_index) = bookmark.split_once(':')?;
+                Some((digit.parse().ok()?, line_index.parse().ok()?))
+            })
+            .collect::<Option<_>>()?,
+    };
+    let document_path = fields.next()?.to_string();
+    Some((
+        document_path,
+        Entry {
+            first_line_index,
+            first_column_index,
+            bookmarks,
+        },
+    ))
+}
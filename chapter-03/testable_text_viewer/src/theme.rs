@@ -0,0 +1,131 @@
+use crossterm::style::Color;
+use std::fs::read_to_string;
+use std::io::{Error, Result};
+
+use crate::highlight::{TokenColors, TokenKind};
+
+/// Foreground and background colors.
+pub type Style = (Color, Color);
+
+/// The colors of everything the viewer shows, named as in the theme files.
+pub struct Theme {
+    pub gutter: Style,
+    /// The text out of any token, the syntax colors being shown on its background.
+    pub text: Style,
+    /// The dashes facing a line of the other pane, in a diff.
+    pub filler: Style,
+    pub cursor: Style,
+    pub selection: Style,
+    pub matched: Style,
+    /// The changed lines of a diff, in the first pane and in the second one.
+    pub removed: Style,
+    pub added: Style,
+    pub separator: Style,
+    pub status: Style,
+    pub message: Style,
+    pub error: Style,
+    pub tokens: TokenColors,
+}
+
+impl Theme {
+    /// Returns the `default` theme, or the `monochrome` one, made of black, white and greys.
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Self {
+                gutter: (Color::Cyan, Color::DarkBlue),
+                text: (Color::Black, Color::Grey),
+                filler: (Color::DarkGrey, Color::Grey),
+                cursor: (Color::Black, Color::White),
+                selection: (Color::White, Color::DarkCyan),
+                matched: (Color::Black, Color::Yellow),
+                removed: (Color::White, Color::DarkRed),
+                added: (Color::White, Color::DarkGreen),
+                separator: (Color::Cyan, Color::DarkBlue),
+                status: (Color::Black, Color::Cyan),
+                message: (Color::White, Color::Black),
+                error: (Color::White, Color::DarkRed),
+                tokens: TokenColors::default(),
+            }),
+            "monochrome" => Some(Self {
+                gutter: (Color::Black, Color::Grey),
+                text: (Color::Black, Color::White),
+                filler: (Color::Grey, Color::White),
+                cursor: (Color::White, Color::Black),
+                selection: (Color::White, Color::DarkGrey),
+                matched: (Color::Black, Color::Grey),
+                removed: (Color::White, Color::DarkGrey),
+                added: (Color::White, Color::DarkGrey),
+                separator: (Color::Black, Color::Grey),
+                status: (Color::White, Color::Black),
+                message: (Color::Black, Color::White),
+                error: (Color::White, Color::Black),
+                tokens: TokenColors::monochrome(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Reads a theme file, made of lines like `preset monochrome`, `set status white dark_blue`
+    /// or `set comment dark_green`, applied in order to the default theme.
+    /// The elements of the text take a foreground and a background color, the token kinds
+    /// only a foreground one. Comments start with `#`.
+    pub fn load(path: &str) -> Result<Self> {
+        let mut theme = Self::preset("default").unwrap();
+        for (line_index, line) in read_to_string(path)?.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let error =
+                |message: &str| Error::other(format!("{path}:{}: {message}", line_index + 1));
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[..] {
+                [] => {}
+                ["preset", name] => {
+                    theme = Self::preset(name).ok_or_else(|| error("Unknown preset"))?;
+                }
+                ["set", element, ref colors @ ..] => {
+                    let colors = colors
+                        .iter()
+                        .map(|color| Color::try_from(*color))
+                        .collect::<std::result::Result<Vec<Color>, ()>>()
+                        .map_err(|()| error("Unknown color"))?;
+                    if let Some(kind) = TokenKind::from_name(element) {
+                        let [foreground] = colors[..] else {
+                            return Err(error("Expected a foreground color"));
+                        };
+                        *theme.tokens.color_mut(kind) = foreground;
+                        continue;
+                    }
+                    let style = theme
+                        .style_mut(element)
+                        .ok_or_else(|| error("Unknown element"))?;
+                    let [foreground, background] = colors[..] else {
+                        return Err(error("Expected a foreground and a background color"));
+                    };
+                    *style = (foreground, background);
+                    if element == "text" {
+                        theme.tokens.text = foreground;
+                    }
+                }
+                _ => return Err(error("Unknown command")),
+            }
+        }
+        Ok(theme)
+    }
+
+    fn style_mut(&mut self, element: &str) -> Option<&mut Style> {
+        Some(match element {
+            "gutter" => &mut self.gutter,
+            "text" => &mut self.text,
+            "filler" => &mut self.filler,
+            "cursor" => &mut self.cursor,
+            "selection" => &mut self.selection,
+            "match" => &mut self.matched,
+            "removed" => &mut self.removed,
+            "added" => &mut self.added,
+            "separator" => &mut self.separator,
+            "status" => &mut self.status,
+            "message" => &mut self.message,
+            "error" => &mut self.error,
+            _ => return None,
+        })
+    }
+}
@@ -0,0 +1,401 @@
+//! A headless terminal, interpreting the output of the viewer into grids of cells,
+//! so that the tests compare readable screens instead of escape sequences.
+//!
+//! Every refresh of the viewer ends by hiding the cursor, which is when a frame is captured.
+//! As the output does not tell the size of the terminal, and the viewer paints every cell,
+//! a frame is the area painted since the previous one.
+
+use unicode_width::UnicodeWidthChar;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Color {
+    Default,
+    Ansi(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    fn name(self) -> String {
+        const ANSI_NAMES: [&str; 16] = [
+            "black",
+            "dark_red",
+            "dark_green",
+            "dark_yellow",
+            "dark_blue",
+            "dark_magenta",
+            "dark_cyan",
+            "grey",
+            "dark_grey",
+            "red",
+            "green",
+            "yellow",
+            "blue",
+            "magenta",
+            "cyan",
+            "white",
+        ];
+        match self {
+            Color::Default => "default".to_string(),
+            Color::Ansi(index) if index < 16 => ANSI_NAMES[index as usize].to_string(),
+            Color::Ansi(index) => format!("ansi{index}"),
+            Color::Rgb(red, green, blue) => format!("#{red:02x}{green:02x}{blue:02x}"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Cell {
+    /// The character with its combining marks, or nothing for the right half of a wide one.
+    pub text: String,
+    pub foreground: Color,
+    pub background: Color,
+}
+
+impl Cell {
+    fn blank(foreground: Color, background: Color) -> Self {
+        Self {
+            text: " ".to_string(),
+            foreground,
+            background,
+        }
+    }
+}
+
+/// The screen shown by a refresh.
+pub struct Frame {
+    pub rows: Vec<Vec<Cell>>,
+    /// Texts copied to the clipboard since the previous frame.
+    pub clipboard: Vec<String>,
+}
+
+impl Frame {
+    pub fn row_text(&self, row: usize) -> String {
+        self.rows[row]
+            .iter()
+            .map(|cell| cell.text.as_str())
+            .collect()
+    }
+
+    /// Shows every row between bars, followed by its colors, as runs of columns
+    /// of the same style, like `a3 b27`, the styles being described after the rows.
+    fn dump(&self, index: usize) -> String {
+        let mut styles: Vec<(Color, Color)> = vec![];
+        let mut dump = format!("Frame {index}\n");
+        for (row_index, row) in self.rows.iter().enumerate() {
+            let mut runs: Vec<(usize, usize)> = vec![];
+            for cell in row {
+                let style = (cell.foreground, cell.background);
+                let style_index = match styles.iter().position(|s| *s == style) {
+                    Some(style_index) => style_index,
+                    None => {
+                        styles.push(style);
+                        styles.len() - 1
+                    }
+                };
+                match runs.last_mut() {
+                    Some((run_style, length)) if *run_style == style_index => *length += 1,
+                    _ => runs.push((style_index, 1)),
+                }
+            }
+            let runs: Vec<String> = runs
+                .iter()
+                .map(|(style_index, length)| format!("{}{length}", style_letter(*style_index)))
+                .collect();
+            dump += &format!("|{}| {}\n", self.row_text(row_index), runs.join(" "));
+        }
+        let legend: Vec<String> = styles
+            .iter()
+            .enumerate()
+            .map(|(style_index, (foreground, background))| {
+                format!(
+                    "{}: {} on {}",
+                    style_letter(style_index),
+                    foreground.name(),
+                    background.name()
+                )
+            })
+            .collect();
+        dump += &format!("Styles: {}\n", legend.join(", "));
+        for text in &self.clipboard {
+            dump += &format!("Clipboard: {text:?}\n");
+        }
+        dump
+    }
+}
+
+fn style_letter(style_index: usize) -> char {
+    char::from(b'a' + style_index as u8)
+}
+
+pub struct Screen {
+    grid: Vec<Vec<Cell>>,
+    cursor: (usize, usize),
+    foreground: Color,
+    background: Color,
+    /// Number of rows and columns painted since the last frame.
+    painted: (usize, usize),
+    clipboard: Vec<String>,
+    alternate: bool,
+    cursor_visible: bool,
+    pub frames: Vec<Frame>,
+}
+
+impl Screen {
+    pub fn from_output(output: &str) -> Self {
+        let mut screen = Self {
+            grid: vec![],
+            cursor: (0, 0),
+            foreground: Color::Default,
+            background: Color::Default,
+            painted: (0, 0),
+            clipboard: vec![],
+            alternate: false,
+            cursor_visible: true,
+            frames: vec![],
+        };
+        let mut chars = output.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '\u{1b}' {
+                screen.print(c);
+                continue;
+            }
+            match chars.next() {
+                Some('[') => {
+                    let mut parameters = String::new();
+                    for c in chars.by_ref() {
+                        if ('\u{40}'..='\u{7e}').contains(&c) {
+                            screen.control(&parameters, c);
+                            break;
+                        }
+                        parameters.push(c);
+                    }
+                }
+                Some(']') => {
+                    let mut command = String::new();
+                    while let Some(c) = chars.next() {
+                        if c == '\u{7}' {
+                            break;
+                        }
+                        if c == '\u{1b}' && chars.peek() == Some(&'\\') {
+                            chars.next();
+                            break;
+                        }
+                        command.push(c);
+                    }
+                    if let Some(encoded) = command.strip_prefix("52;c;") {
+                        screen.clipboard.push(decode_base64(encoded));
+                    }
+                }
+                _ => {}
+            }
+        }
+        screen
+    }
+
+    /// Shows every frame, and then whether the terminal was restored when the program ended.
+    pub fn dump(&self) -> String {
+        let mut dump = String::new();
+        for (index, frame) in self.frames.iter().enumerate() {
+            dump += &frame.dump(index + 1);
+        }
+        dump += &format!(
+            "End: {} screen, cursor {}\n",
+            if self.alternate { "alternate" } else { "main" },
+            if self.cursor_visible {
+                "shown"
+            } else {
+                "hidden"
+            }
+        );
+        dump
+    }
+
+    fn print(&mut self, c: char) {
+        let (row, column) = self.cursor;
+        match c.width() {
+            // A combining mark goes with the preceding character.
+            Some(0) if column > 0 => self.cell(row, column - 1).text.push(c),
+            None | Some(0) => {}
+            Some(width) => {
+                let (foreground, background) = (self.foreground, self.background);
+                *self.cell(row, column) = Cell {
+                    text: c.to_string(),
+                    foreground,
+                    background,
+                };
+                for continuation_column in column + 1..column + width {
+                    *self.cell(row, continuation_column) = Cell {
+                        text: String::new(),
+                        foreground,
+                        background,
+                    };
+                }
+                self.cursor.1 += width;
+            }
+        }
+    }
+
+    /// Returns the cell, extending the grid if needed.
+    fn cell(&mut self, row: usize, column: usize) -> &mut Cell {
+        if self.grid.len() <= row {
+            self.grid.resize(row + 1, vec![]);
+        }
+        let cells = &mut self.grid[row];
+        if cells.len() <= column {
+            cells.resize(column + 1, Cell::blank(Color::Default, Color::Default));
+        }
+        self.painted = (self.painted.0.max(row + 1), self.painted.1.max(column + 1));
+        &mut cells[column]
+    }
+
+    /// Interprets a control sequence, made of `ESC [`, the parameters and the final character.
+    fn control(&mut self, parameters: &str, final_char: char) {
+        let numbers: Vec<u16> = parameters
+            .trim_start_matches('?')
+            .split(';')
+            .map(|number| number.parse().unwrap_or(0))
+            .collect();
+        match (final_char, parameters.starts_with('?')) {
+            ('H', false) => {
+                let row = numbers.first().copied().unwrap_or(1).max(1);
+                let column = numbers.get(1).copied().unwrap_or(1).max(1);
+                self.cursor = (row as usize - 1, column as usize - 1);
+            }
+            ('m', false) => self.select_graphic_rendition(&numbers),
+            ('J', false) if numbers[0] == 2 => {
+                let blank = Cell::blank(self.foreground, self.background);
+                for cells in &mut self.grid {
+                    cells.fill(blank.clone());
+                }
+            }
+            ('h' | 'l', true) => {
+                let enabled = final_char == 'h';
+                match numbers[0] {
+                    25 => {
+                        self.cursor_visible = enabled;
+                        if !enabled {
+                            self.capture_frame();
+                        }
+                    }
+                    1049 => {
+                        self.alternate = enabled;
+                        self.grid.clear();
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, numbers: &[u16]) {
+        let mut numbers = numbers.iter().copied();
+        while let Some(number) = numbers.next() {
+            match number {
+                0 => (self.foreground, self.background) = (Color::Default, Color::Default),
+                30..=37 => self.foreground = Color::Ansi(number as u8 - 30),
+                90..=97 => self.foreground = Color::Ansi(number as u8 - 90 + 8),
+                40..=47 => self.background = Color::Ansi(number as u8 - 40),
+                100..=107 => self.background = Color::Ansi(number as u8 - 100 + 8),
+                39 => self.foreground = Color::Default,
+                49 => self.background = Color::Default,
+                38 | 48 => {
+                    let color = match numbers.next() {
+                        Some(5) => Color::Ansi(numbers.next().unwrap_or(0) as u8),
+                        Some(2) => {
+                            let mut component = || numbers.next().unwrap_or(0) as u8;
+                            Color::Rgb(component(), component(), component())
+                        }
+                        _ => Color::Default,
+                    };
+                    if number == 38 {
+                        self.foreground = color;
+                    } else {
+                        self.background = color;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn capture_frame(&mut self) {
+        // A refresh painting nothing shows the same area as the previous one.
+        let (n_rows, n_cols) = match (self.painted, self.frames.last()) {
+            ((0, 0), Some(frame)) => (frame.rows.len(), frame.rows[0].len()),
+            _ => self.painted,
+        };
+        let rows = (0..n_rows)
+            .map(|row| {
+                (0..n_cols)
+                    .map(|column| self.cell(row, column).clone())
+                    .collect()
+            })
+            .collect();
+        self.frames.push(Frame {
+            rows,
+            clipboard: std::mem::take(&mut self.clipboard),
+        });
+        self.painted = (0, 0);
+    }
+}
+
+fn decode_base64(encoded: &str) -> String {
+    const DIGITS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut bytes = vec![];
+    let mut group = 0u32;
+    let mut n_bits = 0;
+    for digit in encoded.bytes().filter(|digit| *digit != b'=') {
+        let value = DIGITS.iter().position(|d| *d == digit).unwrap_or(0) as u32;
+        group = group << 6 | value;
+        n_bits += 6;
+        if n_bits >= 8 {
+            n_bits -= 8;
+            bytes.push((group >> n_bits) as u8);
+            group &= (1 << n_bits) - 1;
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Compares the screens with the expected ones, saved in the file,
+/// showing the first difference with the frame where it is.
+/// If the `UPDATE_SCREENS` environment variable is set, the file is written instead.
+pub fn check_screens(actual: &str, expected_path: &str) {
+    if std::env::var_os("UPDATE_SCREENS").is_some() {
+        std::fs::write(expected_path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(expected_path)
+        .unwrap_or_else(|_| panic!("Failed to read `{expected_path}`."));
+    if actual == expected {
+        return;
+    }
+    let actual_lines: Vec<&str> = actual.lines().collect();
+    let expected_lines: Vec<&str> = expected.lines().collect();
+    let first_difference = (0..)
+        .find(|index| actual_lines.get(*index) != expected_lines.get(*index))
+        .unwrap();
+    let frame_start = actual_lines[..first_difference.min(actual_lines.len())]
+        .iter()
+        .rposition(|line| line.starts_with("Frame "))
+        .unwrap_or(0);
+    let show = |lines: &[&str]| {
+        lines
+            .iter()
+            .enumerate()
+            .take(first_difference + 4)
+            .skip(frame_start)
+            .map(|(index, line)| {
+                let marker = if index == first_difference { '>' } else { ' ' };
+                format!("{marker} {line}\n")
+            })
+            .collect::<String>()
+    };
+    panic!(
+        "The screens differ from `{expected_path}` at line {}.\nExpected:\n{}Actual:\n{}",
+        first_difference + 1,
+        show(&expected_lines),
+        show(&actual_lines)
+    );
+}
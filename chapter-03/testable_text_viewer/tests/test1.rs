@@ -17,31 +17,533 @@ fn get_program_path() -> String {
 
 use std::io::Write;
 
+mod screen;
+use screen::{check_screens, Screen};
+
 #[test]
 // Prepare this test by running, from the folder ‘tests’:
-// cargo run test1.data.txt --save-events test1.in.txt | tee test1.out.txt
+// cargo run test1.data.txt --save-events test1.in.txt
+// and then write the screens it shows by running:
+// UPDATE_SCREENS=1 cargo test
 fn redirect_input_output_error() {
+    check_replay(
+        "tests/test1.data.txt",
+        &[],
+        include_bytes!("test1.in.txt"),
+        "tests/test1.screens.txt",
+    );
+}
+
+#[test]
+// Long lines, scrolled horizontally, and then wrapped and paged.
+fn horizontal_scrolling_and_wrapping() {
+    check_replay(
+        "tests/test2.data.txt",
+        &[],
+        include_bytes!("test2.in.txt"),
+        "tests/test2.screens.txt",
+    );
+}
+
+#[test]
+// Searching forward and backward, with the case-insensitive and regex modes.
+fn incremental_search() {
+    check_replay(
+        "tests/test3.data.txt",
+        &[],
+        include_bytes!("test3.in.txt"),
+        "tests/test3.screens.txt",
+    );
+}
+
+#[test]
+// Wide characters, combining marks, tab stops, CRLF line endings and control characters.
+fn display_width_layout() {
+    check_replay(
+        "tests/test4.data.txt",
+        &["--tab-width", "4"],
+        include_bytes!("test4.in.txt"),
+        "tests/test4.screens.txt",
+    );
+}
+
+#[test]
+// Invalid UTF-8 sequences, and a last line without newline.
+fn lossy_decoding() {
+    check_replay(
+        "tests/test5.data.txt",
+        &[],
+        include_bytes!("test5.in.txt"),
+        "tests/test5.screens.txt",
+    );
+}
+
+#[test]
+// Following a log file which grows, is rotated, and then truncated.
+// Before every `file_changed` command of the script, the next change is applied to a copy of the file.
+fn follow_mode() {
+    use std::fs::{self, OpenOptions};
+    let log_path = std::env::temp_dir().join(format!("test6-{}.log", std::process::id()));
+    fs::copy("tests/test6.data.txt", &log_path).unwrap();
+    let append = |text: &str| {
+        OpenOptions::new()
+            .append(true)
+            .open(&log_path)
+            .unwrap()
+            .write_all(text.as_bytes())
+            .unwrap()
+    };
+    let changes: [&dyn Fn(); 6] = [
+        &|| append("10:00:07 event 7\n10:00:08 event 8\n"),
+        &|| append("10:00:09 ev"),
+        &|| append("ent 9\n10:00:10 event 10\n"),
+        &|| {
+            let rotated_path = log_path.with_extension("log.1");
+            fs::rename(&log_path, &rotated_path).unwrap();
+            fs::write(&log_path, "10:01:00 rotated\n").unwrap();
+            fs::remove_file(rotated_path).unwrap();
+        },
+        &|| append("10:01:01 event 11\n"),
+        &|| fs::write(&log_path, "10:02:00 truncated\n").unwrap(),
+    ];
+    let output = replay_with_changes(
+        log_path.to_str().unwrap(),
+        include_str!("test6.in.txt"),
+        &changes,
+    );
+    fs::remove_file(&log_path).unwrap();
+    check_screens(
+        &Screen::from_output(&output).dump(),
+        "tests/test6.screens.txt",
+    );
+}
+
+#[test]
+// TOML syntax highlighting, forced although the output is not a terminal.
+fn syntax_highlighting() {
+    check_replay(
+        "tests/test7.data.toml",
+        &["--highlight", "always"],
+        include_bytes!("test7.in.txt"),
+        "tests/test7.screens.txt",
+    );
+}
+
+#[test]
+// A keymap file based on the less preset, actions named in the script,
+// and the command line going to a line, hiding the line numbers and quitting.
+fn keymap_and_command_line() {
+    check_replay(
+        "tests/test8.data.txt",
+        &["--keymap", "tests/test8.keymap.txt"],
+        include_bytes!("test8.in.txt"),
+        "tests/test8.screens.txt",
+    );
+}
+
+#[test]
+// Wheel scrolling, a line selected by its number, and a selection dragged over several lines,
+// both copied to the clipboard by escape sequences.
+fn mouse_selection() {
+    check_replay(
+        "tests/test9.data.txt",
+        &[],
+        include_bytes!("test9.in.txt"),
+        "tests/test9.screens.txt",
+    );
+}
+
+#[test]
+// Repeated commands, keys with modifiers, pasted text, and assertions on the screen rows.
+fn script_language() {
+    check_replay(
+        "tests/test10.data.txt",
+        &[],
+        include_bytes!("test10.in.txt"),
+        "tests/test10.screens.txt",
+    );
+}
+
+#[test]
+// Two documents listed and switched, split with bound scrolling, and compared in a diff.
+fn split_panes_and_diff() {
+    check_replay(
+        "tests/test11.data.txt",
+        &["tests/test11.new.txt"],
+        include_bytes!("test11.in.txt"),
+        "tests/test11.screens.txt",
+    );
+}
+
+#[test]
+// Edit mode, saving the edited file, and quitting without saving the later changes.
+// The file is edited in a folder of this process, where the program runs to show a short path.
+fn editing() {
+    use std::fs;
+    let dir = std::env::temp_dir().join(format!("test12-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let edited_path = dir.join("test12.edited.txt");
+    fs::copy("tests/test12.data.txt", &edited_path).unwrap();
+    let output = run_replay_in(
+        &dir,
+        "test12.edited.txt",
+        &[],
+        include_bytes!("test12.in.txt"),
+    );
+    let edited_text = fs::read_to_string(&edited_path).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    check_output(&output, "tests/test12.screens.txt");
+    assert_eq!(
+        edited_text,
+        "First line edited\n2nd line\nThird line\nFourth line\nFifth line\n"
+    );
+}
+
+#[test]
+// Saving keeps the line endings, even mixed or missing at the end,
+// and a text which is not valid UTF-8 cannot be edited.
+fn editing_keeps_bytes() {
+    let edited_path = std::env::temp_dir().join(format!("test12-{}.txt", std::process::id()));
+    let edited_path = edited_path.to_str().unwrap();
+    std::fs::write(edited_path, "one\r\ntwo\nthree").unwrap();
+    let output = run_replay(
+        edited_path,
+        &[],
+        b"cols40 rows4\ni\npaste \"X\"\ndown*2\nend\nenter\nC s\nesc\naction quit\n",
+    );
+    assert_eq!(std::str::from_utf8(&output.stderr).unwrap(), "");
+    assert_eq!(
+        std::fs::read(edited_path).unwrap(),
+        b"Xone\r\ntwo\nthree\r\n"
+    );
+    std::fs::write(edited_path, b"one\n\xff\n").unwrap();
+    let output = run_replay(
+        edited_path,
+        &[],
+        b"cols40 rows4\ni\nexpect-line 3 \"No editing: line 2 is not valid UTF-8\"\nesc\n",
+    );
+    std::fs::remove_file(edited_path).unwrap();
+    assert_eq!(std::str::from_utf8(&output.stderr).unwrap(), "");
+}
+
+#[test]
+// Bookmarks and jumps, and the position restored from the state file and saved on exit.
+fn bookmarks_and_session() {
+    let state_path = std::env::temp_dir().join(format!("test13-{}.state", std::process::id()));
+    let state_path = state_path.to_str().unwrap();
+    let data_path = std::fs::canonicalize("tests/test13.data.txt").unwrap();
+    let data_path = data_path.to_str().unwrap();
+    std::fs::write(state_path, format!("9 0 2:20 {data_path}\n")).unwrap();
+    let output = run_replay(
+        "tests/test13.data.txt",
+        &["--state", state_path],
+        include_bytes!("test13.in.txt"),
+    );
+    let state = std::fs::read_to_string(state_path).unwrap();
+    std::fs::remove_file(state_path).unwrap();
+    check_output(&output, "tests/test13.screens.txt");
+    assert_eq!(state, format!("15 0 2:20,3:9 {data_path}\n"));
+}
+
+#[test]
+// A theme file changing the monochrome theme, the status bar, and an error shown below it.
+fn status_bar_and_theme() {
+    check_replay(
+        "tests/test14.data.txt",
+        &["--status", "--theme", "tests/test14.theme.txt"],
+        include_bytes!("test14.in.txt"),
+        "tests/test14.screens.txt",
+    );
+}
+
+#[test]
+// The documents written one after the other instead of being paged,
+// with line numbers, and then with highlighting.
+fn streamed_output() {
+    let output = run_replay(
+        "tests/test12.data.txt",
+        &[
+            "tests/test13.data.txt",
+            "--paging",
+            "never",
+            "--line-numbers",
+            "always",
+        ],
+        b"",
+    );
+    assert_eq!(std::str::from_utf8(&output.stderr).unwrap(), "");
+    let expected = [
+        "1 First line\n2 Second line\n3 Third line\n4 Fourth line\n5 Fifth line\n",
+        &std::fs::read_to_string("tests/test13.data.txt")
+            .unwrap()
+            .lines()
+            .enumerate()
+            .map(|(line_index, line)| format!("{:>2} {line}\n", line_index + 1))
+            .collect::<String>(),
+    ]
+    .concat();
+    assert_eq!(std::str::from_utf8(&output.stdout).unwrap(), expected);
+    let output = run_replay(
+        "tests/test7.data.toml",
+        &["--paging", "never", "--highlight", "always"],
+        b"",
+    );
+    assert!(std::str::from_utf8(&output.stdout)
+        .unwrap()
+        .starts_with("\u{1b}[38;5;2m# Settings of the viewer\u{1b}[0m\n\u{1b}[38;5;1m[package]"));
+    // Invalid UTF-8, CRLF endings and a last line without newline are written unchanged.
+    for data_path in ["tests/test4.data.txt", "tests/test5.data.txt"] {
+        let data = std::fs::read(data_path).unwrap();
+        let output = run_replay(data_path, &["--paging", "never"], b"");
+        assert_eq!(output.stdout, data);
+        let output = run_replay(
+            data_path,
+            &["--paging", "never", "--line-numbers", "always"],
+            b"",
+        );
+        let lines: Vec<&[u8]> = data.split_inclusive(|byte| *byte == b'\n').collect();
+        let n_digits = lines.len().to_string().len();
+        let numbered: Vec<u8> = lines
+            .iter()
+            .enumerate()
+            .flat_map(|(line_index, line)| {
+                [format!("{:>n_digits$} ", line_index + 1).as_bytes(), line].concat()
+            })
+            .collect();
+        assert_eq!(output.stdout, numbered);
+    }
+}
+
+#[test]
+// Lines filtered by stacked patterns, keeping their numbers, and the filter toggled.
+fn filtered_lines() {
+    check_replay(
+        "tests/test15.data.txt",
+        &[],
+        include_bytes!("test15.in.txt"),
+        "tests/test15.screens.txt",
+    );
+}
+
+#[test]
+// A binary document shown as rows of bytes, whatever the width of the screen,
+// and a text document streamed as rows of bytes when forced.
+fn hex_view() {
+    check_replay(
+        "tests/test16.data.bin",
+        &[],
+        include_bytes!("test16.in.txt"),
+        "tests/test16.screens.txt",
+    );
+    let output = run_replay(
+        "tests/test12.data.txt",
+        &["--paging", "never", "--hex", "always"],
+        b"",
+    );
+    assert!(std::str::from_utf8(&output.stdout).unwrap().starts_with(
+        "00000000  46 69 72 73 74 20 6c 69  6e 65 0a 53 65 63 6f 6e  |First line.Secon|\n"
+    ));
+}
+
+#[test]
+// The script replayed from the file named by an option, with the view set by the others,
+// and an unknown option rejected.
+fn command_line_options() {
+    check_replay(
+        "tests/test17.data.txt",
+        &[
+            "--replay",
+            "tests/test17.in.txt",
+            "--line",
+            "10",
+            "--tab-width",
+            "4",
+            "--wrap",
+        ],
+        b"",
+        "tests/test17.screens.txt",
+    );
+    let output = run_replay("tests/test17.data.txt", &["--unknown"], b"");
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)
+        .unwrap()
+        .contains("unexpected argument '--unknown'"));
+}
+
+#[test]
+// A script replayed in real time, waiting between its events, and recorded again with its delays,
+// as it is when it is replayed as fast as possible.
+fn timed_replay() {
+    let saved_path = std::env::temp_dir().join(format!("test18-{}.in.txt", std::process::id()));
+    for real_time in [true, false] {
+        let start = std::time::Instant::now();
+        let mut args = vec![
+            "--replay",
+            "tests/test18.in.txt",
+            "--save-events",
+            saved_path.to_str().unwrap(),
+        ];
+        if real_time {
+            args.push("--real-time");
+        }
+        let output = run_replay("tests/test17.data.txt", &args, b"");
+        assert_eq!(std::str::from_utf8(&output.stderr).unwrap(), "");
+        if real_time {
+            assert!(start.elapsed() >= std::time::Duration::from_millis(300));
+        }
+        let saved = std::fs::read_to_string(&saved_path).unwrap();
+        std::fs::remove_file(&saved_path).unwrap();
+        assert_eq!(
+            saved,
+            "cols30 rows6\naction line_down\nwait 300\naction line_down\naction quit\n"
+        );
+    }
+}
+
+#[test]
+// Invalid commands and failed assertions are reported with their line numbers.
+fn script_errors() {
+    for (input, expected_error) in [
+        (
+            &b"cols20 rows4\n\nflip\n"[..],
+            "Event script line 3: Unknown key: flip",
+        ),
+        (
+            &b"cols20 rows4 # size\nmouse press 1\n"[..],
+            "Event script line 2: Invalid mouse position: press 1",
+        ),
+        (
+            &b"cols20 rows4\nexpect-line 1 \"2 Second\"\n"[..],
+            r#"Event script line 2: Row 1 shows \"2 Second line\" instead of \"2 Second\""#,
+        ),
+        (
+            &b"cols20 rows4\nesc*2\n"[..],
+            "Event script line 2: Input file continuing after exit command",
+        ),
+        (
+            &b"cols20 rows4\ndown*4000000000\n"[..],
+            "Event script line 2: repeat count too large",
+        ),
+    ] {
+        let output = run_replay("tests/test1.data.txt", &[], input);
+        let error = std::str::from_utf8(&output.stderr).unwrap();
+        assert!(error.contains(expected_error), "{error}");
+    }
+}
+
+/// Runs the program on the file, sending the script lines one at a time,
+/// and applying the next change before every `file_changed` line. Returns the output.
+fn replay_with_changes(path: &str, input: &str, changes: &[&dyn Fn()]) -> String {
+    use std::io::Read;
     use std::process::{Command, Stdio};
+    // Every screen refresh ends by hiding the cursor.
+    const HIDE_CURSOR: &str = "\u{1b}[?25l";
     let program_path = get_program_path();
     let mut child = Command::new(&program_path)
-        .arg("tests/test1.data.txt")
+        .arg(path)
+        .args(["--replay", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap_or_else(|_| panic!("Failed to launch the program `{}`.", program_path));
+    let mut stdin = child.stdin.take().expect("Failed to open the stdin stream");
+    let mut stdout = child
+        .stdout
+        .take()
+        .expect("Failed to open the stdout stream");
+    let mut changes = changes.iter();
+    let mut output = vec![];
+    let script_lines: Vec<&str> = input.lines().collect();
+    for (line_index, line) in script_lines.iter().enumerate() {
+        if *line == "file_changed" {
+            changes.next().expect("Missing file change")();
+        }
+        stdin
+            .write_all(format!("{line}\n").as_bytes())
+            .expect("Failed to write to the stdin stream");
+        // Waits for the screen refresh following this line, so that the next change happens
+        // after the line is interpreted. The last line terminates the program without refresh.
+        if line_index + 1 < script_lines.len() {
+            while String::from_utf8_lossy(&output)
+                .matches(HIDE_CURSOR)
+                .count()
+                <= line_index
+            {
+                let mut buffer = [0; 4096];
+                let n_bytes = stdout
+                    .read(&mut buffer)
+                    .expect("Failed to read the stdout stream");
+                assert!(n_bytes > 0, "Unexpected end of the output");
+                output.extend(&buffer[..n_bytes]);
+            }
+        }
+    }
+    drop(stdin);
+    stdout
+        .read_to_end(&mut output)
+        .expect("Failed to read the stdout stream");
+    assert!(child
+        .wait()
+        .expect("Failed to wait for the program")
+        .success());
+    String::from_utf8(output).unwrap()
+}
+
+/// Runs the program on the data file, with the other arguments, replaying the input commands,
+/// and compares its output with the expected one.
+fn check_replay(data_path: &str, args: &[&str], input: &'static [u8], screens_path: &str) {
+    check_output(&run_replay(data_path, args, input), screens_path);
+}
+
+/// Compares the screens output by the program with the expected ones, checking it reported no error.
+fn check_output(output: &std::process::Output, screens_path: &str) {
+    check_screens(
+        &Screen::from_output(std::str::from_utf8(&output.stdout).unwrap()).dump(),
+        screens_path,
+    );
+    assert_eq!(std::str::from_utf8(&output.stderr).unwrap(), "");
+}
+
+fn run_replay(data_path: &str, args: &[&str], input: &'static [u8]) -> std::process::Output {
+    run_replay_in(std::path::Path::new("."), data_path, args, input)
+}
+
+/// Runs the program in the folder, where the data path is relative to.
+fn run_replay_in(
+    dir: &std::path::Path,
+    data_path: &str,
+    args: &[&str],
+    input: &'static [u8],
+) -> std::process::Output {
+    use std::process::{Command, Stdio};
+    let program_path = std::env::current_dir()
+        .unwrap()
+        .join(get_program_path())
+        .to_string_lossy()
+        .into_owned();
+    // The script is written to the standard input, unless it is read from a file.
+    let replay_args: &[&str] = if args.contains(&"--replay") {
+        &[]
+    } else {
+        &["--replay", "-"]
+    };
+    let mut child = Command::new(&program_path)
+        .current_dir(dir)
+        .arg(data_path)
+        .args(replay_args)
+        .args(args)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
-        .expect(&format!("Failed to launch the program `{}`.", program_path));
+        .unwrap_or_else(|_| panic!("Failed to launch the program `{}`.", program_path));
     let mut stdin = child.stdin.take().expect("Failed to open the stdin stream");
     std::thread::spawn(move || {
         stdin
-            .write_all(include_bytes!("test1.in.txt"))
+            .write_all(input)
             .expect("Failed to write to the stdin stream");
     });
-    let output = child
+    child
         .wait_with_output()
-        .expect("Failed to read the stdout or stderr streams");
-    assert_eq!(
-        std::str::from_utf8(&output.stdout).unwrap(),
-        include_str!("test1.out.txt")
-    );
-    assert_eq!(std::str::from_utf8(&output.stderr).unwrap(), "");
+        .expect("Failed to read the stdout or stderr streams")
 }
@@ -1,5 +1,8 @@
 use crate::data_access::{
-    DbConnection, Latitude, Longitude, Nation, NationId, NationName, Town, TownId, TownName,
+    check_distinct_names,
+    name_search::{sort_by_rank, NameIndex},
+    DbConnection, DbError, Latitude, Longitude, NameMatch, Nation, NationCode, NationId,
+    NationName, Population, Town, TownId, TownName, UniqueNames,
 };
 use std::collections::{hash_map, HashMap};
 use std::error::Error;
@@ -7,8 +10,10 @@ use std::error::Error;
 pub struct MockDbConnection {
     top_town_id: TownId,
     towns: HashMap<TownId, Town>,
+    town_names: NameIndex<TownId>,
     top_nation_id: NationId,
     nations: HashMap<NationId, Nation>,
+    unique_names: UniqueNames,
 }
 
 impl MockDbConnection {
@@ -16,10 +21,38 @@ impl MockDbConnection {
         Self {
             top_town_id: TownId::Serial(0),
             towns: HashMap::<TownId, Town>::new(),
+            town_names: NameIndex::new(),
             top_nation_id: NationId::Serial(0),
             nations: HashMap::<NationId, Nation>::new(),
+            unique_names: UniqueNames::default(),
         }
     }
+
+    /// Checks the uniqueness rules for a nation going to be written with the specified id.
+    fn check_nation(&self, nation_id: &NationId, nation: &Nation) -> Result<(), DbError> {
+        if self.unique_names.nation_name
+            && self
+                .nations
+                .iter()
+                .any(|(k, v)| k != nation_id && v.name == nation.name)
+        {
+            return Err(DbError::DuplicateNationName);
+        }
+        Ok(())
+    }
+
+    /// Checks the uniqueness rules for a town going to be written with the specified id.
+    fn check_town(&self, town_id: &TownId, town: &Town) -> Result<(), DbError> {
+        if self.unique_names.town_name_per_nation
+            && self
+                .towns
+                .iter()
+                .any(|(k, v)| k != town_id && v.name == town.name && v.nation_id == town.nation_id)
+        {
+            return Err(DbError::DuplicateTownName);
+        }
+        Ok(())
+    }
 }
 
 impl DbConnection for MockDbConnection {
@@ -58,7 +91,33 @@ impl DbConnection for MockDbConnection {
         Ok(Self::create_connection())
     }
 
+    fn unique_names(&mut self) -> Result<UniqueNames, Box<dyn Error>> {
+        Ok(self.unique_names)
+    }
+
+    fn set_unique_names(&mut self, unique_names: &UniqueNames) -> Result<(), Box<dyn Error>> {
+        if unique_names.nation_name {
+            check_distinct_names(
+                self.nations.values().map(|nation| &nation.name.0),
+                DbError::DuplicateNationName,
+            )?;
+        }
+        if unique_names.town_name_per_nation {
+            check_distinct_names(
+                self.towns
+                    .values()
+                    .map(|town| (&town.nation_id, &town.name.0)),
+                DbError::DuplicateTownName,
+            )?;
+        }
+        self.unique_names = *unique_names;
+        Ok(())
+    }
+
     fn insert_nation(&mut self, nation: &Nation) -> Result<NationId, Box<dyn Error>> {
+        let mut new_id = self.top_nation_id.clone();
+        new_id.increment();
+        self.check_nation(&new_id, nation)?;
         self.top_nation_id.increment();
         self.nations
             .insert(self.top_nation_id.clone(), nation.clone());
@@ -66,8 +125,12 @@ impl DbConnection for MockDbConnection {
     }
 
     fn insert_town(&mut self, town: &Town) -> Result<TownId, Box<dyn Error>> {
+        let mut new_id = self.top_town_id.clone();
+        new_id.increment();
+        self.check_town(&new_id, town)?;
         self.top_town_id.increment();
         self.towns.insert(self.top_town_id.clone(), town.clone());
+        self.town_names.insert(&self.top_town_id, &town.name.0);
         Ok(self.top_town_id.clone())
     }
 
@@ -76,6 +139,7 @@ impl DbConnection for MockDbConnection {
     }
 
     fn delete_town(&mut self, id: &TownId) -> Result<bool, Box<dyn Error>> {
+        self.town_names.remove(id);
         Ok(self.towns.remove(&id).is_some())
     }
 
@@ -84,6 +148,7 @@ impl DbConnection for MockDbConnection {
         nation_id: &NationId,
         nation: &Nation,
     ) -> Result<bool, Box<dyn Error>> {
+        self.check_nation(nation_id, nation)?;
         Ok(
             match self
                 .nations
@@ -97,13 +162,17 @@ impl DbConnection for MockDbConnection {
     }
 
     fn update_town(&mut self, town_id: &TownId, town: &Town) -> Result<bool, Box<dyn Error>> {
+        self.check_town(town_id, town)?;
         Ok(
             match self
                 .towns
                 .entry(town_id.clone())
                 .and_modify(|item| *item = town.clone())
             {
-                hash_map::Entry::Occupied(_) => true,
+                hash_map::Entry::Occupied(_) => {
+                    self.town_names.insert(town_id, &town.name.0);
+                    true
+                }
                 hash_map::Entry::Vacant(_) => false,
             },
         )
@@ -140,6 +209,23 @@ impl DbConnection for MockDbConnection {
         })))
     }
 
+    fn filter_nations_by_iso_code(
+        &mut self,
+        iso_code: &NationCode,
+    ) -> Result<
+        Box<dyn Iterator<Item = Result<(NationId, Nation), Box<dyn Error>>> + '_>,
+        Box<dyn Error>,
+    > {
+        let iso_code = iso_code.clone();
+        Ok(Box::new(self.nations.iter().filter_map(move |(k, v)| {
+            if v.iso_code == iso_code {
+                Some(Ok((k.clone(), v.clone())))
+            } else {
+                None
+            }
+        })))
+    }
+
     fn filter_towns_by_name(
         &mut self,
         name: &TownName,
@@ -155,6 +241,23 @@ impl DbConnection for MockDbConnection {
         })))
     }
 
+    fn filter_towns_by_population(
+        &mut self,
+        min_population: &Population,
+        max_population: &Population,
+    ) -> Result<Box<dyn Iterator<Item = Result<(TownId, Town), Box<dyn Error>>> + '_>, Box<dyn Error>>
+    {
+        let min_population = *min_population;
+        let max_population = *max_population;
+        Ok(Box::new(self.towns.iter().filter_map(move |(k, v)| {
+            if min_population <= v.population && v.population <= max_population {
+                Some(Ok((k.clone(), v.clone())))
+            } else {
+                None
+            }
+        })))
+    }
+
     fn filter_towns_by_lat_long(
         &mut self,
         min_lat: &Latitude,
@@ -175,4 +278,22 @@ impl DbConnection for MockDbConnection {
             }
         })))
     }
+
+    fn search_towns_by_name(
+        &mut self,
+        text: &str,
+        name_match: &NameMatch,
+    ) -> Result<Box<dyn Iterator<Item = Result<(TownId, Town), Box<dyn Error>>> + '_>, Box<dyn Error>>
+    {
+        let candidates = self
+            .town_names
+            .candidates(text, name_match)
+            .into_iter()
+            .filter_map(|id| self.towns.get(&id).map(|town| (id, town.clone())));
+        Ok(Box::new(
+            sort_by_rank(candidates, text, name_match, |(_, town)| &town.name.0)
+                .into_iter()
+                .map(Ok),
+        ))
+    }
 }
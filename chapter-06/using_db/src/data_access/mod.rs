@@ -1,7 +1,11 @@
 pub mod mock_db;
+pub mod name_search;
 pub mod persy_db;
 pub mod postgres_db;
+pub mod redb_db;
 pub mod sqlite_db;
+#[cfg(test)]
+mod tests;
 
 use persy::PersyId;
 use std::error::Error;
@@ -126,10 +130,40 @@ pub struct Latitude(pub f64);
 #[derive(Copy, Clone, Debug, PartialEq, PartialOrd, serde::Deserialize, serde::Serialize)]
 pub struct Longitude(pub f64);
 
+/// ISO 3166-1 alpha-2 code, like "FR".
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct NationCode(pub String);
+
+/// Square kilometres.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, serde::Deserialize, serde::Serialize)]
+pub struct Area(pub f64);
+
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Deserialize, serde::Serialize,
+)]
+pub struct Population(pub u64);
+
+/// Metres above sea level.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, serde::Deserialize, serde::Serialize)]
+pub struct Elevation(pub f64);
+
+/// ISO 639-1 code, like "de".
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct LanguageCode(pub String);
+
+/// The name of a town in a language.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct AlternateName {
+    pub language: LanguageCode,
+    pub name: TownName,
+}
+
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct Nation {
     pub name: NationName,
     pub capital_id: OptionalTownId,
+    pub iso_code: NationCode,
+    pub area: Area,
 }
 
 #[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
@@ -138,6 +172,113 @@ pub struct Town {
     pub lat: Latitude,
     pub long: Longitude,
     pub nation_id: NationId,
+    pub population: Population,
+    pub elevation: Elevation,
+    pub alternate_names: Vec<AlternateName>,
+}
+
+/// How a searched text must match a name.
+/// All the matches ignore letter case and diacritics.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NameMatch {
+    /// The whole name must be equal to the text.
+    Exact,
+    /// The name or one of its words must begin with the text.
+    Prefix,
+    /// The name or one of its words must be within the specified edit distance from the text.
+    Fuzzy(usize),
+}
+
+/// Uniqueness rules which a database may enforce.
+/// They are stored in the database, and they are disabled in new databases.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UniqueNames {
+    /// No two nations can have the same name.
+    pub nation_name: bool,
+    /// No two towns of the same nation can have the same name.
+    pub town_name_per_nation: bool,
+}
+
+/// What an upsert operation has done.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Upserted {
+    Inserted,
+    Updated,
+}
+
+/// Errors common to all the databases.
+/// They are returned inside the boxed errors, so they can be found using `downcast_ref`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DbError {
+    /// The operation would violate the rule `UniqueNames::nation_name`.
+    DuplicateNationName,
+    /// The operation would violate the rule `UniqueNames::town_name_per_nation`.
+    DuplicateTownName,
+}
+
+impl std::fmt::Display for DbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DbError::DuplicateNationName => write!(f, "Another nation has the same name."),
+            DbError::DuplicateTownName => {
+                write!(f, "Another town of the same nation has the same name.")
+            }
+        }
+    }
+}
+
+impl Error for DbError {}
+
+/// Checks that the names are distinct.
+/// It is used by the databases not having unique indexes.
+fn check_distinct_names<T: Eq + std::hash::Hash>(
+    names: impl Iterator<Item = T>,
+    error: DbError,
+) -> Result<(), DbError> {
+    let mut found = std::collections::HashSet::new();
+    for name in names {
+        if !found.insert(name) {
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+/// Serialization format of the records, for the databases storing them as bytes.
+pub trait Serder {
+    fn serialize<T: serde::Serialize>(obj: &T) -> Result<Vec<u8>, Box<dyn std::error::Error>>;
+
+    fn deserialize<'de, T: serde::Deserialize<'de>>(
+        buffer: &'de [u8],
+    ) -> Result<T, Box<dyn std::error::Error>>;
+}
+
+pub struct BincodeSerder;
+
+impl Serder for BincodeSerder {
+    fn serialize<T: serde::Serialize>(obj: &T) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(bincode::serialize(obj)?)
+    }
+
+    fn deserialize<'de, T: serde::Deserialize<'de>>(
+        buffer: &'de [u8],
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        Ok(bincode::deserialize(buffer)?)
+    }
+}
+
+pub struct JsonSerder;
+
+impl Serder for JsonSerder {
+    fn serialize<T: serde::Serialize>(obj: &T) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(serde_json::to_vec(obj)?)
+    }
+
+    fn deserialize<'de, T: serde::Deserialize<'de>>(
+        buffer: &'de [u8],
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_slice(buffer)?)
+    }
 }
 
 pub trait DbConnection {
@@ -171,6 +312,11 @@ pub trait DbConnection {
     where
         Self: Sized;
 
+    fn unique_names(&mut self) -> Result<UniqueNames, Box<dyn Error>>;
+
+    /// It fails with a `DbError` if the existing data violate a rule being enabled.
+    fn set_unique_names(&mut self, unique_names: &UniqueNames) -> Result<(), Box<dyn Error>>;
+
     fn insert_nation(&mut self, nation: &Nation) -> Result<NationId, Box<dyn Error>>;
 
     fn insert_town(&mut self, town: &Town) -> Result<TownId, Box<dyn Error>>;
@@ -195,11 +341,25 @@ pub trait DbConnection {
         Box<dyn Error>,
     >;
 
+    fn filter_nations_by_iso_code(
+        &mut self,
+        iso_code: &NationCode,
+    ) -> Result<
+        Box<dyn Iterator<Item = Result<(NationId, Nation), Box<dyn Error>>> + '_>,
+        Box<dyn Error>,
+    >;
+
     fn filter_towns_by_name(
         &mut self,
         name: &TownName,
     ) -> Result<Box<dyn Iterator<Item = Result<(TownId, Town), Box<dyn Error>>> + '_>, Box<dyn Error>>;
 
+    fn filter_towns_by_population(
+        &mut self,
+        min_population: &Population,
+        max_population: &Population,
+    ) -> Result<Box<dyn Iterator<Item = Result<(TownId, Town), Box<dyn Error>>> + '_>, Box<dyn Error>>;
+
     fn filter_towns_by_lat_long(
         &mut self,
         min_lat: &Latitude,
@@ -207,4 +367,129 @@ pub trait DbConnection {
         min_long: &Longitude,
         max_long: &Longitude,
     ) -> Result<Box<dyn Iterator<Item = Result<(TownId, Town), Box<dyn Error>>> + '_>, Box<dyn Error>>;
+
+    /// Returns the towns whose name matches the specified text,
+    /// sorted from the best match to the worst one.
+    fn search_towns_by_name(
+        &mut self,
+        text: &str,
+        name_match: &NameMatch,
+    ) -> Result<Box<dyn Iterator<Item = Result<(TownId, Town), Box<dyn Error>>> + '_>, Box<dyn Error>>;
+
+    /// If a nation with the same name exists, it updates it.
+    /// Otherwise, it inserts the nation.
+    /// This implementation is not atomic, and it should be overridden
+    /// by the databases supporting concurrent connections.
+    fn upsert_nation(&mut self, nation: &Nation) -> Result<(NationId, Upserted), Box<dyn Error>> {
+        find_and_upsert_nation(self, nation)
+    }
+
+    /// If a town with the same name and the same nation exists, it updates it.
+    /// Otherwise, it inserts the town.
+    /// This implementation is not atomic, like `upsert_nation`.
+    fn upsert_town(&mut self, town: &Town) -> Result<(TownId, Upserted), Box<dyn Error>> {
+        find_and_upsert_town(self, town)
+    }
+}
+
+/// Looks for a nation having the same name, and then updates it or inserts the nation.
+fn find_and_upsert_nation<D: DbConnection + ?Sized>(
+    db: &mut D,
+    nation: &Nation,
+) -> Result<(NationId, Upserted), Box<dyn Error>> {
+    let existing_id = match db.filter_nations_by_name(&nation.name)?.next() {
+        Some(row) => Some(row?.0),
+        None => None,
+    };
+    match existing_id {
+        Some(id) => {
+            db.update_nation(&id, nation)?;
+            Ok((id, Upserted::Updated))
+        }
+        None => Ok((db.insert_nation(nation)?, Upserted::Inserted)),
+    }
+}
+
+/// Looks for a town having the same name and the same nation,
+/// and then updates it or inserts the town.
+fn find_and_upsert_town<D: DbConnection + ?Sized>(
+    db: &mut D,
+    town: &Town,
+) -> Result<(TownId, Upserted), Box<dyn Error>> {
+    let mut existing_id = None;
+    for row in db.filter_towns_by_name(&town.name)? {
+        let (id, existing_town) = row?;
+        if existing_town.nation_id == town.nation_id {
+            existing_id = Some(id);
+            break;
+        }
+    }
+    match existing_id {
+        Some(id) => {
+            db.update_town(&id, town)?;
+            Ok((id, Upserted::Updated))
+        }
+        None => Ok((db.insert_town(town)?, Upserted::Inserted)),
+    }
+}
+
+/// A problem found by an integrity check.
+/// It can be serialized, so that it can be processed by other programs.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub enum IntegrityProblem {
+    /// A table, a segment or an index is missing, or has an unexpected version.
+    Schema(String),
+    /// The storage engine reported a corruption.
+    Storage(String),
+    /// A record cannot be deserialized.
+    UnreadableRecord {
+        segment: String,
+        key: String,
+        error: String,
+    },
+    /// A town refers to a nation which does not exist.
+    MissingNation {
+        town_id: TownId,
+        nation_id: NationId,
+    },
+}
+
+/// Operations available only for the databases stored in a single file.
+pub trait DbMaintenance: DbConnection {
+    /// Opens the database as it is, without upgrading it,
+    /// so that it can be checked or copied without being changed.
+    fn open_unchanged(path: &str) -> Result<Self, Box<dyn Error>>
+    where
+        Self: Sized;
+
+    /// Copies the whole database to a new file, while it is open.
+    fn backup(&mut self, path: &str) -> Result<(), Box<dyn Error>>;
+
+    /// Replaces the database file at `path` with a copy of the backup.
+    /// The backup is checked as it is before replacing the database,
+    /// which must not be open, and so a backup of an older version is refused.
+    fn restore(backup_path: &str, path: &str) -> Result<(), Box<dyn Error>>
+    where
+        Self: Sized,
+    {
+        let restoring_path = format!("{}.restoring", path);
+        std::fs::copy(backup_path, &restoring_path)?;
+        match Self::open_unchanged(&restoring_path).and_then(|mut db| db.check_integrity()) {
+            Ok(problems) if problems.is_empty() => {
+                std::fs::rename(&restoring_path, path)?;
+                Ok(())
+            }
+            Ok(problems) => {
+                std::fs::remove_file(&restoring_path)?;
+                Err(format!("The backup has {} integrity problems.", problems.len()).into())
+            }
+            Err(err) => {
+                std::fs::remove_file(&restoring_path)?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Returns the problems found in the database, which is consistent if there are none.
+    fn check_integrity(&mut self) -> Result<Vec<IntegrityProblem>, Box<dyn Error>>;
 }
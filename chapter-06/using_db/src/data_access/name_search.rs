@@ -0,0 +1,231 @@
+use crate::data_access::NameMatch;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Returns the name in lowercase and without diacritics,
+/// so that "München" and "MUNCHEN" become both "munchen".
+pub fn normalize(name: &str) -> String {
+    name.to_lowercase()
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect()
+}
+
+/// Returns the words of an already normalized name.
+pub fn words(normalized_name: &str) -> impl Iterator<Item = &str> {
+    normalized_name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+}
+
+/// If the normalized name satisfies the search,
+/// it returns its rank, where 0 is the best match.
+pub fn rank(name_match: &NameMatch, normalized_text: &str, normalized_name: &str) -> Option<usize> {
+    match name_match {
+        NameMatch::Exact => {
+            if normalized_name == normalized_text {
+                Some(0)
+            } else {
+                None
+            }
+        }
+        NameMatch::Prefix => {
+            if normalized_name.starts_with(normalized_text) {
+                Some(normalized_name.chars().count() - normalized_text.chars().count())
+            } else if words(normalized_name).any(|word| word.starts_with(normalized_text)) {
+                Some(normalized_name.chars().count())
+            } else {
+                None
+            }
+        }
+        NameMatch::Fuzzy(max_distance) => {
+            let distance = words(normalized_name)
+                .map(|word| strsim::levenshtein(word, normalized_text))
+                .chain(std::iter::once(strsim::levenshtein(
+                    normalized_name,
+                    normalized_text,
+                )))
+                .min()
+                .unwrap_or(usize::MAX);
+            if distance <= *max_distance {
+                Some(distance)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Keeps only the items whose name satisfies the search,
+/// sorted from the best match to the worst one.
+pub fn sort_by_rank<T>(
+    items: impl Iterator<Item = T>,
+    text: &str,
+    name_match: &NameMatch,
+    name_of: impl Fn(&T) -> &str,
+) -> Vec<T> {
+    let normalized_text = normalize(text);
+    let mut ranked: Vec<(usize, String, T)> = items
+        .filter_map(|item| {
+            let normalized_name = normalize(name_of(&item));
+            rank(name_match, &normalized_text, &normalized_name)
+                .map(|rank| (rank, normalized_name, item))
+        })
+        .collect();
+    ranked.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+    ranked.into_iter().map(|(_, _, item)| item).collect()
+}
+
+/// In-memory index of names, for the databases not having a text search engine.
+/// Its keys are the normalized names and their words.
+pub struct NameIndex<Id> {
+    keys: BTreeMap<String, HashSet<Id>>,
+    names: HashMap<Id, String>,
+}
+
+impl<Id> NameIndex<Id>
+where
+    Id: Clone + Eq + Hash,
+{
+    pub fn new() -> Self {
+        Self {
+            keys: BTreeMap::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, id: &Id, name: &str) {
+        self.remove(id);
+        let normalized_name = normalize(name);
+        for key in words(&normalized_name).chain(std::iter::once(normalized_name.as_str())) {
+            self.keys
+                .entry(key.to_string())
+                .or_default()
+                .insert(id.clone());
+        }
+        self.names.insert(id.clone(), normalized_name);
+    }
+
+    pub fn remove(&mut self, id: &Id) {
+        if let Some(normalized_name) = self.names.remove(id) {
+            for key in words(&normalized_name).chain(std::iter::once(normalized_name.as_str())) {
+                if let Some(ids) = self.keys.get_mut(key) {
+                    ids.remove(id);
+                    if ids.is_empty() {
+                        self.keys.remove(key);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the ids of the names which may satisfy the search.
+    /// They should be checked and sorted using `sort_by_rank`.
+    pub fn candidates(&self, text: &str, name_match: &NameMatch) -> HashSet<Id> {
+        let normalized_text = normalize(text);
+        let mut result = HashSet::new();
+        match name_match {
+            NameMatch::Exact => {
+                if let Some(ids) = self.keys.get(&normalized_text) {
+                    result.extend(ids.iter().cloned());
+                }
+            }
+            NameMatch::Prefix => {
+                for (_, ids) in self
+                    .keys
+                    .range(normalized_text.clone()..)
+                    .take_while(|(key, _)| key.starts_with(&normalized_text))
+                {
+                    result.extend(ids.iter().cloned());
+                }
+            }
+            NameMatch::Fuzzy(max_distance) => {
+                for (_, ids) in self
+                    .keys
+                    .iter()
+                    .filter(|(key, _)| strsim::levenshtein(key, &normalized_text) <= *max_distance)
+                {
+                    result.extend(ids.iter().cloned());
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_ignores_case_and_diacritics() {
+        assert_eq!(normalize("München"), "munchen");
+        assert_eq!(normalize("MUNCHEN"), "munchen");
+        assert_eq!(normalize("Île-de-France"), "ile-de-france");
+        assert_eq!(
+            words("saint-etienne du rouvray").collect::<Vec<_>>(),
+            ["saint", "etienne", "du", "rouvray"]
+        );
+    }
+
+    #[test]
+    fn rank_by_match() {
+        assert_eq!(rank(&NameMatch::Exact, "munchen", "munchen"), Some(0));
+        assert_eq!(rank(&NameMatch::Exact, "munch", "munchen"), None);
+        // The names beginning with the text come before those having a word beginning with it.
+        assert_eq!(rank(&NameMatch::Prefix, "mun", "munchen"), Some(4));
+        assert_eq!(
+            rank(&NameMatch::Prefix, "mun", "bad munstereifel"),
+            Some(16)
+        );
+        assert_eq!(rank(&NameMatch::Prefix, "unch", "munchen"), None);
+        // Either a word or the whole name may be within the distance.
+        assert_eq!(rank(&NameMatch::Fuzzy(2), "munchen", "munchen"), Some(0));
+        assert_eq!(rank(&NameMatch::Fuzzy(2), "munhen", "bad munchen"), Some(1));
+        assert_eq!(rank(&NameMatch::Fuzzy(2), "newyork", "new york"), Some(1));
+        assert_eq!(rank(&NameMatch::Fuzzy(1), "munich", "munchen"), None);
+    }
+
+    #[test]
+    fn sort_by_rank_keeps_the_matches_in_order() {
+        let names = ["Munich", "Bad Münstereifel", "Berlin", "München", "Munster"];
+        let sorted = sort_by_rank(names.into_iter(), "MUN", &NameMatch::Prefix, |name| name);
+        // Equal ranks are sorted by normalized name.
+        assert_eq!(sorted, ["Munich", "München", "Munster", "Bad Münstereifel"]);
+        let sorted = sort_by_rank(names.into_iter(), "Munchen", &NameMatch::Fuzzy(2), |name| {
+            name
+        });
+        assert_eq!(sorted, ["München"]);
+    }
+
+    #[test]
+    fn name_index_candidates() {
+        let mut index = NameIndex::new();
+        index.insert(&1, "München");
+        index.insert(&2, "Bad Münstereifel");
+        index.insert(&3, "Berlin");
+        fn candidates(index: &NameIndex<i32>, text: &str, name_match: &NameMatch) -> Vec<i32> {
+            let mut ids: Vec<i32> = index.candidates(text, name_match).into_iter().collect();
+            ids.sort();
+            ids
+        }
+        assert_eq!(candidates(&index, "MUNCHEN", &NameMatch::Exact), [1]);
+        assert_eq!(candidates(&index, "mün", &NameMatch::Prefix), [1, 2]);
+        assert_eq!(candidates(&index, "bad", &NameMatch::Prefix), [2]);
+        assert_eq!(candidates(&index, "berln", &NameMatch::Fuzzy(1)), [3]);
+        assert_eq!(
+            candidates(&index, "berln", &NameMatch::Fuzzy(0)),
+            Vec::<i32>::new()
+        );
+        // Renaming and removing update the keys.
+        index.insert(&3, "Bern");
+        assert_eq!(
+            candidates(&index, "berlin", &NameMatch::Exact),
+            Vec::<i32>::new()
+        );
+        assert_eq!(candidates(&index, "bern", &NameMatch::Exact), [3]);
+        index.remove(&1);
+        assert_eq!(candidates(&index, "mün", &NameMatch::Prefix), [2]);
+    }
+}
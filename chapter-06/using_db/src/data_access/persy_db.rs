@@ -1,4 +1,4 @@
-use persy::{Config, Persy, PersyId};
+use persy::{Config, Persy, PersyError, PersyId, Transaction, ValueMode, PE};
 
 /*
 pub fn open(filename: &str) -> Result<Persy, Box<dyn std::error::Error>> {
@@ -90,54 +90,26 @@ an implementation of  the By changing
 */
 
 use crate::data_access::{
-    DbConnection, Latitude, Longitude, Nation, NationId, NationName, OptionalTownId, Town, TownId,
-    TownName,
+    check_distinct_names,
+    name_search::{sort_by_rank, NameIndex},
+    Area, DbConnection, DbError, DbMaintenance, Elevation, IntegrityProblem, Latitude, Longitude,
+    NameMatch, Nation, NationCode, NationId, NationName, OptionalTownId, Population, Serder, Town,
+    TownId, TownName, UniqueNames,
 };
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind};
 use std::{error::Error, str::FromStr};
 
 use serde::{Deserialize, Serialize};
 
-pub trait Serder {
-    fn serialize<T: serde::Serialize>(obj: &T) -> Result<Vec<u8>, Box<dyn std::error::Error>>;
-
-    fn deserialize<'de, T: serde::Deserialize<'de>>(
-        buffer: &'de [u8],
-    ) -> Result<T, Box<dyn std::error::Error>>;
-}
-
-pub struct BincodeSerder;
-
-impl Serder for BincodeSerder {
-    fn serialize<T: serde::Serialize>(obj: &T) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        Ok(bincode::serialize(obj)?)
-    }
-
-    fn deserialize<'de, T: serde::Deserialize<'de>>(
-        buffer: &'de [u8],
-    ) -> Result<T, Box<dyn std::error::Error>> {
-        Ok(bincode::deserialize(buffer)?)
-    }
-}
-
-pub struct JsonSerder;
-
-impl Serder for JsonSerder {
-    fn serialize<T: serde::Serialize>(obj: &T) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        Ok(serde_json::to_vec(obj)?)
-    }
-
-    fn deserialize<'de, T: serde::Deserialize<'de>>(
-        buffer: &'de [u8],
-    ) -> Result<T, Box<dyn std::error::Error>> {
-        Ok(serde_json::from_slice(buffer)?)
-    }
-}
-
 pub struct PersyConnection<S>
 where
     S: Serder,
 {
     conn: Persy,
+    path: String,
+    town_names: NameIndex<TownId>,
     phantom: std::marker::PhantomData<S>,
 }
 
@@ -145,14 +117,68 @@ impl<S> PersyConnection<S>
 where
     S: Serder,
 {
-    fn new(db: Persy) -> Self {
-        Self {
+    fn new(db: Persy, path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut town_names = NameIndex::new();
+        // The missing segment and the unreadable records are reported by `check_integrity`.
+        if db.exists_segment("Towns")? {
+            for (k, v) in db.scan("Towns")? {
+                if let Ok(town) = S::deserialize::<Town>(v.as_slice()) {
+                    town_names.insert(&TownId::PersyKey(k.to_string()), &town.name.0);
+                }
+            }
+        }
+        Ok(Self {
             conn: db,
+            path: path.to_string(),
+            town_names,
             phantom: std::marker::PhantomData::<S>,
-        }
+        })
+    }
+}
+
+/// The rules of `UniqueNames` are enforced by these exclusive indexes,
+/// which exist only while their rules are enabled.
+const NATIONS_NAME_INDEX: &str = "NationsName";
+const TOWNS_NATION_NAME_INDEX: &str = "TownsNationName";
+
+/// Key of a town in the index of the town names per nation.
+/// The nation keys have no slashes, so different towns have different keys.
+fn town_name_key(town: &Town) -> String {
+    format!("{}/{}", town.nation_id, town.name.0)
+}
+
+fn duplicate_key_error(index: &str) -> DbError {
+    if index == NATIONS_NAME_INDEX {
+        DbError::DuplicateNationName
+    } else {
+        DbError::DuplicateTownName
     }
 }
 
+/// The duplicate keys of the unique indexes are reported as `DbError`.
+fn map_duplicate_key<T: Into<PersyError>>(err: PE<T>) -> Box<dyn Error> {
+    match err.error().into() {
+        PersyError::IndexDuplicateKey(index, _) => Box::new(duplicate_key_error(&index)),
+        err => Box::new(err),
+    }
+}
+
+/// Adds the key of a record to a unique index.
+/// The key is looked for before, as a prepare failing on a duplicate key
+/// can leave the index locked; the prepare still fails if another transaction adds it.
+fn put_unique(
+    tx: &mut Transaction,
+    index: &str,
+    key: String,
+    id: PersyId,
+) -> Result<(), Box<dyn Error>> {
+    if tx.one::<String, PersyId>(index, &key)?.is_some() {
+        return Err(Box::new(duplicate_key_error(index)));
+    }
+    tx.put(index, key, id)?;
+    Ok(())
+}
+
 impl<S> DbConnection for PersyConnection<S>
 where
     S: Serder,
@@ -165,19 +191,21 @@ where
         if !segments_exist(&db)? {
             return Err("Persy open_existing: segments missing.".into());
         }
-        Ok(PersyConnection::new(db))
+        upgrade_segments::<S>(&db)?;
+        PersyConnection::new(db, options)
     }
 
     fn open_existing_truncated(options: &str) -> Result<Self, Box<dyn std::error::Error>>
     where
         Self: Sized,
     {
-        let mut db = Persy::open(options, Config::new())?;
+        let db = Persy::open(options, Config::new())?;
         if !segments_exist(&db)? {
             return Err("Persy open_existing_truncated: segments missing.".into());
         }
-        truncate_segments(&mut db)?;
-        Ok(PersyConnection::new(db))
+        upgrade_segments::<S>(&db)?;
+        truncate_segments::<S>(&db)?;
+        PersyConnection::new(db, options)
     }
 
     fn create(options: &str) -> Result<Self, Box<dyn std::error::Error>>
@@ -188,10 +216,10 @@ where
             return Err("Persy create: file already exists.".into());
         }
         let db = Persy::open_or_create_with(options, Config::new(), |persy| {
-            create_segments(&persy)?;
+            create_segments::<S>(&persy)?;
             Ok(())
         })?;
-        Ok(PersyConnection::new(db))
+        PersyConnection::new(db, options)
     }
 
     fn open_or_create(options: &str) -> Result<Self, Box<dyn std::error::Error>>
@@ -199,10 +227,12 @@ where
         Self: Sized,
     {
         let db = Persy::open_or_create_with(options, Config::new(), |_| Ok(()))?;
-        if !segments_exist(&db)? {
-            create_segments(&db)?;
+        if segments_exist(&db)? {
+            upgrade_segments::<S>(&db)?;
+        } else {
+            create_segments::<S>(&db)?;
         }
-        Ok(PersyConnection::new(db))
+        PersyConnection::new(db, options)
     }
 
     fn open_truncated_or_create(options: &str) -> Result<Self, Box<dyn std::error::Error>>
@@ -211,18 +241,68 @@ where
     {
         let db = Persy::open_or_create_with(options, Config::new(), |_| Ok(()))?;
         if segments_exist(&db)? {
-            truncate_segments(&db)?;
+            upgrade_segments::<S>(&db)?;
+            truncate_segments::<S>(&db)?;
         } else {
-            create_segments(&db)?;
+            create_segments::<S>(&db)?;
+        }
+        PersyConnection::new(db, options)
+    }
+
+    fn unique_names(&mut self) -> Result<UniqueNames, Box<dyn Error>> {
+        Ok(UniqueNames {
+            nation_name: self.conn.exists_index(NATIONS_NAME_INDEX)?,
+            town_name_per_nation: self.conn.exists_index(TOWNS_NATION_NAME_INDEX)?,
+        })
+    }
+
+    fn set_unique_names(&mut self, unique_names: &UniqueNames) -> Result<(), Box<dyn Error>> {
+        let mut tx = self.conn.begin()?;
+        let nation_index_exists = tx.exists_index(NATIONS_NAME_INDEX)?;
+        if unique_names.nation_name && !nation_index_exists {
+            let mut names = vec![];
+            for (_, v) in self.conn.scan("Nations")? {
+                names.push(S::deserialize::<Nation>(v.as_slice())?.name.0);
+            }
+            check_distinct_names(names.into_iter(), DbError::DuplicateNationName)?;
+            tx.create_index::<String, PersyId>(NATIONS_NAME_INDEX, ValueMode::Exclusive)?;
+            for (k, v) in self.conn.scan("Nations")? {
+                let nation: Nation = S::deserialize(v.as_slice())?;
+                tx.put(NATIONS_NAME_INDEX, nation.name.0, k)
+                    .map_err(map_duplicate_key)?;
+            }
+        } else if !unique_names.nation_name && nation_index_exists {
+            tx.drop_index(NATIONS_NAME_INDEX)?;
+        }
+        let town_index_exists = tx.exists_index(TOWNS_NATION_NAME_INDEX)?;
+        if unique_names.town_name_per_nation && !town_index_exists {
+            let mut names = vec![];
+            for (_, v) in self.conn.scan("Towns")? {
+                let town: Town = S::deserialize(v.as_slice())?;
+                names.push((town.nation_id, town.name.0));
+            }
+            check_distinct_names(names.into_iter(), DbError::DuplicateTownName)?;
+            tx.create_index::<String, PersyId>(TOWNS_NATION_NAME_INDEX, ValueMode::Exclusive)?;
+            for (k, v) in self.conn.scan("Towns")? {
+                let town: Town = S::deserialize(v.as_slice())?;
+                tx.put(TOWNS_NATION_NAME_INDEX, town_name_key(&town), k)
+                    .map_err(map_duplicate_key)?;
+            }
+        } else if !unique_names.town_name_per_nation && town_index_exists {
+            tx.drop_index(TOWNS_NATION_NAME_INDEX)?;
         }
-        Ok(PersyConnection::new(db))
+        tx.prepare().map_err(map_duplicate_key)?.commit()?;
+        Ok(())
     }
 
     fn insert_nation(&mut self, nation: &Nation) -> Result<NationId, Box<dyn Error>> {
         let mut tx = self.conn.begin()?;
         let data = S::serialize(&nation)?;
         let id = tx.insert("Nations", &data)?;
-        tx.prepare()?.commit()?;
+        if tx.exists_index(NATIONS_NAME_INDEX)? {
+            put_unique(&mut tx, NATIONS_NAME_INDEX, nation.name.0.clone(), id)?;
+        }
+        tx.prepare().map_err(map_duplicate_key)?.commit()?;
         Ok(NationId::PersyKey(id.to_string()))
     }
 
@@ -230,54 +310,98 @@ where
         let mut tx = self.conn.begin()?;
         let data = S::serialize(&town)?;
         let id = tx.insert("Towns", &data)?;
-        tx.prepare()?.commit()?;
-        Ok(TownId::PersyKey(id.to_string()))
+        if tx.exists_index(TOWNS_NATION_NAME_INDEX)? {
+            put_unique(&mut tx, TOWNS_NATION_NAME_INDEX, town_name_key(town), id)?;
+        }
+        tx.prepare().map_err(map_duplicate_key)?.commit()?;
+        let id = TownId::PersyKey(id.to_string());
+        self.town_names.insert(&id, &town.name.0);
+        Ok(id)
     }
 
     fn delete_nation(&mut self, id: &NationId) -> Result<bool, Box<dyn Error>> {
+        let NationId::PersyKey(key) = id else {
+            return Ok(false);
+        };
+        let id = PersyId::from_str(key)?;
         let mut tx = self.conn.begin()?;
-        let mut success = false;
-        if let NationId::PersyKey(key) = id {
-            success = tx.delete("Nations", &PersyId::from_str(key)?).is_ok();
-            tx.prepare()?.commit()?;
+        let Some(old_data) = tx.read("Nations", &id)? else {
+            return Ok(false);
+        };
+        tx.delete("Nations", &id)?;
+        if tx.exists_index(NATIONS_NAME_INDEX)? {
+            let old: Nation = S::deserialize(old_data.as_slice())?;
+            tx.remove(NATIONS_NAME_INDEX, old.name.0, Some(id))?;
         }
-        Ok(success)
+        tx.prepare()?.commit()?;
+        Ok(true)
     }
 
     fn delete_town(&mut self, id: &TownId) -> Result<bool, Box<dyn Error>> {
+        let TownId::PersyKey(key) = id else {
+            return Ok(false);
+        };
+        let persy_id = PersyId::from_str(key)?;
         let mut tx = self.conn.begin()?;
-        let mut success = false;
-        if let TownId::PersyKey(key) = id {
-            success = tx.delete("Towns", &PersyId::from_str(key)?).is_ok();
-            tx.prepare()?.commit()?;
+        let Some(old_data) = tx.read("Towns", &persy_id)? else {
+            return Ok(false);
+        };
+        tx.delete("Towns", &persy_id)?;
+        if tx.exists_index(TOWNS_NATION_NAME_INDEX)? {
+            let old: Town = S::deserialize(old_data.as_slice())?;
+            tx.remove(TOWNS_NATION_NAME_INDEX, town_name_key(&old), Some(persy_id))?;
         }
-        Ok(success)
+        tx.prepare()?.commit()?;
+        self.town_names.remove(id);
+        Ok(true)
     }
 
     fn update_nation(&mut self, id: &NationId, nation: &Nation) -> Result<bool, Box<dyn Error>> {
+        let NationId::PersyKey(key) = id else {
+            return Ok(false);
+        };
+        let id = PersyId::from_str(key)?;
         let mut tx = self.conn.begin()?;
-        let new_data = S::serialize(nation)?;
-        let mut success = false;
-        if let NationId::PersyKey(key) = id {
-            success = tx
-                .update("Nations", &PersyId::from_str(key)?, &new_data)
-                .is_ok();
-            tx.prepare()?.commit()?;
+        let Some(old_data) = tx.read("Nations", &id)? else {
+            return Ok(false);
+        };
+        tx.update("Nations", &id, &S::serialize(nation)?)?;
+        if tx.exists_index(NATIONS_NAME_INDEX)? {
+            let old: Nation = S::deserialize(old_data.as_slice())?;
+            if old.name != nation.name {
+                tx.remove(NATIONS_NAME_INDEX, old.name.0, Some(id))?;
+                put_unique(&mut tx, NATIONS_NAME_INDEX, nation.name.0.clone(), id)?;
+            }
         }
-        Ok(success)
+        tx.prepare().map_err(map_duplicate_key)?.commit()?;
+        Ok(true)
     }
 
     fn update_town(&mut self, id: &TownId, town: &Town) -> Result<bool, Box<dyn Error>> {
+        let TownId::PersyKey(key) = id else {
+            return Ok(false);
+        };
+        let persy_id = PersyId::from_str(key)?;
         let mut tx = self.conn.begin()?;
-        let new_data = S::serialize(town)?;
-        let mut success = false;
-        if let TownId::PersyKey(key) = id {
-            success = tx
-                .update("Towns", &PersyId::from_str(key)?, &new_data)
-                .is_ok();
-            tx.prepare()?.commit()?;
+        let Some(old_data) = tx.read("Towns", &persy_id)? else {
+            return Ok(false);
+        };
+        tx.update("Towns", &persy_id, &S::serialize(town)?)?;
+        if tx.exists_index(TOWNS_NATION_NAME_INDEX)? {
+            let old: Town = S::deserialize(old_data.as_slice())?;
+            if town_name_key(&old) != town_name_key(town) {
+                tx.remove(TOWNS_NATION_NAME_INDEX, town_name_key(&old), Some(persy_id))?;
+                put_unique(
+                    &mut tx,
+                    TOWNS_NATION_NAME_INDEX,
+                    town_name_key(town),
+                    persy_id,
+                )?;
+            }
         }
-        Ok(success)
+        tx.prepare().map_err(map_duplicate_key)?.commit()?;
+        self.town_names.insert(id, &town.name.0);
+        Ok(true)
     }
 
     fn get_nation(&mut self, id: &NationId) -> Result<Option<Nation>, Box<dyn Error>> {
@@ -340,6 +464,25 @@ where
         )))
     }
 
+    fn filter_nations_by_iso_code(
+        &mut self,
+        iso_code: &NationCode,
+    ) -> Result<
+        Box<dyn Iterator<Item = Result<(NationId, Nation), Box<dyn Error>>> + '_>,
+        Box<dyn Error>,
+    > {
+        let iso_code = iso_code.clone();
+        Ok(Box::new(self.conn.scan("Nations")?.filter_map(
+            move |(k, v)| match S::deserialize::<Nation>(v.as_slice()) {
+                Ok(nation) if nation.iso_code == iso_code => {
+                    Some(Ok((NationId::PersyKey(k.to_string()), nation)))
+                }
+                Ok(_) => None,
+                Err(err) => Some(Err(err)),
+            },
+        )))
+    }
+
     fn filter_towns_by_name(
         &mut self,
         name: &TownName,
@@ -358,6 +501,27 @@ where
         )))
     }
 
+    fn filter_towns_by_population(
+        &mut self,
+        min_population: &Population,
+        max_population: &Population,
+    ) -> Result<Box<dyn Iterator<Item = Result<(TownId, Town), Box<dyn Error>>> + '_>, Box<dyn Error>>
+    {
+        let min_population = *min_population;
+        let max_population = *max_population;
+        Ok(Box::new(self.conn.scan("Towns")?.filter_map(
+            move |(k, v)| match S::deserialize::<Town>(v.as_slice()) {
+                Ok(town)
+                    if min_population <= town.population && town.population <= max_population =>
+                {
+                    Some(Ok((TownId::PersyKey(k.to_string()), town)))
+                }
+                Ok(_) => None,
+                Err(err) => Some(Err(err)),
+            },
+        )))
+    }
+
     fn filter_towns_by_lat_long(
         &mut self,
         min_lat: &Latitude,
@@ -386,26 +550,321 @@ where
             },
         )))
     }
+
+    fn search_towns_by_name(
+        &mut self,
+        text: &str,
+        name_match: &NameMatch,
+    ) -> Result<Box<dyn Iterator<Item = Result<(TownId, Town), Box<dyn Error>>> + '_>, Box<dyn Error>>
+    {
+        let mut candidates = vec![];
+        for id in self.town_names.candidates(text, name_match) {
+            if let TownId::PersyKey(key) = &id {
+                if let Some(v) = self.conn.read("Towns", &PersyId::from_str(key)?)? {
+                    candidates.push((id, S::deserialize::<Town>(v.as_slice())?));
+                }
+            }
+        }
+        Ok(Box::new(
+            sort_by_rank(candidates.into_iter(), text, name_match, |(_, town)| {
+                &town.name.0
+            })
+            .into_iter()
+            .map(Ok),
+        ))
+    }
+}
+
+impl<S> DbMaintenance for PersyConnection<S>
+where
+    S: Serder,
+{
+    fn open_unchanged(path: &str) -> Result<Self, Box<dyn Error>> {
+        PersyConnection::new(Persy::open(path, Config::new())?, path)
+    }
+
+    fn backup(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        // Every transaction is committed before the operations return,
+        // and so, while this connection is borrowed, the file is consistent.
+        // Creating the file fails if it exists, even if it is created meanwhile.
+        let mut backup = match OpenOptions::new().write(true).create_new(true).open(path) {
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+                return Err("Persy backup: file already exists.".into());
+            }
+            result => result?,
+        };
+        if let Err(error) = io::copy(&mut File::open(&self.path)?, &mut backup) {
+            let _ = std::fs::remove_file(path);
+            return Err(error.into());
+        }
+        Ok(())
+    }
+
+    fn check_integrity(&mut self) -> Result<Vec<IntegrityProblem>, Box<dyn Error>> {
+        let mut problems = vec![];
+        for segment in ["Nations", "Towns"] {
+            if !self.conn.exists_segment(segment)? {
+                problems.push(IntegrityProblem::Schema(format!(
+                    "Segment {segment} is missing."
+                )));
+            }
+        }
+        // The records of an older version would be reported as unreadable.
+        if !self.conn.exists_segment("Version")? {
+            problems.push(IntegrityProblem::Schema(format!(
+                "Segments version is 1 instead of {SEGMENTS_VERSION}."
+            )));
+        }
+        if !problems.is_empty() {
+            return Ok(problems);
+        }
+        for (k, v) in self.conn.scan("Version")? {
+            match S::deserialize::<u32>(v.as_slice()) {
+                Ok(SEGMENTS_VERSION) => {}
+                Ok(version) => problems.push(IntegrityProblem::Schema(format!(
+                    "Segments version is {version} instead of {SEGMENTS_VERSION}."
+                ))),
+                Err(err) => problems.push(IntegrityProblem::UnreadableRecord {
+                    segment: "Version".to_string(),
+                    key: k.to_string(),
+                    error: err.to_string(),
+                }),
+            }
+        }
+        let mut nation_ids = HashSet::new();
+        for (k, v) in self.conn.scan("Nations")? {
+            if let Err(err) = S::deserialize::<Nation>(v.as_slice()) {
+                problems.push(IntegrityProblem::UnreadableRecord {
+                    segment: "Nations".to_string(),
+                    key: k.to_string(),
+                    error: err.to_string(),
+                });
+            }
+            nation_ids.insert(NationId::PersyKey(k.to_string()));
+        }
+        for (k, v) in self.conn.scan("Towns")? {
+            match S::deserialize::<Town>(v.as_slice()) {
+                Ok(town) => {
+                    if !nation_ids.contains(&town.nation_id) {
+                        problems.push(IntegrityProblem::MissingNation {
+                            town_id: TownId::PersyKey(k.to_string()),
+                            nation_id: town.nation_id,
+                        });
+                    }
+                }
+                Err(err) => problems.push(IntegrityProblem::UnreadableRecord {
+                    segment: "Towns".to_string(),
+                    key: k.to_string(),
+                    error: err.to_string(),
+                }),
+            }
+        }
+        Ok(problems)
+    }
 }
 
 fn segments_exist(db: &Persy) -> Result<bool, Box<dyn std::error::Error>> {
     Ok(db.exists_segment("Nations")? && db.exists_segment("Towns")?)
 }
 
-fn create_segments(db: &Persy) -> Result<(), Box<dyn std::error::Error>> {
+/// Version of the layout of the records.
+/// The databases without the "Version" segment have version 1.
+const SEGMENTS_VERSION: u32 = 2;
+
+fn create_segments<S: Serder>(db: &Persy) -> Result<(), Box<dyn std::error::Error>> {
     let mut tx = db.begin()?;
     tx.create_segment("Nations")?;
     tx.create_segment("Towns")?;
+    tx.create_segment("Version")?;
+    tx.insert("Version", &S::serialize(&SEGMENTS_VERSION)?)?;
+    tx.prepare()?.commit()?;
+    Ok(())
+}
+
+/// Layout of the nation records in version 1.
+#[derive(serde::Deserialize)]
+struct NationV1 {
+    name: NationName,
+    capital_id: OptionalTownId,
+}
+
+/// Layout of the town records in version 1.
+#[derive(serde::Deserialize)]
+struct TownV1 {
+    name: TownName,
+    lat: Latitude,
+    long: Longitude,
+    nation_id: NationId,
+}
+
+/// Rewrites the records of an older database using the current layout.
+fn upgrade_segments<S: Serder>(db: &Persy) -> Result<(), Box<dyn std::error::Error>> {
+    if db.exists_segment("Version")? {
+        return Ok(());
+    }
+    let mut tx = db.begin()?;
+    for (id, v) in db.scan("Nations")? {
+        let old: NationV1 = S::deserialize(v.as_slice())?;
+        let nation = Nation {
+            name: old.name,
+            capital_id: old.capital_id,
+            iso_code: NationCode(String::new()),
+            area: Area(0.),
+        };
+        tx.update("Nations", &id, &S::serialize(&nation)?)?;
+    }
+    for (id, v) in db.scan("Towns")? {
+        let old: TownV1 = S::deserialize(v.as_slice())?;
+        let town = Town {
+            name: old.name,
+            lat: old.lat,
+            long: old.long,
+            nation_id: old.nation_id,
+            population: Population(0),
+            elevation: Elevation(0.),
+            alternate_names: vec![],
+        };
+        tx.update("Towns", &id, &S::serialize(&town)?)?;
+    }
+    tx.create_segment("Version")?;
+    tx.insert("Version", &S::serialize(&SEGMENTS_VERSION)?)?;
     tx.prepare()?.commit()?;
     Ok(())
 }
 
-fn truncate_segments(db: &Persy) -> Result<(), Box<dyn std::error::Error>> {
+/// Empties the database, which may have been written using another serialization format,
+/// and so the version is written again using the current one.
+fn truncate_segments<S: Serder>(db: &Persy) -> Result<(), Box<dyn std::error::Error>> {
     let mut tx = db.begin()?;
     tx.drop_segment("Nations")?;
     tx.drop_segment("Towns")?;
+    tx.drop_segment("Version")?;
     tx.create_segment("Nations")?;
     tx.create_segment("Towns")?;
+    tx.create_segment("Version")?;
+    tx.insert("Version", &S::serialize(&SEGMENTS_VERSION)?)?;
+    for index in [NATIONS_NAME_INDEX, TOWNS_NATION_NAME_INDEX] {
+        if tx.exists_index(index)? {
+            tx.drop_index(index)?;
+            tx.create_index::<String, PersyId>(index, ValueMode::Exclusive)?;
+        }
+    }
     tx.prepare()?.commit()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_access::{tests::TempPath, BincodeSerder, JsonSerder};
+
+    /// Layouts of the records in version 1, as they were written.
+    #[derive(serde::Serialize)]
+    struct WrittenNationV1 {
+        name: NationName,
+        capital_id: OptionalTownId,
+    }
+
+    #[derive(serde::Serialize)]
+    struct WrittenTownV1 {
+        name: TownName,
+        lat: Latitude,
+        long: Longitude,
+        nation_id: NationId,
+    }
+
+    /// Creates a database of version 1, having a nation and a town, and returns their keys.
+    fn create_version_1<S: Serder>(path: &str) -> (PersyId, PersyId) {
+        let db = Persy::open_or_create_with(path, Config::new(), |_| Ok(())).unwrap();
+        let mut tx = db.begin().unwrap();
+        tx.create_segment("Nations").unwrap();
+        tx.create_segment("Towns").unwrap();
+        let nation_key = tx
+            .insert(
+                "Nations",
+                &S::serialize(&WrittenNationV1 {
+                    name: NationName("Germany".to_string()),
+                    capital_id: OptionalTownId(None),
+                })
+                .unwrap(),
+            )
+            .unwrap();
+        let town_key = tx
+            .insert(
+                "Towns",
+                &S::serialize(&WrittenTownV1 {
+                    name: TownName("München".to_string()),
+                    lat: Latitude(48.1),
+                    long: Longitude(11.6),
+                    nation_id: NationId::PersyKey(nation_key.to_string()),
+                })
+                .unwrap(),
+            )
+            .unwrap();
+        tx.prepare().unwrap().commit().unwrap();
+        (nation_key, town_key)
+    }
+
+    fn check_upgrade<S: Serder>(path: &str) {
+        let (nation_key, town_key) = create_version_1::<S>(path);
+        let mut db = PersyConnection::<S>::open_existing(path).unwrap();
+        let nation = db
+            .get_nation(&NationId::PersyKey(nation_key.to_string()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(nation.name, NationName("Germany".to_string()));
+        assert_eq!(nation.iso_code, NationCode(String::new()));
+        let town = db
+            .get_town(&TownId::PersyKey(town_key.to_string()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(town.name, TownName("München".to_string()));
+        assert_eq!(town.population, Population(0));
+        assert_eq!(db.check_integrity().unwrap(), []);
+    }
+
+    #[test]
+    // A record which cannot be deserialized is reported, without preventing the opening.
+    fn check_unreadable_record() {
+        let path = TempPath::new("unreadable.persy");
+        let mut db = PersyConnection::<BincodeSerder>::create(&path.0).unwrap();
+        db.insert_nation(&Nation {
+            name: NationName("France".to_string()),
+            capital_id: OptionalTownId(None),
+            iso_code: NationCode("FR".to_string()),
+            area: Area(551_695.),
+        })
+        .unwrap();
+        drop(db);
+        let persy = Persy::open(&path.0, Config::new()).unwrap();
+        let mut tx = persy.begin().unwrap();
+        let key = tx.insert("Towns", &[0xff]).unwrap();
+        tx.prepare().unwrap().commit().unwrap();
+        drop(persy);
+        let problems = PersyConnection::<BincodeSerder>::open_unchanged(&path.0)
+            .unwrap()
+            .check_integrity()
+            .unwrap();
+        assert!(matches!(
+            &problems[..],
+            [IntegrityProblem::UnreadableRecord { segment, key: found_key, .. }]
+                if segment == "Towns" && *found_key == key.to_string()
+        ));
+        // With another serialization format, the version is unreadable too.
+        let problems = PersyConnection::<JsonSerder>::open_unchanged(&path.0)
+            .unwrap()
+            .check_integrity()
+            .unwrap();
+        assert!(matches!(
+            &problems[0],
+            IntegrityProblem::UnreadableRecord { segment, .. } if segment == "Version"
+        ));
+    }
+
+    #[test]
+    // The records of version 1 are rewritten with the new fields, and the version is stored.
+    fn upgrade_from_version_1() {
+        check_upgrade::<BincodeSerder>(&TempPath::new("upgrade_bincode.persy").0);
+        check_upgrade::<JsonSerder>(&TempPath::new("upgrade_json.persy").0);
+    }
+}
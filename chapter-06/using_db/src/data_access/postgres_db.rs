@@ -20,10 +20,15 @@ psql -U postgres -h localhost
 */
 
 use crate::data_access::{
-    DbConnection, Latitude, Longitude, Nation, NationId, NationName, OptionalTownId, Town, TownId,
-    TownName,
+    name_search::{normalize, sort_by_rank},
+    AlternateName, Area, DbConnection, DbError, Elevation, Latitude, Longitude, NameMatch, Nation,
+    NationCode, NationId, NationName, OptionalTownId, Population, Town, TownId, TownName,
+    UniqueNames, Upserted,
+};
+use postgres::{
+    error::SqlState, fallible_iterator::FallibleIterator, types::ToSql, Client, NoTls, Row,
+    RowIter, Transaction,
 };
-use postgres::{fallible_iterator::FallibleIterator, types::ToSql, Client, NoTls, Row, RowIter};
 
 /*
 fn value_or_null<'a, T: ToSql + Sync + 'a>(n: Option<T>) -> Box<dyn ToSql + Sync + 'a> {
@@ -345,28 +350,125 @@ impl PostgresConnection {
         })
     }
 
+    /// The trigram index of the search names needs the `pg_trgm` extension,
+    /// which only a privileged user can install in the database.
+    fn create_trigram_extension(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.conn
+            .batch_execute("CREATE EXTENSION IF NOT EXISTS pg_trgm")
+            .map_err(|err| {
+                format!("Postgresql: cannot create the pg_trgm extension for the town names index: {err}")
+            })?;
+        Ok(())
+    }
+
     fn create_tables(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.create_trigram_extension()?;
         let _ = self.conn.batch_execute(
             "CREATE TABLE Nations (
                 rowid BIGSERIAL PRIMARY KEY,
                 name VARCHAR(40) NOT NULL,
-                capital_id BIGINT NULL
+                capital_id BIGINT NULL,
+                iso_code VARCHAR(2) NOT NULL,
+                area DOUBLE PRECISION NOT NULL
             );
             CREATE TABLE Towns (
                 rowid BIGSERIAL PRIMARY KEY,
                 name VARCHAR(40) NOT NULL,
                 lat DOUBLE PRECISION NOT NULL,
                 long DOUBLE PRECISION NOT NULL,
-                nation_id BIGINT NOT NULL
-            );",
+                nation_id BIGINT NOT NULL,
+                search_name VARCHAR(40) NOT NULL,
+                population BIGINT NOT NULL,
+                elevation DOUBLE PRECISION NOT NULL
+            );
+            CREATE INDEX TownsSearchName ON Towns USING GIN (search_name gin_trgm_ops);
+            CREATE TABLE TownAlternateNames (
+                town_id BIGINT NOT NULL REFERENCES Towns (rowid) ON DELETE CASCADE,
+                language VARCHAR(3) NOT NULL,
+                name VARCHAR(40) NOT NULL
+            );
+            CREATE INDEX TownAlternateNamesTown ON TownAlternateNames (town_id);",
         )?;
         Ok(())
     }
 
+    fn column_exists(
+        &mut self,
+        table: &str,
+        column: &str,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self
+            .conn
+            .query_opt(
+                "SELECT COUNT(*) FROM information_schema.columns
+                WHERE table_schema = 'public'
+                AND table_name = $1 AND column_name = $2",
+                &[&table, &column],
+            )?
+            .ok_or(String::from("Postgresql column_exists: internal error."))?
+            .get::<_, i64>(0)
+            == 1)
+    }
+
+    /// Brings the tables of a database created by a previous version of this program
+    /// to the current layout, giving default values to the new columns.
+    fn upgrade_tables(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.ensure_search_index()?;
+        if !self.column_exists("nations", "iso_code")? {
+            self.conn.batch_execute(
+                "ALTER TABLE Nations ADD COLUMN iso_code VARCHAR(2) NOT NULL DEFAULT '';
+                ALTER TABLE Nations ADD COLUMN area DOUBLE PRECISION NOT NULL DEFAULT 0;
+                ALTER TABLE Towns ADD COLUMN population BIGINT NOT NULL DEFAULT 0;
+                ALTER TABLE Towns ADD COLUMN elevation DOUBLE PRECISION NOT NULL DEFAULT 0;
+                CREATE TABLE TownAlternateNames (
+                    town_id BIGINT NOT NULL REFERENCES Towns (rowid) ON DELETE CASCADE,
+                    language VARCHAR(3) NOT NULL,
+                    name VARCHAR(40) NOT NULL
+                );
+                CREATE INDEX TownAlternateNamesTown ON TownAlternateNames (town_id);",
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Databases created before the search column was introduced get it now.
+    /// It contains the town name without case and diacritics.
+    fn ensure_search_index(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.column_exists("towns", "search_name")? {
+            self.create_trigram_extension()?;
+            let mut tx = self.conn.transaction()?;
+            tx.batch_execute(
+                "ALTER TABLE Towns ADD COLUMN search_name VARCHAR(40) NOT NULL DEFAULT '';
+                CREATE INDEX TownsSearchName ON Towns USING GIN (search_name gin_trgm_ops);",
+            )?;
+            for row in tx.query("SELECT rowid, name FROM Towns", &[])? {
+                tx.execute(
+                    "UPDATE Towns SET search_name = $2 WHERE rowid = $1",
+                    &[&row.get::<_, i64>("rowid"), &normalize(row.get("name"))],
+                )?;
+            }
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    fn index_exists(&mut self, name: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self
+            .conn
+            .query_opt(
+                "SELECT COUNT(*) FROM pg_indexes
+                WHERE schemaname = 'public' AND indexname = $1",
+                &[&name],
+            )?
+            .ok_or(String::from("Postgresql index_exists: internal error."))?
+            .get::<_, i64>(0)
+            == 1)
+    }
+
     fn truncate_tables(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         let _ = self
             .conn
-            .batch_execute("TRUNCATE Nations, Towns RESTART IDENTITY;")?;
+            .batch_execute("TRUNCATE Nations, Towns, TownAlternateNames RESTART IDENTITY;")?;
         Ok(())
     }
 
@@ -385,6 +487,19 @@ impl PostgresConnection {
     }
 }
 
+/// The rules of `UniqueNames` are enforced by unique indexes,
+/// whose violations are reported as `DbError`.
+fn map_unique_violation(err: postgres::Error) -> Box<dyn std::error::Error> {
+    if err.code() == Some(&SqlState::UNIQUE_VIOLATION) {
+        match err.as_db_error().and_then(|db_error| db_error.table()) {
+            Some("nations") => return Box::new(DbError::DuplicateNationName),
+            Some("towns") => return Box::new(DbError::DuplicateTownName),
+            _ => {}
+        }
+    }
+    Box::new(err)
+}
+
 impl DbConnection for PostgresConnection {
     fn open_existing(options: &str) -> Result<Self, Box<dyn std::error::Error>>
     where
@@ -394,6 +509,7 @@ impl DbConnection for PostgresConnection {
         if !result.tables_exist()? {
             return Err("Postgresql open_existing: tables missing.".into());
         }
+        result.upgrade_tables()?;
         Ok(result)
     }
 
@@ -405,6 +521,7 @@ impl DbConnection for PostgresConnection {
         if !result.tables_exist()? {
             return Err("Postgresql open_existing_truncated: tables missing.".into());
         }
+        result.upgrade_tables()?;
         result.truncate_tables();
         Ok(result)
     }
@@ -417,7 +534,7 @@ impl DbConnection for PostgresConnection {
         if result.tables_exist()? {
             return Err("Postgresql create: tables already exist.".into());
         }
-        result.create_tables();
+        result.create_tables()?;
         Ok(result)
     }
 
@@ -427,7 +544,9 @@ impl DbConnection for PostgresConnection {
     {
         let mut result = Self::create_connection(options)?;
         if !result.tables_exist()? {
-            result.create_tables();
+            result.create_tables()?;
+        } else {
+            result.upgrade_tables()?;
         }
         Ok(result)
     }
@@ -438,40 +557,204 @@ impl DbConnection for PostgresConnection {
     {
         let mut result = Self::create_connection(options)?;
         if result.tables_exist()? {
+            result.upgrade_tables()?;
             result.truncate_tables();
         } else {
-            result.create_tables();
+            result.create_tables()?;
         }
         Ok(result)
     }
 
+    fn unique_names(&mut self) -> Result<UniqueNames, Box<dyn std::error::Error>> {
+        Ok(UniqueNames {
+            nation_name: self.index_exists("nationsname")?,
+            town_name_per_nation: self.index_exists("townsnationname")?,
+        })
+    }
+
+    fn set_unique_names(
+        &mut self,
+        unique_names: &UniqueNames,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if unique_names.nation_name {
+            self.conn
+                .batch_execute("CREATE UNIQUE INDEX IF NOT EXISTS NationsName ON Nations (name)")
+                .map_err(map_unique_violation)?;
+        } else {
+            self.conn
+                .batch_execute("DROP INDEX IF EXISTS NationsName")?;
+        }
+        if unique_names.town_name_per_nation {
+            self.conn
+                .batch_execute(
+                    "CREATE UNIQUE INDEX IF NOT EXISTS TownsNationName ON Towns (nation_id, name)",
+                )
+                .map_err(map_unique_violation)?;
+        } else {
+            self.conn
+                .batch_execute("DROP INDEX IF EXISTS TownsNationName")?;
+        }
+        Ok(())
+    }
+
     fn insert_nation(&mut self, nation: &Nation) -> Result<NationId, Box<dyn std::error::Error>> {
-        let result = self.conn.query_one(
-            "INSERT INTO Nations (
-                name, capital_id
+        let result = self
+            .conn
+            .query_one(
+                "INSERT INTO Nations (
+                name, capital_id, iso_code, area
             ) VALUES (
-                $1, $2
+                $1, $2, $3, $4
             ) RETURNING rowid",
-            &[&nation.name.0, &*value_or_null(&nation.capital_id)],
-        )?;
+                &[
+                    &nation.name.0,
+                    &*value_or_null(&nation.capital_id),
+                    &nation.iso_code.0,
+                    &nation.area.0,
+                ],
+            )
+            .map_err(map_unique_violation)?;
         Ok(NationId::BigSerial(result.get(0)))
     }
 
     fn insert_town(&mut self, town: &Town) -> Result<TownId, Box<dyn std::error::Error>> {
-        let result = self.conn.query_one(
-            "INSERT INTO Towns (
-                name, lat, long, nation_id
+        let mut tx = self.conn.transaction()?;
+        let result = tx
+            .query_one(
+                "INSERT INTO Towns (
+                name, lat, long, nation_id, search_name, population, elevation
             ) VALUES (
-                $1, $2, $3, $4
+                $1, $2, $3, $4, $5, $6, $7
             ) RETURNING rowid",
-            &[
-                &town.name.0,
-                &town.lat.0,
-                &town.long.0,
-                &town.nation_id.to_i64(),
-            ],
-        )?;
-        Ok(TownId::BigSerial(result.get(0)))
+                &[
+                    &town.name.0,
+                    &town.lat.0,
+                    &town.long.0,
+                    &town.nation_id.to_i64(),
+                    &normalize(&town.name.0),
+                    &(town.population.0 as i64),
+                    &town.elevation.0,
+                ],
+            )
+            .map_err(map_unique_violation)?;
+        let id = result.get(0);
+        write_alternate_names(&mut tx, id, &town.alternate_names)?;
+        tx.commit()?;
+        Ok(TownId::BigSerial(id))
+    }
+
+    fn upsert_nation(
+        &mut self,
+        nation: &Nation,
+    ) -> Result<(NationId, Upserted), Box<dyn std::error::Error>> {
+        let unique_name = self.index_exists("nationsname")?;
+        let mut tx = self.conn.transaction()?;
+        let params: [&(dyn ToSql + Sync); 4] = [
+            &nation.name.0,
+            &*value_or_null(&nation.capital_id),
+            &nation.iso_code.0,
+            &nation.area.0,
+        ];
+        // A row inserted by this transaction has no deleting transaction yet.
+        let row = if unique_name {
+            tx.query_one(
+                "INSERT INTO Nations (
+                    name, capital_id, iso_code, area
+                ) VALUES (
+                    $1, $2, $3, $4
+                ) ON CONFLICT (name) DO UPDATE SET
+                    capital_id = EXCLUDED.capital_id,
+                    iso_code = EXCLUDED.iso_code,
+                    area = EXCLUDED.area
+                RETURNING rowid, xmax = 0 AS inserted",
+                &params,
+            )?
+        } else {
+            // Without a unique index, the lock keeps the other connections
+            // from writing the same name meanwhile.
+            tx.batch_execute("LOCK TABLE Nations IN SHARE ROW EXCLUSIVE MODE")?;
+            match tx.query_opt(
+                "UPDATE Nations SET
+                    capital_id = $2,
+                    iso_code = $3,
+                    area = $4
+                WHERE rowid = (SELECT MIN(rowid) FROM Nations WHERE name = $1)
+                RETURNING rowid, FALSE AS inserted",
+                &params,
+            )? {
+                Some(row) => row,
+                None => tx.query_one(
+                    "INSERT INTO Nations (
+                        name, capital_id, iso_code, area
+                    ) VALUES (
+                        $1, $2, $3, $4
+                    ) RETURNING rowid, TRUE AS inserted",
+                    &params,
+                )?,
+            }
+        };
+        tx.commit()?;
+        Ok((NationId::BigSerial(row.get("rowid")), upserted(&row)))
+    }
+
+    fn upsert_town(
+        &mut self,
+        town: &Town,
+    ) -> Result<(TownId, Upserted), Box<dyn std::error::Error>> {
+        let unique_name = self.index_exists("townsnationname")?;
+        let mut tx = self.conn.transaction()?;
+        let params: [&(dyn ToSql + Sync); 7] = [
+            &town.name.0,
+            &town.lat.0,
+            &town.long.0,
+            &town.nation_id.to_i64(),
+            &normalize(&town.name.0),
+            &(town.population.0 as i64),
+            &town.elevation.0,
+        ];
+        let row = if unique_name {
+            tx.query_one(
+                "INSERT INTO Towns (
+                    name, lat, long, nation_id, search_name, population, elevation
+                ) VALUES (
+                    $1, $2, $3, $4, $5, $6, $7
+                ) ON CONFLICT (nation_id, name) DO UPDATE SET
+                    lat = EXCLUDED.lat,
+                    long = EXCLUDED.long,
+                    search_name = EXCLUDED.search_name,
+                    population = EXCLUDED.population,
+                    elevation = EXCLUDED.elevation
+                RETURNING rowid, xmax = 0 AS inserted",
+                &params,
+            )?
+        } else {
+            tx.batch_execute("LOCK TABLE Towns IN SHARE ROW EXCLUSIVE MODE")?;
+            match tx.query_opt(
+                "UPDATE Towns SET
+                    lat = $2,
+                    long = $3,
+                    search_name = $5,
+                    population = $6,
+                    elevation = $7
+                WHERE rowid = (SELECT MIN(rowid) FROM Towns WHERE nation_id = $4 AND name = $1)
+                RETURNING rowid, FALSE AS inserted",
+                &params,
+            )? {
+                Some(row) => row,
+                None => tx.query_one(
+                    "INSERT INTO Towns (
+                        name, lat, long, nation_id, search_name, population, elevation
+                    ) VALUES (
+                        $1, $2, $3, $4, $5, $6, $7
+                    ) RETURNING rowid, TRUE AS inserted",
+                    &params,
+                )?,
+            }
+        };
+        let id = row.get("rowid");
+        write_alternate_names(&mut tx, id, &town.alternate_names)?;
+        tx.commit()?;
+        Ok((TownId::BigSerial(id), upserted(&row)))
     }
 
     fn delete_nation(&mut self, id: &NationId) -> Result<bool, Box<dyn std::error::Error>> {
@@ -495,17 +778,24 @@ impl DbConnection for PostgresConnection {
         id: &NationId,
         nation: &Nation,
     ) -> Result<bool, Box<dyn std::error::Error>> {
-        let updated_lines = self.conn.execute(
-            "UPDATE Nations SET
+        let updated_lines = self
+            .conn
+            .execute(
+                "UPDATE Nations SET
                 name = $2,
-                capital_id = $3
+                capital_id = $3,
+                iso_code = $4,
+                area = $5
             WHERE rowid = $1",
-            &[
-                &id.to_i64(),
-                &nation.name.0,
-                &*value_or_null(&nation.capital_id),
-            ],
-        )?;
+                &[
+                    &id.to_i64(),
+                    &nation.name.0,
+                    &*value_or_null(&nation.capital_id),
+                    &nation.iso_code.0,
+                    &nation.area.0,
+                ],
+            )
+            .map_err(map_unique_violation)?;
         Ok(updated_lines == 1)
     }
 
@@ -514,38 +804,47 @@ impl DbConnection for PostgresConnection {
         id: &TownId,
         town: &Town,
     ) -> Result<bool, Box<dyn std::error::Error>> {
-        let updated_lines = self.conn.execute(
-            "UPDATE Towns SET
+        let mut tx = self.conn.transaction()?;
+        let updated_lines = tx
+            .execute(
+                "UPDATE Towns SET
                 name = $2,
                 lat = $3,
                 long = $4,
-                nation_id = $5
+                nation_id = $5,
+                search_name = $6,
+                population = $7,
+                elevation = $8
                 WHERE rowid = $1",
-            &[
-                &id.to_i64(),
-                &town.name.0,
-                &town.lat.0,
-                &town.long.0,
-                &town.nation_id.to_i64(),
-            ],
-        )?;
+                &[
+                    &id.to_i64(),
+                    &town.name.0,
+                    &town.lat.0,
+                    &town.long.0,
+                    &town.nation_id.to_i64(),
+                    &normalize(&town.name.0),
+                    &(town.population.0 as i64),
+                    &town.elevation.0,
+                ],
+            )
+            .map_err(map_unique_violation)?;
+        if updated_lines == 1 {
+            write_alternate_names(&mut tx, id.to_i64(), &town.alternate_names)?;
+        }
+        tx.commit()?;
         Ok(updated_lines == 1)
     }
 
     fn get_nation(&mut self, id: &NationId) -> Result<Option<Nation>, Box<dyn std::error::Error>> {
         Ok(
             match self.conn.query_opt(
-                "SELECT * FROM Nations
-                WHERE rowid = $1",
+                &format!(
+                    "SELECT {NATION_COLUMNS} FROM Nations
+                    WHERE rowid = $1"
+                ),
                 &[&id.to_i64()],
             )? {
-                Some(result) => Some(Nation {
-                    name: NationName(result.get("name")),
-                    capital_id: OptionalTownId(match result.get("capital_id") {
-                        Some(id) => Some(TownId::BigSerial(id)),
-                        None => None,
-                    }),
-                }),
+                Some(result) => Some(nation_from_row(&result)?.1),
                 None => None,
             },
         )
@@ -554,16 +853,13 @@ impl DbConnection for PostgresConnection {
     fn get_town(&mut self, id: &TownId) -> Result<Option<Town>, Box<dyn std::error::Error>> {
         Ok(
             match self.conn.query_opt(
-                "SELECT * FROM Towns
-                WHERE rowid = $1",
+                &format!(
+                    "SELECT {TOWN_COLUMNS} FROM Towns
+                    WHERE rowid = $1"
+                ),
                 &[&id.to_i64()],
             )? {
-                Some(result) => Some(Town {
-                    name: TownName(result.get("name")),
-                    lat: Latitude(result.get("lat")),
-                    long: Longitude(result.get("long")),
-                    nation_id: NationId::BigSerial(result.get("nation_id")),
-                }),
+                Some(result) => Some(town_from_row(&result)?.1),
                 None => None,
             },
         )
@@ -590,29 +886,35 @@ impl DbConnection for PostgresConnection {
         Box<dyn Iterator<Item = Result<(NationId, Nation), Box<dyn std::error::Error>>> + '_>,
         Box<dyn std::error::Error>,
     > {
-        let it = self.conn.query_raw(
-            "SELECT rowid, name, capital_id FROM Nations
-            WHERE name = $1",
+        let row_iter = self.conn.query_raw(
+            &format!(
+                "SELECT {NATION_COLUMNS} FROM Nations
+                WHERE name = $1"
+            ),
             [name.0.clone()].iter(),
-        );
-        match it {
-            Ok(row_iter) => Ok(Box::new(row_iter_to_row_iterator(row_iter).map(
-                |row_error: Result<Row, postgres::Error>| match row_error {
-                    Ok(row) => Ok((
-                        NationId::BigSerial(row.get("rowid")),
-                        Nation {
-                            name: NationName(row.get("name")),
-                            capital_id: OptionalTownId(match row.get("capital_id") {
-                                Some(id) => Some(TownId::BigSerial(id)),
-                                None => None,
-                            }),
-                        },
-                    )),
-                    Err(error) => Err(Box::new(error) as Box<dyn std::error::Error>),
-                },
-            ))),
-            Err(error) => Err(Box::new(error)),
-        }
+        )?;
+        Ok(Box::new(
+            row_iter_to_row_iterator(row_iter).map(|row| nation_from_row(&row?)),
+        ))
+    }
+
+    fn filter_nations_by_iso_code(
+        &mut self,
+        iso_code: &NationCode,
+    ) -> Result<
+        Box<dyn Iterator<Item = Result<(NationId, Nation), Box<dyn std::error::Error>>> + '_>,
+        Box<dyn std::error::Error>,
+    > {
+        let row_iter = self.conn.query_raw(
+            &format!(
+                "SELECT {NATION_COLUMNS} FROM Nations
+                WHERE iso_code = $1"
+            ),
+            [iso_code.0.clone()].iter(),
+        )?;
+        Ok(Box::new(
+            row_iter_to_row_iterator(row_iter).map(|row| nation_from_row(&row?)),
+        ))
     }
 
     fn filter_towns_by_name(
@@ -622,28 +924,36 @@ impl DbConnection for PostgresConnection {
         Box<dyn Iterator<Item = Result<(TownId, Town), Box<dyn std::error::Error>>> + '_>,
         Box<dyn std::error::Error>,
     > {
-        let it = self.conn.query_raw(
-            "SELECT rowid, name, lat, long, nation_id FROM Towns
-            WHERE name = $1",
+        let row_iter = self.conn.query_raw(
+            &format!(
+                "SELECT {TOWN_COLUMNS} FROM Towns
+                WHERE name = $1"
+            ),
             [name.0.clone()].iter(),
-        );
-        match it {
-            Ok(row_iter) => Ok(Box::new(row_iter_to_row_iterator(row_iter).map(
-                |row_error: Result<Row, postgres::Error>| match row_error {
-                    Ok(row) => Ok((
-                        TownId::BigSerial(row.get("rowid")),
-                        Town {
-                            name: TownName(row.get("name")),
-                            lat: Latitude(row.get("lat")),
-                            long: Longitude(row.get("long")),
-                            nation_id: NationId::BigSerial(row.get("nation_id")),
-                        },
-                    )),
-                    Err(error) => Err(Box::new(error) as Box<dyn std::error::Error>),
-                },
-            ))),
-            Err(error) => Err(Box::new(error)),
-        }
+        )?;
+        Ok(Box::new(
+            row_iter_to_row_iterator(row_iter).map(|row| town_from_row(&row?)),
+        ))
+    }
+
+    fn filter_towns_by_population(
+        &mut self,
+        min_population: &Population,
+        max_population: &Population,
+    ) -> Result<
+        Box<dyn Iterator<Item = Result<(TownId, Town), Box<dyn std::error::Error>>> + '_>,
+        Box<dyn std::error::Error>,
+    > {
+        let row_iter = self.conn.query_raw(
+            &format!(
+                "SELECT {TOWN_COLUMNS} FROM Towns
+                WHERE $1 <= population AND population <= $2"
+            ),
+            [min_population.0 as i64, max_population.0 as i64].iter(),
+        )?;
+        Ok(Box::new(
+            row_iter_to_row_iterator(row_iter).map(|row| town_from_row(&row?)),
+        ))
     }
 
     fn filter_towns_by_lat_long(
@@ -656,30 +966,167 @@ impl DbConnection for PostgresConnection {
         Box<dyn Iterator<Item = Result<(TownId, Town), Box<dyn std::error::Error>>> + '_>,
         Box<dyn std::error::Error>,
     > {
-        let it = self.conn.query_raw(
-            "SELECT rowid, name, lat, long, nation_id FROM Towns
-            WHERE $1 <= lat AND lat <= $2
-            AND $3 <= long AND long <= $4",
+        let row_iter = self.conn.query_raw(
+            &format!(
+                "SELECT {TOWN_COLUMNS} FROM Towns
+                WHERE $1 <= lat AND lat <= $2
+                AND $3 <= long AND long <= $4"
+            ),
             [min_lat.0, max_lat.0, min_long.0, max_long.0].iter(),
-        );
-        match it {
-            Ok(row_iter) => Ok(Box::new(row_iter_to_row_iterator(row_iter).map(
-                |row_error: Result<Row, postgres::Error>| match row_error {
-                    Ok(row) => Ok((
-                        TownId::BigSerial(row.get("rowid")),
-                        Town {
-                            name: TownName(row.get("name")),
-                            lat: Latitude(row.get("lat")),
-                            long: Longitude(row.get("long")),
-                            nation_id: NationId::BigSerial(row.get("nation_id")),
-                        },
-                    )),
-                    Err(error) => Err(Box::new(error) as Box<dyn std::error::Error>),
-                },
-            ))),
-            Err(error) => Err(Box::new(error)),
-        }
+        )?;
+        Ok(Box::new(
+            row_iter_to_row_iterator(row_iter).map(|row| town_from_row(&row?)),
+        ))
+    }
+
+    fn search_towns_by_name(
+        &mut self,
+        text: &str,
+        name_match: &NameMatch,
+    ) -> Result<
+        Box<dyn Iterator<Item = Result<(TownId, Town), Box<dyn std::error::Error>>> + '_>,
+        Box<dyn std::error::Error>,
+    > {
+        let normalized_text = normalize(text);
+        let rows = match name_match {
+            NameMatch::Exact => self.conn.query(
+                &format!(
+                    "SELECT {TOWN_COLUMNS} FROM Towns
+                    WHERE search_name = $1"
+                ),
+                &[&normalized_text],
+            )?,
+            // The words inside the names are checked by `sort_by_rank`.
+            NameMatch::Prefix => self.conn.query(
+                &format!(
+                    "SELECT {TOWN_COLUMNS} FROM Towns
+                    WHERE search_name LIKE '%' || $1 || '%'"
+                ),
+                &[&normalized_text
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_")],
+            )?,
+            // The trigram index picks the candidates, and `sort_by_rank` checks their edit distance.
+            NameMatch::Fuzzy(max_distance) => {
+                match fuzzy_similarity_threshold(&normalized_text, *max_distance) {
+                    Some(threshold) => {
+                        let mut tx = self.conn.transaction()?;
+                        tx.batch_execute(&format!(
+                            "SET LOCAL pg_trgm.word_similarity_threshold = {threshold}"
+                        ))?;
+                        let rows = tx.query(
+                            &format!(
+                                "SELECT {TOWN_COLUMNS} FROM Towns
+                                WHERE $1 <% search_name"
+                            ),
+                            &[&normalized_text],
+                        )?;
+                        tx.commit()?;
+                        rows
+                    }
+                    None => self
+                        .conn
+                        .query(&format!("SELECT {TOWN_COLUMNS} FROM Towns"), &[])?,
+                }
+            }
+        };
+        let candidates = rows
+            .iter()
+            .map(town_from_row)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Box::new(
+            sort_by_rank(candidates.into_iter(), text, name_match, |(_, town)| {
+                &town.name.0
+            })
+            .into_iter()
+            .map(Ok),
+        ))
+    }
+}
+
+const NATION_COLUMNS: &str = "rowid, name, capital_id, iso_code, area";
+
+/// The alternate names are collected in a JSON array.
+const TOWN_COLUMNS: &str = "rowid, name, lat, long, nation_id, population, elevation,
+    (SELECT COALESCE(json_agg(json_build_object('language', language, 'name', name)
+        ORDER BY language, name), '[]')::TEXT
+    FROM TownAlternateNames WHERE town_id = Towns.rowid) AS alternate_names";
+
+fn nation_from_row(row: &Row) -> Result<(NationId, Nation), Box<dyn std::error::Error>> {
+    Ok((
+        NationId::BigSerial(row.try_get("rowid")?),
+        Nation {
+            name: NationName(row.try_get("name")?),
+            capital_id: OptionalTownId(
+                row.try_get::<_, Option<i64>>("capital_id")?
+                    .map(TownId::BigSerial),
+            ),
+            iso_code: NationCode(row.try_get("iso_code")?),
+            area: Area(row.try_get("area")?),
+        },
+    ))
+}
+
+/// Returns a word similarity below that of any name within the edit distance of the text,
+/// or `None` if every name may be within it.
+/// The text has a trigram more than characters, and every edit changes at most
+/// three of them, or four when it separates words; so this bound is lenient.
+fn fuzzy_similarity_threshold(normalized_text: &str, max_distance: usize) -> Option<f64> {
+    let n_trigrams = normalized_text.chars().count() + 1;
+    let n_changed_trigrams = 4 * max_distance;
+    if n_changed_trigrams >= n_trigrams {
+        None
+    } else {
+        Some((n_trigrams - n_changed_trigrams) as f64 / n_trigrams as f64 - 0.01)
+    }
+}
+
+fn town_from_row(row: &Row) -> Result<(TownId, Town), Box<dyn std::error::Error>> {
+    Ok((
+        TownId::BigSerial(row.try_get("rowid")?),
+        Town {
+            name: TownName(row.try_get("name")?),
+            lat: Latitude(row.try_get("lat")?),
+            long: Longitude(row.try_get("long")?),
+            nation_id: NationId::BigSerial(row.try_get("nation_id")?),
+            population: Population(row.try_get::<_, i64>("population")? as u64),
+            elevation: Elevation(row.try_get("elevation")?),
+            alternate_names: serde_json::from_str(row.try_get("alternate_names")?)?,
+        },
+    ))
+}
+
+/// Tells what an upsert returning the `inserted` column has done.
+fn upserted(row: &Row) -> Upserted {
+    if row.get("inserted") {
+        Upserted::Inserted
+    } else {
+        Upserted::Updated
+    }
+}
+
+/// Replaces the alternate names of the specified town.
+fn write_alternate_names(
+    tx: &mut Transaction,
+    town_id: i64,
+    alternate_names: &[AlternateName],
+) -> Result<(), Box<dyn std::error::Error>> {
+    tx.execute(
+        "DELETE FROM TownAlternateNames WHERE town_id = $1",
+        &[&town_id],
+    )?;
+    for alternate_name in alternate_names {
+        tx.execute(
+            "INSERT INTO TownAlternateNames (
+                town_id, language, name
+            ) VALUES (
+                $1, $2, $3
+            )",
+            &[&town_id, &alternate_name.language.0, &alternate_name.name.0],
+        )?;
     }
+    Ok(())
 }
 
 struct RowIterator<'a> {
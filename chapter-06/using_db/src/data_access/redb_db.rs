@@ -0,0 +1,681 @@
+use crate::data_access::{
+    name_search::{sort_by_rank, NameIndex},
+    DbConnection, DbError, Latitude, Longitude, NameMatch, Nation, NationCode, NationId,
+    NationName, Population, Serder, Town, TownId, TownName, UniqueNames,
+};
+use redb::{
+    Database, MultimapTableDefinition, ReadableMultimapTable, ReadableTable, TableDefinition,
+    TableError, WriteTransaction,
+};
+use std::collections::HashSet;
+use std::error::Error;
+
+/// The records, serialized by the `Serder`, by id.
+const NATIONS: TableDefinition<u64, &[u8]> = TableDefinition::new("Nations");
+const TOWNS: TableDefinition<u64, &[u8]> = TableDefinition::new("Towns");
+
+/// Secondary indexes, from a field to the ids of the records.
+const NATIONS_BY_NAME: MultimapTableDefinition<&str, u64> =
+    MultimapTableDefinition::new("NationsByName");
+const TOWNS_BY_NAME: MultimapTableDefinition<&str, u64> =
+    MultimapTableDefinition::new("TownsByName");
+const TOWNS_BY_LATITUDE: MultimapTableDefinition<u64, u64> =
+    MultimapTableDefinition::new("TownsByLatitude");
+const TOWNS_BY_LONGITUDE: MultimapTableDefinition<u64, u64> =
+    MultimapTableDefinition::new("TownsByLongitude");
+
+/// Settings of the database, independent from the serialization format,
+/// and the next ids, which are never reused, even after deleting the last records.
+const METADATA: TableDefinition<&str, u64> = TableDefinition::new("Metadata");
+
+/// Version of the layout of the tables, stored in `METADATA`.
+const TABLES_VERSION: u64 = 1;
+
+/// Maps a coordinate to a key having the same order,
+/// as the index keys are compared as integers.
+fn coordinate_key(value: f64) -> u64 {
+    // Adding zero turns -0 into 0.
+    let bits = (value + 0.).to_bits();
+    if bits >> 63 == 0 {
+        bits | 1 << 63
+    } else {
+        !bits
+    }
+}
+
+pub struct RedbConnection<S>
+where
+    S: Serder,
+{
+    conn: Database,
+    town_names: NameIndex<TownId>,
+    unique_names: UniqueNames,
+    phantom: std::marker::PhantomData<S>,
+}
+
+impl<S> RedbConnection<S>
+where
+    S: Serder,
+{
+    fn new(db: Database) -> Result<Self, Box<dyn std::error::Error>> {
+        let tx = db.begin_read()?;
+        let mut town_names = NameIndex::new();
+        // As with Persy, a town record which cannot be read is left out of the index,
+        // and fails only the operations reading it.
+        for row in tx.open_table(TOWNS)?.range::<u64>(..)? {
+            let (k, v) = row?;
+            if let Ok(town) = S::deserialize::<Town>(v.value()) {
+                town_names.insert(&TownId::BigSerial(k.value() as i64), &town.name.0);
+            }
+        }
+        let metadata = tx.open_table(METADATA)?;
+        let unique_names = UniqueNames {
+            nation_name: metadata
+                .get("unique_nation_name")?
+                .is_some_and(|v| v.value() == 1),
+            town_name_per_nation: metadata
+                .get("unique_town_name_per_nation")?
+                .is_some_and(|v| v.value() == 1),
+        };
+        drop(tx);
+        Ok(Self {
+            conn: db,
+            town_names,
+            unique_names,
+            phantom: std::marker::PhantomData::<S>,
+        })
+    }
+
+    /// Checks the uniqueness rules for a nation going to be written with the specified id.
+    fn check_nation(
+        &self,
+        by_name: &impl ReadableMultimapTable<&'static str, u64>,
+        id: u64,
+        nation: &Nation,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.unique_names.nation_name {
+            for other_id in by_name.get(nation.name.0.as_str())? {
+                if other_id?.value() != id {
+                    return Err(Box::new(DbError::DuplicateNationName));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the uniqueness rules for a town going to be written with the specified id.
+    fn check_town(
+        &self,
+        towns: &impl ReadableTable<u64, &'static [u8]>,
+        by_name: &impl ReadableMultimapTable<&'static str, u64>,
+        id: u64,
+        town: &Town,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.unique_names.town_name_per_nation {
+            for other_id in by_name.get(town.name.0.as_str())? {
+                let other_id = other_id?.value();
+                if other_id == id {
+                    continue;
+                }
+                if let Some(v) = towns.get(other_id)? {
+                    if S::deserialize::<Town>(v.value())?.nation_id == town.nation_id {
+                        return Err(Box::new(DbError::DuplicateTownName));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the ids of the towns whose coordinate is in the range of keys of the index.
+    fn find_towns(
+        &self,
+        index: MultimapTableDefinition<u64, u64>,
+        min: f64,
+        max: f64,
+    ) -> Result<HashSet<u64>, Box<dyn Error>> {
+        let mut ids = HashSet::new();
+        for row in self
+            .conn
+            .begin_read()?
+            .open_multimap_table(index)?
+            .range(coordinate_key(min)..=coordinate_key(max))?
+        {
+            for id in row?.1 {
+                ids.insert(id?.value());
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Returns the towns having the specified ids, skipping the missing ones.
+    fn read_towns(
+        &self,
+        ids: impl Iterator<Item = u64>,
+    ) -> Result<Vec<(TownId, Town)>, Box<dyn Error>> {
+        let towns = self.conn.begin_read()?.open_table(TOWNS)?;
+        let mut result = vec![];
+        for id in ids {
+            if let Some(v) = towns.get(id)? {
+                result.push((TownId::BigSerial(id as i64), S::deserialize(v.value())?));
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl<S> DbConnection for RedbConnection<S>
+where
+    S: Serder,
+{
+    fn open_existing(options: &str) -> Result<Self, Box<dyn std::error::Error>>
+    where
+        Self: Sized,
+    {
+        let db = Database::open(options)?;
+        if !tables_exist(&db)? {
+            return Err("Redb open_existing: tables missing.".into());
+        }
+        RedbConnection::new(db)
+    }
+
+    fn open_existing_truncated(options: &str) -> Result<Self, Box<dyn std::error::Error>>
+    where
+        Self: Sized,
+    {
+        let db = Database::open(options)?;
+        if !tables_exist(&db)? {
+            return Err("Redb open_existing_truncated: tables missing.".into());
+        }
+        truncate_tables(&db)?;
+        RedbConnection::new(db)
+    }
+
+    fn create(options: &str) -> Result<Self, Box<dyn std::error::Error>>
+    where
+        Self: Sized,
+    {
+        if std::path::Path::new(options).exists() {
+            return Err("Redb create: file already exists.".into());
+        }
+        let db = Database::create(options)?;
+        create_tables(&db)?;
+        RedbConnection::new(db)
+    }
+
+    fn open_or_create(options: &str) -> Result<Self, Box<dyn std::error::Error>>
+    where
+        Self: Sized,
+    {
+        let db = Database::create(options)?;
+        if !tables_exist(&db)? {
+            create_tables(&db)?;
+        }
+        RedbConnection::new(db)
+    }
+
+    fn open_truncated_or_create(options: &str) -> Result<Self, Box<dyn std::error::Error>>
+    where
+        Self: Sized,
+    {
+        let db = Database::create(options)?;
+        if tables_exist(&db)? {
+            truncate_tables(&db)?;
+        } else {
+            create_tables(&db)?;
+        }
+        RedbConnection::new(db)
+    }
+
+    fn unique_names(&mut self) -> Result<UniqueNames, Box<dyn Error>> {
+        Ok(self.unique_names)
+    }
+
+    fn set_unique_names(&mut self, unique_names: &UniqueNames) -> Result<(), Box<dyn Error>> {
+        let tx = self.conn.begin_write()?;
+        {
+            if unique_names.nation_name {
+                for row in tx.open_multimap_table(NATIONS_BY_NAME)?.iter()? {
+                    if row?.1.len() > 1 {
+                        return Err(Box::new(DbError::DuplicateNationName));
+                    }
+                }
+            }
+            if unique_names.town_name_per_nation {
+                let towns = tx.open_table(TOWNS)?;
+                for row in tx.open_multimap_table(TOWNS_BY_NAME)?.iter()? {
+                    let mut nation_ids = vec![];
+                    for id in row?.1 {
+                        if let Some(v) = towns.get(id?.value())? {
+                            nation_ids.push(S::deserialize::<Town>(v.value())?.nation_id);
+                        }
+                    }
+                    for (i, nation_id) in nation_ids.iter().enumerate() {
+                        if nation_ids[..i].contains(nation_id) {
+                            return Err(Box::new(DbError::DuplicateTownName));
+                        }
+                    }
+                }
+            }
+            let mut metadata = tx.open_table(METADATA)?;
+            metadata.insert("unique_nation_name", unique_names.nation_name as u64)?;
+            metadata.insert(
+                "unique_town_name_per_nation",
+                unique_names.town_name_per_nation as u64,
+            )?;
+        }
+        tx.commit()?;
+        self.unique_names = *unique_names;
+        Ok(())
+    }
+
+    fn insert_nation(&mut self, nation: &Nation) -> Result<NationId, Box<dyn Error>> {
+        let tx = self.conn.begin_write()?;
+        let id;
+        {
+            let mut nations = tx.open_table(NATIONS)?;
+            let mut by_name = tx.open_multimap_table(NATIONS_BY_NAME)?;
+            id = next_id(&tx, "next_nation_id", &nations)?;
+            self.check_nation(&by_name, id, nation)?;
+            nations.insert(id, S::serialize(nation)?.as_slice())?;
+            by_name.insert(nation.name.0.as_str(), id)?;
+        }
+        tx.commit()?;
+        Ok(NationId::BigSerial(id as i64))
+    }
+
+    fn insert_town(&mut self, town: &Town) -> Result<TownId, Box<dyn Error>> {
+        let tx = self.conn.begin_write()?;
+        let id;
+        {
+            let mut towns = tx.open_table(TOWNS)?;
+            let mut by_name = tx.open_multimap_table(TOWNS_BY_NAME)?;
+            let mut by_latitude = tx.open_multimap_table(TOWNS_BY_LATITUDE)?;
+            let mut by_longitude = tx.open_multimap_table(TOWNS_BY_LONGITUDE)?;
+            id = next_id(&tx, "next_town_id", &towns)?;
+            self.check_town(&towns, &by_name, id, town)?;
+            towns.insert(id, S::serialize(town)?.as_slice())?;
+            by_name.insert(town.name.0.as_str(), id)?;
+            by_latitude.insert(coordinate_key(town.lat.0), id)?;
+            by_longitude.insert(coordinate_key(town.long.0), id)?;
+        }
+        tx.commit()?;
+        let id = TownId::BigSerial(id as i64);
+        self.town_names.insert(&id, &town.name.0);
+        Ok(id)
+    }
+
+    fn delete_nation(&mut self, id: &NationId) -> Result<bool, Box<dyn Error>> {
+        let key = id.to_i64() as u64;
+        let tx = self.conn.begin_write()?;
+        let old_nation: Option<Nation>;
+        {
+            let mut nations = tx.open_table(NATIONS)?;
+            old_nation = match nations.remove(key)? {
+                Some(v) => Some(S::deserialize(v.value())?),
+                None => None,
+            };
+            if let Some(old_nation) = &old_nation {
+                tx.open_multimap_table(NATIONS_BY_NAME)?
+                    .remove(old_nation.name.0.as_str(), key)?;
+            }
+        }
+        tx.commit()?;
+        Ok(old_nation.is_some())
+    }
+
+    fn delete_town(&mut self, id: &TownId) -> Result<bool, Box<dyn Error>> {
+        let key = id.to_i64() as u64;
+        let tx = self.conn.begin_write()?;
+        let old_town: Option<Town>;
+        {
+            let mut towns = tx.open_table(TOWNS)?;
+            old_town = match towns.remove(key)? {
+                Some(v) => Some(S::deserialize(v.value())?),
+                None => None,
+            };
+            if let Some(old_town) = &old_town {
+                tx.open_multimap_table(TOWNS_BY_NAME)?
+                    .remove(old_town.name.0.as_str(), key)?;
+                tx.open_multimap_table(TOWNS_BY_LATITUDE)?
+                    .remove(coordinate_key(old_town.lat.0), key)?;
+                tx.open_multimap_table(TOWNS_BY_LONGITUDE)?
+                    .remove(coordinate_key(old_town.long.0), key)?;
+            }
+        }
+        tx.commit()?;
+        if old_town.is_some() {
+            self.town_names.remove(id);
+        }
+        Ok(old_town.is_some())
+    }
+
+    fn update_nation(&mut self, id: &NationId, nation: &Nation) -> Result<bool, Box<dyn Error>> {
+        let key = id.to_i64() as u64;
+        let tx = self.conn.begin_write()?;
+        let old_nation: Option<Nation>;
+        {
+            let mut nations = tx.open_table(NATIONS)?;
+            let mut by_name = tx.open_multimap_table(NATIONS_BY_NAME)?;
+            old_nation = match nations.get(key)? {
+                Some(v) => Some(S::deserialize(v.value())?),
+                None => None,
+            };
+            if let Some(old_nation) = &old_nation {
+                self.check_nation(&by_name, key, nation)?;
+                nations.insert(key, S::serialize(nation)?.as_slice())?;
+                by_name.remove(old_nation.name.0.as_str(), key)?;
+                by_name.insert(nation.name.0.as_str(), key)?;
+            }
+        }
+        tx.commit()?;
+        Ok(old_nation.is_some())
+    }
+
+    fn update_town(&mut self, id: &TownId, town: &Town) -> Result<bool, Box<dyn Error>> {
+        let key = id.to_i64() as u64;
+        let tx = self.conn.begin_write()?;
+        let old_town: Option<Town>;
+        {
+            let mut towns = tx.open_table(TOWNS)?;
+            let mut by_name = tx.open_multimap_table(TOWNS_BY_NAME)?;
+            let mut by_latitude = tx.open_multimap_table(TOWNS_BY_LATITUDE)?;
+            let mut by_longitude = tx.open_multimap_table(TOWNS_BY_LONGITUDE)?;
+            old_town = match towns.get(key)? {
+                Some(v) => Some(S::deserialize(v.value())?),
+                None => None,
+            };
+            if let Some(old_town) = &old_town {
+                self.check_town(&towns, &by_name, key, town)?;
+                towns.insert(key, S::serialize(town)?.as_slice())?;
+                by_name.remove(old_town.name.0.as_str(), key)?;
+                by_name.insert(town.name.0.as_str(), key)?;
+                by_latitude.remove(coordinate_key(old_town.lat.0), key)?;
+                by_latitude.insert(coordinate_key(town.lat.0), key)?;
+                by_longitude.remove(coordinate_key(old_town.long.0), key)?;
+                by_longitude.insert(coordinate_key(town.long.0), key)?;
+            }
+        }
+        tx.commit()?;
+        if old_town.is_some() {
+            self.town_names.insert(id, &town.name.0);
+        }
+        Ok(old_town.is_some())
+    }
+
+    fn get_nation(&mut self, id: &NationId) -> Result<Option<Nation>, Box<dyn Error>> {
+        let nations = self.conn.begin_read()?.open_table(NATIONS)?;
+        let result = match nations.get(id.to_i64() as u64)? {
+            Some(v) => Ok(Some(S::deserialize(v.value())?)),
+            None => Ok(None),
+        };
+        result
+    }
+
+    fn get_town(&mut self, id: &TownId) -> Result<Option<Town>, Box<dyn Error>> {
+        let towns = self.conn.begin_read()?.open_table(TOWNS)?;
+        let result = match towns.get(id.to_i64() as u64)? {
+            Some(v) => Ok(Some(S::deserialize(v.value())?)),
+            None => Ok(None),
+        };
+        result
+    }
+
+    fn filter_nations_by_name(
+        &mut self,
+        name: &NationName,
+    ) -> Result<
+        Box<dyn Iterator<Item = Result<(NationId, Nation), Box<dyn Error>>> + '_>,
+        Box<dyn Error>,
+    > {
+        let tx = self.conn.begin_read()?;
+        let nations = tx.open_table(NATIONS)?;
+        let ids = tx
+            .open_multimap_table(NATIONS_BY_NAME)?
+            .get(name.0.as_str())?;
+        Ok(Box::new(ids.filter_map(move |id| {
+            let id = match id {
+                Ok(id) => id.value(),
+                Err(err) => return Some(Err(err.into())),
+            };
+            match nations.get(id) {
+                Ok(Some(v)) => Some(
+                    S::deserialize(v.value())
+                        .map(|nation| (NationId::BigSerial(id as i64), nation)),
+                ),
+                Ok(None) => None,
+                Err(err) => Some(Err(err.into())),
+            }
+        })))
+    }
+
+    fn filter_nations_by_iso_code(
+        &mut self,
+        iso_code: &NationCode,
+    ) -> Result<
+        Box<dyn Iterator<Item = Result<(NationId, Nation), Box<dyn Error>>> + '_>,
+        Box<dyn Error>,
+    > {
+        let iso_code = iso_code.clone();
+        let nations = self.conn.begin_read()?.open_table(NATIONS)?;
+        Ok(Box::new(nations.range::<u64>(..)?.filter_map(
+            move |row| match row {
+                Ok((k, v)) => match S::deserialize::<Nation>(v.value()) {
+                    Ok(nation) if nation.iso_code == iso_code => {
+                        Some(Ok((NationId::BigSerial(k.value() as i64), nation)))
+                    }
+                    Ok(_) => None,
+                    Err(err) => Some(Err(err)),
+                },
+                Err(err) => Some(Err(err.into())),
+            },
+        )))
+    }
+
+    fn filter_towns_by_name(
+        &mut self,
+        name: &TownName,
+    ) -> Result<Box<dyn Iterator<Item = Result<(TownId, Town), Box<dyn Error>>> + '_>, Box<dyn Error>>
+    {
+        let mut ids = vec![];
+        for id in self
+            .conn
+            .begin_read()?
+            .open_multimap_table(TOWNS_BY_NAME)?
+            .get(name.0.as_str())?
+        {
+            ids.push(id?.value());
+        }
+        Ok(Box::new(
+            self.read_towns(ids.into_iter())?.into_iter().map(Ok),
+        ))
+    }
+
+    fn filter_towns_by_population(
+        &mut self,
+        min_population: &Population,
+        max_population: &Population,
+    ) -> Result<Box<dyn Iterator<Item = Result<(TownId, Town), Box<dyn Error>>> + '_>, Box<dyn Error>>
+    {
+        let min_population = *min_population;
+        let max_population = *max_population;
+        let towns = self.conn.begin_read()?.open_table(TOWNS)?;
+        Ok(Box::new(towns.range::<u64>(..)?.filter_map(
+            move |row| match row {
+                Ok((k, v)) => match S::deserialize::<Town>(v.value()) {
+                    Ok(town)
+                        if min_population <= town.population
+                            && town.population <= max_population =>
+                    {
+                        Some(Ok((TownId::BigSerial(k.value() as i64), town)))
+                    }
+                    Ok(_) => None,
+                    Err(err) => Some(Err(err)),
+                },
+                Err(err) => Some(Err(err.into())),
+            },
+        )))
+    }
+
+    fn filter_towns_by_lat_long(
+        &mut self,
+        min_lat: &Latitude,
+        max_lat: &Latitude,
+        min_long: &Longitude,
+        max_long: &Longitude,
+    ) -> Result<Box<dyn Iterator<Item = Result<(TownId, Town), Box<dyn Error>>> + '_>, Box<dyn Error>>
+    {
+        // The ranges are looked up in their indexes, and only the towns in both are read.
+        let by_latitude = self.find_towns(TOWNS_BY_LATITUDE, min_lat.0, max_lat.0)?;
+        let by_longitude = self.find_towns(TOWNS_BY_LONGITUDE, min_long.0, max_long.0)?;
+        let mut ids: Vec<u64> = by_latitude.intersection(&by_longitude).copied().collect();
+        ids.sort_unstable();
+        Ok(Box::new(
+            self.read_towns(ids.into_iter())?.into_iter().map(Ok),
+        ))
+    }
+
+    fn search_towns_by_name(
+        &mut self,
+        text: &str,
+        name_match: &NameMatch,
+    ) -> Result<Box<dyn Iterator<Item = Result<(TownId, Town), Box<dyn Error>>> + '_>, Box<dyn Error>>
+    {
+        let candidates = self.read_towns(
+            self.town_names
+                .candidates(text, name_match)
+                .iter()
+                .map(|id| id.to_i64() as u64),
+        )?;
+        Ok(Box::new(
+            sort_by_rank(candidates.into_iter(), text, name_match, |(_, town)| {
+                &town.name.0
+            })
+            .into_iter()
+            .map(Ok),
+        ))
+    }
+}
+
+fn tables_exist(db: &Database) -> Result<bool, Box<dyn std::error::Error>> {
+    match db.begin_read()?.open_table(METADATA) {
+        Ok(metadata) => Ok(metadata.get("version")?.is_some()),
+        Err(TableError::TableDoesNotExist(_)) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Returns the id for a new record, and stores the following one.
+/// Without a stored id, as after a truncation, the ids continue after the last record.
+fn next_id(
+    tx: &WriteTransaction,
+    counter: &str,
+    records: &impl ReadableTable<u64, &'static [u8]>,
+) -> Result<u64, Box<dyn Error>> {
+    let mut metadata = tx.open_table(METADATA)?;
+    let id = match metadata.get(counter)? {
+        Some(v) => v.value(),
+        None => records.last()?.map_or(0, |(k, _)| k.value()) + 1,
+    };
+    metadata.insert(counter, id + 1)?;
+    Ok(id)
+}
+
+fn create_tables(db: &Database) -> Result<(), Box<dyn std::error::Error>> {
+    let tx = db.begin_write()?;
+    tx.open_table(NATIONS)?;
+    tx.open_table(TOWNS)?;
+    tx.open_multimap_table(NATIONS_BY_NAME)?;
+    tx.open_multimap_table(TOWNS_BY_NAME)?;
+    tx.open_multimap_table(TOWNS_BY_LATITUDE)?;
+    tx.open_multimap_table(TOWNS_BY_LONGITUDE)?;
+    tx.open_table(METADATA)?.insert("version", TABLES_VERSION)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn truncate_tables(db: &Database) -> Result<(), Box<dyn std::error::Error>> {
+    let tx = db.begin_write()?;
+    tx.delete_table(NATIONS)?;
+    tx.delete_table(TOWNS)?;
+    tx.delete_multimap_table(NATIONS_BY_NAME)?;
+    tx.delete_multimap_table(TOWNS_BY_NAME)?;
+    tx.delete_multimap_table(TOWNS_BY_LATITUDE)?;
+    tx.delete_multimap_table(TOWNS_BY_LONGITUDE)?;
+    tx.open_table(NATIONS)?;
+    tx.open_table(TOWNS)?;
+    tx.open_multimap_table(NATIONS_BY_NAME)?;
+    tx.open_multimap_table(TOWNS_BY_NAME)?;
+    tx.open_multimap_table(TOWNS_BY_LATITUDE)?;
+    tx.open_multimap_table(TOWNS_BY_LONGITUDE)?;
+    let mut metadata = tx.open_table(METADATA)?;
+    metadata.remove("next_nation_id")?;
+    metadata.remove("next_town_id")?;
+    drop(metadata);
+    tx.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_access::{
+        tests::{nation, town, TempPath},
+        BincodeSerder,
+    };
+
+    #[test]
+    fn coordinate_keys_keep_the_order() {
+        let values = [-180., -33.87, -0.22, -0., 0., 2.35, 51.51, 180.];
+        for pair in values.windows(2) {
+            assert!(coordinate_key(pair[0]) <= coordinate_key(pair[1]));
+        }
+        assert_eq!(coordinate_key(-0.), coordinate_key(0.));
+        assert!(coordinate_key(-0.22) < coordinate_key(0.));
+    }
+
+    #[test]
+    fn ids_are_not_reused() {
+        let path = TempPath::new("ids.redb");
+        let mut db = RedbConnection::<BincodeSerder>::create(&path.0).unwrap();
+        let nation_id = db.insert_nation(&nation("France", "FR")).unwrap();
+        let first_id = db
+            .insert_town(&town("Paris", &nation_id, 48.86, 2.35))
+            .unwrap();
+        assert!(db.delete_town(&first_id).unwrap());
+        drop(db);
+        // The next id is kept across the openings, even after deleting the last town.
+        let mut db = RedbConnection::<BincodeSerder>::open_existing(&path.0).unwrap();
+        let second_id = db
+            .insert_town(&town("Lyon", &nation_id, 45.76, 4.84))
+            .unwrap();
+        assert_eq!(second_id.to_i64(), first_id.to_i64() + 1);
+        assert_eq!(db.get_town(&first_id).unwrap(), None);
+    }
+
+    #[test]
+    fn open_with_an_unreadable_town() {
+        let path = TempPath::new("unreadable.redb");
+        let mut db = RedbConnection::<BincodeSerder>::create(&path.0).unwrap();
+        let nation_id = db.insert_nation(&nation("France", "FR")).unwrap();
+        let paris_id = db
+            .insert_town(&town("Paris", &nation_id, 48.86, 2.35))
+            .unwrap();
+        let tx = db.conn.begin_write().unwrap();
+        tx.open_table(TOWNS)
+            .unwrap()
+            .insert(1000, &[0xff][..])
+            .unwrap();
+        tx.commit().unwrap();
+        drop(db);
+        // Only the operations reading the bad town fail.
+        let mut db = RedbConnection::<BincodeSerder>::open_existing(&path.0).unwrap();
+        assert!(db.get_town(&TownId::BigSerial(1000)).is_err());
+        let found: Vec<TownId> = db
+            .search_towns_by_name("paris", &NameMatch::Exact)
+            .unwrap()
+            .map(|row| row.unwrap().0)
+            .collect();
+        assert_eq!(found, [paris_id]);
+    }
+}
@@ -1,14 +1,21 @@
 use crate::data_access::{
-    DbConnection, Latitude, Longitude, Nation, NationId, NationName, OptionalTownId, Town, TownId,
-    TownName,
+    find_and_upsert_nation, find_and_upsert_town,
+    name_search::{normalize, sort_by_rank, words},
+    AlternateName, Area, DbConnection, DbError, DbMaintenance, Elevation, IntegrityProblem,
+    Latitude, Longitude, NameMatch, Nation, NationCode, NationId, NationName, OptionalTownId,
+    Population, Town, TownId, TownName, UniqueNames, Upserted,
 };
-use sqlite::{Connection, State, Statement, Value};
+use sqlite::{Connection, OpenFlags, Row, Statement, Value};
 use std::error::Error;
 
 pub struct SqliteConnection {
     conn: Connection,
 }
 
+/// Version of the layout of the tables, stored as `user_version`.
+/// The databases created before its introduction have version 0.
+const TABLES_VERSION: i64 = 2;
+
 impl SqliteConnection {
     fn create_connection(options: &str) -> Result<Self, Box<dyn std::error::Error>> {
         Ok(Self {
@@ -20,22 +27,171 @@ impl SqliteConnection {
         let _ = self.conn.execute(
             "CREATE TABLE Nations (
                 name TEXT NOT NULL,
-                capital_id INTEGER NULL
+                capital_id INTEGER NULL,
+                iso_code TEXT NOT NULL,
+                area FLOAT NOT NULL
             );
             CREATE TABLE Towns (
                 name TEXT NOT NULL,
                 lat FLOAT NOT NULL,
                 long FLOAT NOT NULL,
-                nation_id INTEGER NOT NULL
-            );",
+                nation_id INTEGER NOT NULL,
+                population INTEGER NOT NULL,
+                elevation FLOAT NOT NULL
+            );
+            CREATE TABLE TownAlternateNames (
+                town_id INTEGER NOT NULL,
+                language TEXT NOT NULL,
+                name TEXT NOT NULL
+            );
+            CREATE INDEX TownAlternateNamesTown ON TownAlternateNames (town_id);",
         )?;
+        self.conn
+            .execute(format!("PRAGMA user_version = {TABLES_VERSION}"))?;
+        self.create_search_index()?;
+        Ok(())
+    }
+
+    /// Brings the tables of a database created by a previous version of this program
+    /// to the current layout, giving default values to the new columns.
+    fn upgrade_tables(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.ensure_search_index()?;
+        let mut command = self.conn.prepare("PRAGMA user_version")?;
+        command.next()?;
+        let version = command.read::<i64, _>(0)?;
+        drop(command);
+        if version < 2 {
+            // All the changes are applied, or none if one of them fails.
+            self.in_transaction(|db| {
+                db.conn.execute(format!(
+                    "ALTER TABLE Nations ADD COLUMN iso_code TEXT NOT NULL DEFAULT '';
+                    ALTER TABLE Nations ADD COLUMN area FLOAT NOT NULL DEFAULT 0;
+                    ALTER TABLE Towns ADD COLUMN population INTEGER NOT NULL DEFAULT 0;
+                    ALTER TABLE Towns ADD COLUMN elevation FLOAT NOT NULL DEFAULT 0;
+                    CREATE TABLE TownAlternateNames (
+                        town_id INTEGER NOT NULL,
+                        language TEXT NOT NULL,
+                        name TEXT NOT NULL
+                    );
+                    CREATE INDEX TownAlternateNamesTown ON TownAlternateNames (town_id);
+                    PRAGMA user_version = {TABLES_VERSION};"
+                ))?;
+                Ok(())
+            })?;
+        }
         Ok(())
     }
 
+    /// Runs the operation in a transaction taking the write lock at once,
+    /// so that no other connection writes between its reads and its writes.
+    fn in_transaction<T>(
+        &mut self,
+        operation: impl FnOnce(&mut Self) -> Result<T, Box<dyn Error>>,
+    ) -> Result<T, Box<dyn Error>> {
+        self.conn.execute("BEGIN IMMEDIATE")?;
+        match operation(self) {
+            Ok(result) => {
+                self.conn.execute("COMMIT")?;
+                Ok(result)
+            }
+            Err(err) => {
+                self.conn.execute("ROLLBACK")?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Replaces the alternate names of the specified town.
+    fn write_alternate_names(
+        &mut self,
+        town_id: &TownId,
+        alternate_names: &[AlternateName],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.conn
+            .prepare("DELETE FROM TownAlternateNames WHERE town_id = :town_id")?
+            .param(":town_id", town_id.to_value())?
+            .next()?;
+        for alternate_name in alternate_names {
+            self.conn
+                .prepare(
+                    "INSERT INTO TownAlternateNames (
+                        town_id, language, name
+                    ) VALUES (
+                        :town_id, :language, :name
+                    )",
+                )?
+                .param(":town_id", town_id.to_value())?
+                .param(":language", alternate_name.language.0.clone().into())?
+                .param(":name", alternate_name.name.to_value())?
+                .next()?;
+        }
+        Ok(())
+    }
+
+    /// Creates a full-text index on town names, ignoring case and diacritics,
+    /// kept up to date by triggers, and the table of its distinct words.
+    fn create_search_index(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.conn.execute(
+            "CREATE VIRTUAL TABLE TownNames USING fts5(
+                name,
+                content='Towns',
+                content_rowid='rowid',
+                tokenize='unicode61 remove_diacritics 2'
+            );
+            CREATE VIRTUAL TABLE TownNameTerms USING fts5vocab(TownNames, 'row');
+            CREATE TRIGGER TownsInsert AFTER INSERT ON Towns BEGIN
+                INSERT INTO TownNames (rowid, name) VALUES (new.rowid, new.name);
+            END;
+            CREATE TRIGGER TownsDelete AFTER DELETE ON Towns BEGIN
+                INSERT INTO TownNames (TownNames, rowid, name) VALUES ('delete', old.rowid, old.name);
+            END;
+            CREATE TRIGGER TownsUpdate AFTER UPDATE ON Towns BEGIN
+                INSERT INTO TownNames (TownNames, rowid, name) VALUES ('delete', old.rowid, old.name);
+                INSERT INTO TownNames (rowid, name) VALUES (new.rowid, new.name);
+            END;
+            INSERT INTO TownNames (TownNames) VALUES ('rebuild');",
+        )?;
+        Ok(())
+    }
+
+    /// Databases created before the full-text index was introduced get it now.
+    fn ensure_search_index(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut command = self.conn.prepare(
+            "SELECT COUNT(*)
+            FROM sqlite_master
+            WHERE type='table' AND name = 'TownNames'",
+        )?;
+        command.next()?;
+        let exists = command.read::<i64, _>(0)? == 1;
+        drop(command);
+        if !exists {
+            self.create_search_index()?;
+        }
+        Ok(())
+    }
+
+    fn schema_object_exists(
+        &mut self,
+        object_type: &str,
+        name: &str,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let mut command = self
+            .conn
+            .prepare(
+                "SELECT COUNT(*)
+                FROM sqlite_master
+                WHERE type = :type AND name = :name",
+            )?
+            .param(":type", object_type.into())?
+            .param(":name", name.into())?;
+        command.next()?;
+        Ok(command.read::<i64, _>(0)? == 1)
+    }
+
     fn truncate_tables(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         let _ = self
             .conn
-            .execute("DELETE FROM Nations; DELETE FROM Towns")?;
+            .execute("DELETE FROM Nations; DELETE FROM Towns; DELETE FROM TownAlternateNames")?;
         Ok(())
     }
 
@@ -50,6 +206,20 @@ impl SqliteConnection {
     }
 }
 
+/// The rules of `UniqueNames` are enforced by unique indexes,
+/// whose violations are reported as `DbError`.
+fn map_unique_violation(err: sqlite::Error) -> Box<dyn Error> {
+    match err.message.as_deref() {
+        Some(message) if message.starts_with("UNIQUE constraint failed: Nations.") => {
+            Box::new(DbError::DuplicateNationName)
+        }
+        Some(message) if message.starts_with("UNIQUE constraint failed: Towns.") => {
+            Box::new(DbError::DuplicateTownName)
+        }
+        _ => Box::new(err),
+    }
+}
+
 trait SqliteParam {
     fn param(self, name: &str, value: Value) -> Result<Self, Box<dyn Error>>
     where
@@ -94,7 +264,13 @@ macro_rules! impl_IsOptionalNewType {
 }
 
 //impl_IsNewType!(for NationId, NationName, TownId, TownName, Latitude, Longitude);
-impl_IsNewType!(for NationName, TownName, Latitude, Longitude);
+impl_IsNewType!(for NationName, TownName, Latitude, Longitude, NationCode, Area, Elevation);
+
+impl IsNewType for Population {
+    fn inner(&self) -> Value {
+        (self.0 as i64).into()
+    }
+}
 //impl_IsOptionalNewType!(for OptionalTownId);
 
 trait ToValue {
@@ -152,6 +328,7 @@ impl DbConnection for SqliteConnection {
         if !result.tables_exist()? {
             return Err("Sqlite open_existing: tables missing.".into());
         }
+        result.upgrade_tables()?;
         Ok(result)
     }
 
@@ -163,6 +340,7 @@ impl DbConnection for SqliteConnection {
         if !result.tables_exist()? {
             return Err("Sqlite open_existing_truncated: tables missing.".into());
         }
+        result.upgrade_tables()?;
         result.truncate_tables();
         Ok(result)
     }
@@ -175,7 +353,7 @@ impl DbConnection for SqliteConnection {
         if result.tables_exist()? {
             return Err("Sqlite create: tables already exist.".into());
         }
-        result.create_tables();
+        result.create_tables()?;
         Ok(result)
     }
 
@@ -185,7 +363,9 @@ impl DbConnection for SqliteConnection {
     {
         let mut result = Self::create_connection(options)?;
         if !result.tables_exist()? {
-            result.create_tables();
+            result.create_tables()?;
+        } else {
+            result.upgrade_tables()?;
         }
         Ok(result)
     }
@@ -196,26 +376,56 @@ impl DbConnection for SqliteConnection {
     {
         let mut result = Self::create_connection(options)?;
         if result.tables_exist()? {
+            result.upgrade_tables()?;
             result.truncate_tables();
         } else {
-            result.create_tables();
+            result.create_tables()?;
         }
         Ok(result)
     }
 
+    fn unique_names(&mut self) -> Result<UniqueNames, Box<dyn Error>> {
+        Ok(UniqueNames {
+            nation_name: self.schema_object_exists("index", "NationsName")?,
+            town_name_per_nation: self.schema_object_exists("index", "TownsNationName")?,
+        })
+    }
+
+    fn set_unique_names(&mut self, unique_names: &UniqueNames) -> Result<(), Box<dyn Error>> {
+        if unique_names.nation_name {
+            self.conn
+                .execute("CREATE UNIQUE INDEX IF NOT EXISTS NationsName ON Nations (name)")
+                .map_err(map_unique_violation)?;
+        } else {
+            self.conn.execute("DROP INDEX IF EXISTS NationsName")?;
+        }
+        if unique_names.town_name_per_nation {
+            self.conn
+                .execute(
+                    "CREATE UNIQUE INDEX IF NOT EXISTS TownsNationName ON Towns (nation_id, name)",
+                )
+                .map_err(map_unique_violation)?;
+        } else {
+            self.conn.execute("DROP INDEX IF EXISTS TownsNationName")?;
+        }
+        Ok(())
+    }
+
     fn insert_nation(&mut self, nation: &Nation) -> Result<NationId, Box<dyn Error>> {
         let mut command = self
             .conn
             .prepare(
                 "INSERT INTO Nations (
-                    name, capital_id
+                    name, capital_id, iso_code, area
                 ) VALUES (
-                    :name, :capital_id
+                    :name, :capital_id, :iso_code, :area
                 ) RETURNING ROWID",
             )?
             .param(":name", nation.name.to_value())?
-            .param(":capital_id", nation.capital_id.to_value())?;
-        command.next()?;
+            .param(":capital_id", nation.capital_id.to_value())?
+            .param(":iso_code", nation.iso_code.to_value())?
+            .param(":area", nation.area.to_value())?;
+        command.next().map_err(map_unique_violation)?;
         Ok(NationId::BigSerial(command.read(0)?))
     }
 
@@ -224,17 +434,32 @@ impl DbConnection for SqliteConnection {
             .conn
             .prepare(
                 "INSERT INTO Towns (
-                    name, lat, long, nation_id
+                    name, lat, long, nation_id, population, elevation
                 ) VALUES (
-                    :name, :lat, :long, :nation_id
+                    :name, :lat, :long, :nation_id, :population, :elevation
                 ) RETURNING ROWID",
             )?
             .param(":name", town.name.to_value())?
             .param(":lat", town.lat.to_value())?
             .param(":long", town.long.to_value())?
-            .param(":nation_id", town.nation_id.to_value())?;
-        command.next()?;
-        Ok(TownId::BigSerial(command.read(0)?))
+            .param(":nation_id", town.nation_id.to_value())?
+            .param(":population", town.population.to_value())?
+            .param(":elevation", town.elevation.to_value())?;
+        command.next().map_err(map_unique_violation)?;
+        let id = TownId::BigSerial(command.read(0)?);
+        drop(command);
+        self.write_alternate_names(&id, &town.alternate_names)?;
+        Ok(id)
+    }
+
+    fn upsert_nation(&mut self, nation: &Nation) -> Result<(NationId, Upserted), Box<dyn Error>> {
+        // The RETURNING clause of an upsert cannot tell whether it inserted the row,
+        // so the nation is looked for while holding the write lock.
+        self.in_transaction(|db| find_and_upsert_nation(db, nation))
+    }
+
+    fn upsert_town(&mut self, town: &Town) -> Result<(TownId, Upserted), Box<dyn Error>> {
+        self.in_transaction(|db| find_and_upsert_town(db, town))
     }
 
     fn delete_nation(&mut self, id: &NationId) -> Result<bool, Box<dyn Error>> {
@@ -247,6 +472,7 @@ impl DbConnection for SqliteConnection {
     }
 
     fn delete_town(&mut self, id: &TownId) -> Result<bool, Box<dyn Error>> {
+        self.write_alternate_names(id, &[])?;
         let mut command = self
             .conn
             .prepare("DELETE FROM Towns WHERE ROWID = :id RETURNING ROWID")?
@@ -261,13 +487,17 @@ impl DbConnection for SqliteConnection {
             .prepare(
                 "UPDATE Nations SET
                     name = :name,
-                    capital_id = :capital_id
+                    capital_id = :capital_id,
+                    iso_code = :iso_code,
+                    area = :area
                 WHERE ROWID = :nation_id RETURNING ROWID",
             )?
             .param(":name", nation.name.to_value())?
             .param(":capital_id", nation.capital_id.to_value())?
+            .param(":iso_code", nation.iso_code.to_value())?
+            .param(":area", nation.area.to_value())?
             .param(":nation_id", id.to_value())?;
-        command.next()?;
+        command.next().map_err(map_unique_violation)?;
         Ok(command.read::<i64, _>(0)? == id.to_i64())
     }
 
@@ -279,57 +509,56 @@ impl DbConnection for SqliteConnection {
                     name = :name,
                     lat = :lat,
                     long = :long,
-                    nation_id = :nation_id
+                    nation_id = :nation_id,
+                    population = :population,
+                    elevation = :elevation
                 WHERE ROWID = :id RETURNING ROWID",
             )?
             .param(":name", town.name.to_value())?
             .param(":lat", town.lat.to_value())?
             .param(":long", town.long.to_value())?
             .param(":nation_id", town.nation_id.to_value())?
+            .param(":population", town.population.to_value())?
+            .param(":elevation", town.elevation.to_value())?
             .param(":id", id.to_value())?;
-        command.next()?;
-        Ok(command.read::<i64, _>(0)? == id.to_i64())
+        command.next().map_err(map_unique_violation)?;
+        let updated = command.read::<i64, _>(0)? == id.to_i64();
+        drop(command);
+        if updated {
+            self.write_alternate_names(id, &town.alternate_names)?;
+        }
+        Ok(updated)
     }
 
     fn get_nation(&mut self, id: &NationId) -> Result<Option<Nation>, Box<dyn Error>> {
-        let mut command = self
+        match self
             .conn
-            .prepare(
-                "SELECT * FROM Nations
-                WHERE ROWID = :id",
-            )?
-            .param(":id", id.to_value())?;
-        match command.next() {
-            Ok(State::Row) => Ok(Some(Nation {
-                name: NationName(command.read("name")?),
-                //capital_id: Some(TownId(command.read::<i64, _>("capital_id")?)),
-                capital_id: OptionalTownId(match command.read::<Option<i64>, _>("capital_id")? {
-                    Some(id) => Some(TownId::BigSerial(id)),
-                    None => None,
-                }),
-            })),
-            Ok(State::Done) => Ok(None),
-            Err(e) => Err(Box::new(e)),
+            .prepare(format!(
+                "SELECT {NATION_COLUMNS} FROM Nations
+                WHERE ROWID = :id"
+            ))?
+            .param(":id", id.to_value())?
+            .into_iter()
+            .next()
+        {
+            Some(row) => Ok(Some(nation_from_row(&row?)?.1)),
+            None => Ok(None),
         }
     }
 
     fn get_town(&mut self, id: &TownId) -> Result<Option<Town>, Box<dyn Error>> {
-        let mut command = self
+        match self
             .conn
-            .prepare(
-                "SELECT * FROM Towns
-                WHERE ROWID = :id",
-            )?
-            .param(":id", id.to_value())?;
-        match command.next() {
-            Ok(State::Row) => Ok(Some(Town {
-                name: TownName(command.read("name")?),
-                lat: Latitude(command.read("lat")?),
-                long: Longitude(command.read("long")?),
-                nation_id: NationId::BigSerial(command.read("nation_id")?),
-            })),
-            Ok(State::Done) => Ok(None),
-            Err(e) => Err(Box::new(e)),
+            .prepare(format!(
+                "SELECT {TOWN_COLUMNS} FROM Towns
+                WHERE ROWID = :id"
+            ))?
+            .param(":id", id.to_value())?
+            .into_iter()
+            .next()
+        {
+            Some(row) => Ok(Some(town_from_row(&row?)?.1)),
+            None => Ok(None),
         }
     }
 
@@ -342,26 +571,32 @@ impl DbConnection for SqliteConnection {
     > {
         Ok(Box::new(
             self.conn
-                .prepare(
-                    "SELECT rowid, name, capital_id FROM Nations
-                    WHERE name = :name",
-                )?
+                .prepare(format!(
+                    "SELECT {NATION_COLUMNS} FROM Nations
+                    WHERE name = :name"
+                ))?
                 .param(":name", name.to_value())?
                 .into_iter()
-                .map(move |row| {
-                    let row = row.unwrap();
-                    let capital_id = OptionalTownId(match row.read("capital_id") {
-                        Some(town_id) => Some(TownId::BigSerial(town_id)),
-                        None => None,
-                    });
-                    Ok((
-                        NationId::BigSerial(row.read("rowid")),
-                        Nation {
-                            name: NationName(row.read::<&str, _>("name").to_string()),
-                            capital_id: capital_id,
-                        },
-                    ))
-                }),
+                .map(|row| nation_from_row(&row?)),
+        ))
+    }
+
+    fn filter_nations_by_iso_code(
+        &mut self,
+        iso_code: &NationCode,
+    ) -> Result<
+        Box<dyn Iterator<Item = Result<(NationId, Nation), Box<dyn Error>>> + '_>,
+        Box<dyn Error>,
+    > {
+        Ok(Box::new(
+            self.conn
+                .prepare(format!(
+                    "SELECT {NATION_COLUMNS} FROM Nations
+                    WHERE iso_code = :iso_code"
+                ))?
+                .param(":iso_code", iso_code.to_value())?
+                .into_iter()
+                .map(|row| nation_from_row(&row?)),
         ))
     }
 
@@ -372,24 +607,32 @@ impl DbConnection for SqliteConnection {
     {
         Ok(Box::new(
             self.conn
-                .prepare(
-                    "SELECT rowid, name, lat, long, nation_id FROM Towns
-                    WHERE name = :name",
-                )?
+                .prepare(format!(
+                    "SELECT {TOWN_COLUMNS} FROM Towns
+                    WHERE name = :name"
+                ))?
                 .param(":name", name.to_value())?
                 .into_iter()
-                .map(move |row| {
-                    let row = row.unwrap();
-                    Ok((
-                        TownId::BigSerial(row.read("rowid")),
-                        Town {
-                            name: TownName(row.read::<&str, _>("name").to_string()),
-                            lat: Latitude(row.read("lat")),
-                            long: Longitude(row.read("long")),
-                            nation_id: NationId::BigSerial(row.read("nation_id")),
-                        },
-                    ))
-                }),
+                .map(|row| town_from_row(&row?)),
+        ))
+    }
+
+    fn filter_towns_by_population(
+        &mut self,
+        min_population: &Population,
+        max_population: &Population,
+    ) -> Result<Box<dyn Iterator<Item = Result<(TownId, Town), Box<dyn Error>>> + '_>, Box<dyn Error>>
+    {
+        Ok(Box::new(
+            self.conn
+                .prepare(format!(
+                    "SELECT {TOWN_COLUMNS} FROM Towns
+                    WHERE :min_population <= population AND population <= :max_population"
+                ))?
+                .param(":min_population", min_population.to_value())?
+                .param(":max_population", max_population.to_value())?
+                .into_iter()
+                .map(|row| town_from_row(&row?)),
         ))
     }
 
@@ -403,28 +646,217 @@ impl DbConnection for SqliteConnection {
     {
         Ok(Box::new(
             self.conn
-                .prepare(
-                    "SELECT rowid, name, lat, long, nation_id FROM Towns
+                .prepare(format!(
+                    "SELECT {TOWN_COLUMNS} FROM Towns
                     WHERE :min_lat <= lat AND lat <= :max_lat
-                    AND :min_long <= long AND long <= :max_long",
-                )?
+                    AND :min_long <= long AND long <= :max_long"
+                ))?
                 .param(":min_lat", min_lat.to_value())?
                 .param(":max_lat", max_lat.to_value())?
                 .param(":min_long", min_long.to_value())?
                 .param(":max_long", max_long.to_value())?
                 .into_iter()
-                .map(move |row| {
-                    let row = row.unwrap();
-                    Ok((
-                        TownId::BigSerial(row.read("rowid")),
-                        Town {
-                            name: TownName(row.read::<&str, _>("name").to_string()),
-                            lat: Latitude(row.read("lat")),
-                            long: Longitude(row.read("long")),
-                            nation_id: NationId::BigSerial(row.read("nation_id")),
-                        },
-                    ))
-                }),
+                .map(|row| town_from_row(&row?)),
         ))
     }
+
+    fn search_towns_by_name(
+        &mut self,
+        text: &str,
+        name_match: &NameMatch,
+    ) -> Result<Box<dyn Iterator<Item = Result<(TownId, Town), Box<dyn Error>>> + '_>, Box<dyn Error>>
+    {
+        let normalized_text = normalize(text);
+        let text_words: Vec<&str> = words(&normalized_text).collect();
+        let expression = match name_match {
+            NameMatch::Exact => text_words
+                .iter()
+                .map(|word| format!("\"{word}\""))
+                .collect::<Vec<_>>()
+                .join(" "),
+            NameMatch::Prefix => text_words
+                .iter()
+                .map(|word| format!("\"{word}\"*"))
+                .collect::<Vec<_>>()
+                .join(" "),
+            NameMatch::Fuzzy(max_distance) => {
+                // The index cannot compute edit distances,
+                // so the near words are looked for in its vocabulary.
+                let mut near_terms = vec![];
+                for row in self.conn.prepare("SELECT term FROM TownNameTerms")? {
+                    let term = row?.read::<&str, _>("term").to_string();
+                    if text_words
+                        .iter()
+                        .any(|word| strsim::levenshtein(word, &term) <= *max_distance)
+                    {
+                        near_terms.push(format!("\"{term}\""));
+                    }
+                }
+                near_terms.join(" OR ")
+            }
+        };
+        if expression.is_empty() {
+            return Ok(Box::new(std::iter::empty()));
+        }
+        let mut candidates = vec![];
+        for row in self
+            .conn
+            .prepare(format!(
+                "SELECT {TOWN_COLUMNS} FROM TownNames
+                JOIN Towns ON Towns.rowid = TownNames.rowid
+                WHERE TownNames MATCH :expression"
+            ))?
+            .param(":expression", expression.into())?
+        {
+            candidates.push(town_from_row(&row?)?);
+        }
+        Ok(Box::new(
+            sort_by_rank(candidates.into_iter(), text, name_match, |(_, town)| {
+                &town.name.0
+            })
+            .into_iter()
+            .map(Ok),
+        ))
+    }
+}
+
+impl DbMaintenance for SqliteConnection {
+    fn open_unchanged(path: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            conn: Connection::open_with_flags(path, OpenFlags::new().set_read_only())?,
+        })
+    }
+
+    fn backup(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        // It writes a consistent snapshot, and it fails if the file already exists.
+        self.conn
+            .prepare("VACUUM INTO :path")?
+            .param(":path", path.into())?
+            .next()?;
+        Ok(())
+    }
+
+    fn check_integrity(&mut self) -> Result<Vec<IntegrityProblem>, Box<dyn Error>> {
+        let mut problems = vec![];
+        for row in self.conn.prepare("PRAGMA integrity_check")? {
+            let message = row?.read::<&str, _>(0).to_string();
+            if message != "ok" {
+                problems.push(IntegrityProblem::Storage(message));
+            }
+        }
+        for table in ["Nations", "Towns", "TownAlternateNames", "TownNames"] {
+            if !self.schema_object_exists("table", table)? {
+                problems.push(IntegrityProblem::Schema(format!(
+                    "Table {table} is missing."
+                )));
+            }
+        }
+        let mut command = self.conn.prepare("PRAGMA user_version")?;
+        command.next()?;
+        let version = command.read::<i64, _>(0)?;
+        drop(command);
+        if version != TABLES_VERSION {
+            problems.push(IntegrityProblem::Schema(format!(
+                "Tables version is {version} instead of {TABLES_VERSION}."
+            )));
+        }
+        if !problems.is_empty() {
+            return Ok(problems);
+        }
+        for row in self.conn.prepare(
+            "SELECT Towns.rowid, Towns.nation_id FROM Towns
+            LEFT JOIN Nations ON Nations.rowid = Towns.nation_id
+            WHERE Nations.rowid IS NULL",
+        )? {
+            let row = row?;
+            problems.push(IntegrityProblem::MissingNation {
+                town_id: TownId::BigSerial(row.try_read(0)?),
+                nation_id: NationId::BigSerial(row.try_read(1)?),
+            });
+        }
+        Ok(problems)
+    }
+}
+
+const NATION_COLUMNS: &str = "rowid, name, capital_id, iso_code, area";
+
+/// The alternate names are collected in a JSON array.
+const TOWN_COLUMNS: &str = "Towns.rowid, Towns.name, lat, long, nation_id, population, elevation,
+    (SELECT json_group_array(json_object('language', language, 'name', name))
+    FROM (SELECT language, name FROM TownAlternateNames
+        WHERE town_id = Towns.rowid ORDER BY language, name)) AS alternate_names";
+
+fn nation_from_row(row: &Row) -> Result<(NationId, Nation), Box<dyn Error>> {
+    Ok((
+        NationId::BigSerial(row.try_read("rowid")?),
+        Nation {
+            name: NationName(row.try_read::<&str, _>("name")?.to_string()),
+            capital_id: OptionalTownId(
+                row.try_read::<Option<i64>, _>("capital_id")?
+                    .map(TownId::BigSerial),
+            ),
+            iso_code: NationCode(row.try_read::<&str, _>("iso_code")?.to_string()),
+            area: Area(row.try_read("area")?),
+        },
+    ))
+}
+
+fn town_from_row(row: &Row) -> Result<(TownId, Town), Box<dyn Error>> {
+    Ok((
+        TownId::BigSerial(row.try_read("rowid")?),
+        Town {
+            name: TownName(row.try_read::<&str, _>("name")?.to_string()),
+            lat: Latitude(row.try_read("lat")?),
+            long: Longitude(row.try_read("long")?),
+            nation_id: NationId::BigSerial(row.try_read("nation_id")?),
+            population: Population(row.try_read::<i64, _>("population")? as u64),
+            elevation: Elevation(row.try_read("elevation")?),
+            alternate_names: serde_json::from_str(row.try_read::<&str, _>("alternate_names")?)?,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_access::tests::TempPath;
+
+    #[test]
+    // A database written before the tables had a version gets the new columns and the index.
+    fn upgrade_from_version_0() {
+        let path = TempPath::new("upgrade.sqlite");
+        sqlite::open(&path.0)
+            .unwrap()
+            .execute(
+                "CREATE TABLE Nations (
+                    name TEXT NOT NULL,
+                    capital_id INTEGER NULL
+                );
+                CREATE TABLE Towns (
+                    name TEXT NOT NULL,
+                    lat FLOAT NOT NULL,
+                    long FLOAT NOT NULL,
+                    nation_id INTEGER NOT NULL
+                );
+                INSERT INTO Nations (name, capital_id) VALUES ('Germany', NULL);
+                INSERT INTO Towns (name, lat, long, nation_id) VALUES ('München', 48.1, 11.6, 1);",
+            )
+            .unwrap();
+        let mut db = SqliteConnection::open_existing(&path.0).unwrap();
+        let nation = db.get_nation(&NationId::Serial(1)).unwrap().unwrap();
+        assert_eq!(nation.iso_code, NationCode(String::new()));
+        assert_eq!(nation.area, Area(0.));
+        let town = db.get_town(&TownId::Serial(1)).unwrap().unwrap();
+        assert_eq!(town.name, TownName("München".to_string()));
+        assert_eq!(town.population, Population(0));
+        assert_eq!(town.alternate_names, []);
+        let found: Vec<_> = db
+            .search_towns_by_name("munchen", &NameMatch::Exact)
+            .unwrap()
+            .collect();
+        assert_eq!(found.len(), 1);
+        let mut command = db.conn.prepare("PRAGMA user_version").unwrap();
+        command.next().unwrap();
+        assert_eq!(command.read::<i64, _>(0).unwrap(), TABLES_VERSION);
+    }
 }
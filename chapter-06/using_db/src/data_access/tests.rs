@@ -0,0 +1,401 @@
+//! Checks of the behavior common to the databases which run without a server.
+
+use crate::data_access::{
+    mock_db::MockDbConnection, persy_db::PersyConnection, redb_db::RedbConnection,
+    sqlite_db::SqliteConnection, AlternateName, Area, BincodeSerder, DbConnection, DbError,
+    DbMaintenance, Elevation, IntegrityProblem, JsonSerder, LanguageCode, Latitude, Longitude,
+    NameMatch, Nation, NationCode, NationId, NationName, OptionalTownId, Population, Town,
+    TownName, UniqueNames, Upserted,
+};
+use std::error::Error;
+
+/// Path of a database file of this test process, removed when dropped.
+pub struct TempPath(pub String);
+
+impl TempPath {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("using_db-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        Self(path.to_str().unwrap().to_string())
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Runs the check on a new empty database of every kind.
+fn for_each_database(name: &str, check: fn(&mut dyn DbConnection)) {
+    println!("Mock");
+    check(&mut MockDbConnection::create("").unwrap());
+    println!("SQLite");
+    check(&mut SqliteConnection::create(":memory:").unwrap());
+    println!("Persy Bincode");
+    let path = TempPath::new(&format!("{name}_bincode.persy"));
+    check(&mut PersyConnection::<BincodeSerder>::create(&path.0).unwrap());
+    println!("Persy Json");
+    let path = TempPath::new(&format!("{name}_json.persy"));
+    check(&mut PersyConnection::<JsonSerder>::create(&path.0).unwrap());
+    println!("Redb Bincode");
+    let path = TempPath::new(&format!("{name}_bincode.redb"));
+    check(&mut RedbConnection::<BincodeSerder>::create(&path.0).unwrap());
+    println!("Redb Json");
+    let path = TempPath::new(&format!("{name}_json.redb"));
+    check(&mut RedbConnection::<JsonSerder>::create(&path.0).unwrap());
+}
+
+pub fn nation(name: &str, iso_code: &str) -> Nation {
+    Nation {
+        name: NationName(name.to_string()),
+        capital_id: OptionalTownId(None),
+        iso_code: NationCode(iso_code.to_string()),
+        area: Area(1000.),
+    }
+}
+
+pub fn town(name: &str, nation_id: &NationId, lat: f64, long: f64) -> Town {
+    Town {
+        name: TownName(name.to_string()),
+        lat: Latitude(lat),
+        long: Longitude(long),
+        nation_id: nation_id.clone(),
+        population: Population(1000),
+        elevation: Elevation(100.),
+        alternate_names: vec![],
+    }
+}
+
+fn searched_names(db: &mut dyn DbConnection, text: &str, name_match: &NameMatch) -> Vec<String> {
+    db.search_towns_by_name(text, name_match)
+        .unwrap()
+        .map(|row| row.unwrap().1.name.0)
+        .collect()
+}
+
+fn check_search(db: &mut dyn DbConnection) {
+    let nation_id = db.insert_nation(&nation("Germany", "DE")).unwrap();
+    let mut ids = vec![];
+    for name in [
+        "München",
+        "Munich",
+        "Munster",
+        "Bad Münstereifel",
+        "Berlin",
+        "Frankfurt am Main",
+    ] {
+        ids.push(db.insert_town(&town(name, &nation_id, 50., 10.)).unwrap());
+    }
+    assert_eq!(
+        searched_names(db, "MUNCHEN", &NameMatch::Exact),
+        ["München"]
+    );
+    assert_eq!(
+        searched_names(db, "mün", &NameMatch::Prefix),
+        ["Munich", "München", "Munster", "Bad Münstereifel"]
+    );
+    assert_eq!(
+        searched_names(db, "main", &NameMatch::Prefix),
+        ["Frankfurt am Main"]
+    );
+    assert_eq!(
+        searched_names(db, "Munchen", &NameMatch::Fuzzy(1)),
+        ["München"]
+    );
+    assert_eq!(
+        searched_names(db, "berln", &NameMatch::Fuzzy(1)),
+        ["Berlin"]
+    );
+    assert!(searched_names(db, "berln", &NameMatch::Fuzzy(0)).is_empty());
+    // The search follows the updates and the deletions.
+    db.update_town(&ids[4], &town("Bern", &nation_id, 50., 10.))
+        .unwrap();
+    assert!(searched_names(db, "berlin", &NameMatch::Exact).is_empty());
+    assert_eq!(searched_names(db, "bern", &NameMatch::Exact), ["Bern"]);
+    db.delete_town(&ids[0]).unwrap();
+    assert_eq!(
+        searched_names(db, "mun", &NameMatch::Prefix),
+        ["Munich", "Munster", "Bad Münstereifel"]
+    );
+}
+
+#[test]
+fn search_towns_by_name() {
+    for_each_database("search", check_search);
+}
+
+fn check_fields_and_filters(db: &mut dyn DbConnection) {
+    let france_id = db.insert_nation(&nation("France", "FR")).unwrap();
+    let germany = nation("Germany", "DE");
+    let germany_id = db.insert_nation(&germany).unwrap();
+    let found: Vec<_> = db
+        .filter_nations_by_iso_code(&NationCode("DE".to_string()))
+        .unwrap()
+        .map(|row| row.unwrap())
+        .collect();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].0, germany_id);
+    assert_eq!(found[0].1.name, germany.name);
+    assert_eq!(found[0].1.area, germany.area);
+
+    let mut munich = town("München", &germany_id, 48.1, 11.6);
+    munich.population = Population(1_500_000);
+    munich.elevation = Elevation(519.);
+    munich.alternate_names = vec![
+        AlternateName {
+            language: LanguageCode("en".to_string()),
+            name: TownName("Munich".to_string()),
+        },
+        AlternateName {
+            language: LanguageCode("it".to_string()),
+            name: TownName("Monaco di Baviera".to_string()),
+        },
+    ];
+    let munich_id = db.insert_town(&munich).unwrap();
+    assert_eq!(db.get_town(&munich_id).unwrap(), Some(munich.clone()));
+    for (name, population) in [("Lyon", 500_000), ("Annecy", 130_000)] {
+        let mut french_town = town(name, &france_id, 45., 5.);
+        french_town.population = Population(population);
+        db.insert_town(&french_town).unwrap();
+    }
+    let mut names: Vec<String> = db
+        .filter_towns_by_population(&Population(130_000), &Population(500_000))
+        .unwrap()
+        .map(|row| row.unwrap().1.name.0)
+        .collect();
+    names.sort();
+    assert_eq!(names, ["Annecy", "Lyon"]);
+
+    // The alternate names are replaced by an update.
+    munich.alternate_names.truncate(1);
+    munich.population = Population(1_600_000);
+    assert!(db.update_town(&munich_id, &munich).unwrap());
+    assert_eq!(db.get_town(&munich_id).unwrap(), Some(munich));
+}
+
+#[test]
+fn fields_and_filters() {
+    for_each_database("fields", check_fields_and_filters);
+}
+
+fn lat_long_names(db: &mut dyn DbConnection, lat: (f64, f64), long: (f64, f64)) -> Vec<String> {
+    let mut names: Vec<String> = db
+        .filter_towns_by_lat_long(
+            &Latitude(lat.0),
+            &Latitude(lat.1),
+            &Longitude(long.0),
+            &Longitude(long.1),
+        )
+        .unwrap()
+        .map(|row| row.unwrap().1.name.0)
+        .collect();
+    names.sort();
+    names
+}
+
+fn check_lat_long(db: &mut dyn DbConnection) {
+    let nation_id = db.insert_nation(&nation("World", "WW")).unwrap();
+    let mut ids = vec![];
+    for (name, lat, long) in [
+        ("Paris", 48.86, 2.35),
+        ("London", 51.51, -0.13),
+        ("Greenwich", 51.48, -0.),
+        ("Quito", -0.22, -78.51),
+        ("Sydney", -33.87, 151.21),
+    ] {
+        ids.push(db.insert_town(&town(name, &nation_id, lat, long)).unwrap());
+    }
+    // Both coordinates must be in their ranges, whose bounds are included.
+    assert_eq!(
+        lat_long_names(db, (48.86, 52.), (-0.13, 2.35)),
+        ["Greenwich", "London", "Paris"]
+    );
+    assert_eq!(lat_long_names(db, (51., 52.), (0., 1.)), ["Greenwich"]);
+    assert_eq!(
+        lat_long_names(db, (-90., 0.), (-180., 180.)),
+        ["Quito", "Sydney"]
+    );
+    assert!(lat_long_names(db, (-90., 0.), (-10., 10.)).is_empty());
+    // The filter follows the updates and the deletions.
+    db.update_town(&ids[0], &town("Paris", &nation_id, -48.86, 2.35))
+        .unwrap();
+    assert_eq!(
+        lat_long_names(db, (-90., 0.), (-180., 180.)),
+        ["Paris", "Quito", "Sydney"]
+    );
+    db.delete_town(&ids[3]).unwrap();
+    assert_eq!(
+        lat_long_names(db, (-90., 0.), (-180., 180.)),
+        ["Paris", "Sydney"]
+    );
+}
+
+#[test]
+fn lat_long_filter() {
+    for_each_database("lat_long", check_lat_long);
+}
+
+fn db_error<T: std::fmt::Debug>(result: Result<T, Box<dyn Error>>) -> DbError {
+    match result.unwrap_err().downcast::<DbError>() {
+        Ok(error) => *error,
+        Err(error) => panic!("Not a DbError: {error}"),
+    }
+}
+
+fn check_unique_names(db: &mut dyn DbConnection) {
+    assert_eq!(db.unique_names().unwrap(), UniqueNames::default());
+    let france_id = db.insert_nation(&nation("France", "FR")).unwrap();
+    let duplicate_id = db.insert_nation(&nation("France", "FR")).unwrap();
+    let both_rules = UniqueNames {
+        nation_name: true,
+        town_name_per_nation: true,
+    };
+    assert_eq!(
+        db_error(db.set_unique_names(&both_rules)),
+        DbError::DuplicateNationName
+    );
+    assert_eq!(db.unique_names().unwrap(), UniqueNames::default());
+    assert!(db.delete_nation(&duplicate_id).unwrap());
+    db.set_unique_names(&both_rules).unwrap();
+    assert_eq!(db.unique_names().unwrap(), both_rules);
+
+    assert_eq!(
+        db_error(db.insert_nation(&nation("France", "FR"))),
+        DbError::DuplicateNationName
+    );
+    let germany_id = db.insert_nation(&nation("Germany", "DE")).unwrap();
+    assert_eq!(
+        db_error(db.update_nation(&germany_id, &nation("France", "DE"))),
+        DbError::DuplicateNationName
+    );
+    // A nation keeps its own name.
+    assert!(db
+        .update_nation(&france_id, &nation("France", "FR"))
+        .unwrap());
+
+    let paris_id = db
+        .insert_town(&town("Paris", &france_id, 48.9, 2.4))
+        .unwrap();
+    db.insert_town(&town("Paris", &germany_id, 50., 10.))
+        .unwrap();
+    assert_eq!(
+        db_error(db.insert_town(&town("Paris", &france_id, 0., 0.))),
+        DbError::DuplicateTownName
+    );
+    let lyon_id = db
+        .insert_town(&town("Lyon", &france_id, 45.8, 4.8))
+        .unwrap();
+    assert_eq!(
+        db_error(db.update_town(&lyon_id, &town("Paris", &france_id, 45.8, 4.8))),
+        DbError::DuplicateTownName
+    );
+    assert_eq!(db.get_town(&paris_id).unwrap().unwrap().lat, Latitude(48.9));
+
+    // Disabling the rules allows the duplicates again.
+    db.set_unique_names(&UniqueNames::default()).unwrap();
+    db.insert_nation(&nation("France", "FR")).unwrap();
+    db.insert_town(&town("Paris", &france_id, 0., 0.)).unwrap();
+}
+
+fn check_upserts(db: &mut dyn DbConnection) {
+    let (france_id, upserted) = db.upsert_nation(&nation("France", "FR")).unwrap();
+    assert_eq!(upserted, Upserted::Inserted);
+    let mut france = nation("France", "FR");
+    france.area = Area(551_695.);
+    assert_eq!(
+        db.upsert_nation(&france).unwrap(),
+        (france_id.clone(), Upserted::Updated)
+    );
+    assert_eq!(
+        db.get_nation(&france_id).unwrap().unwrap().area,
+        france.area
+    );
+    let (germany_id, upserted) = db.upsert_nation(&nation("Germany", "DE")).unwrap();
+    assert_eq!(upserted, Upserted::Inserted);
+    assert_ne!(germany_id, france_id);
+
+    let (paris_id, upserted) = db.upsert_town(&town("Paris", &france_id, 0., 0.)).unwrap();
+    assert_eq!(upserted, Upserted::Inserted);
+    let paris = town("Paris", &france_id, 48.9, 2.4);
+    assert_eq!(
+        db.upsert_town(&paris).unwrap(),
+        (paris_id.clone(), Upserted::Updated)
+    );
+    assert_eq!(db.get_town(&paris_id).unwrap(), Some(paris));
+    // A town of another nation is another town.
+    let (other_id, upserted) = db
+        .upsert_town(&town("Paris", &germany_id, 50., 10.))
+        .unwrap();
+    assert_eq!(upserted, Upserted::Inserted);
+    assert_ne!(other_id, paris_id);
+}
+
+#[test]
+fn unique_names() {
+    for_each_database("unique", check_unique_names);
+}
+
+#[test]
+fn upserts() {
+    for_each_database("upserts", check_upserts);
+    // The upserts are the same when the names are unique.
+    for_each_database("unique_upserts", |db| {
+        db.set_unique_names(&UniqueNames {
+            nation_name: true,
+            town_name_per_nation: true,
+        })
+        .unwrap();
+        check_upserts(db);
+    });
+}
+
+fn check_maintenance<C: DbMaintenance>(name: &str) {
+    let path = TempPath::new(name);
+    let backup_path = TempPath::new(&format!("{name}.backup"));
+    let bad_backup_path = TempPath::new(&format!("{name}.bad_backup"));
+    let mut db = C::create(&path.0).unwrap();
+    let nation_id = db.insert_nation(&nation("France", "FR")).unwrap();
+    let paris = town("Paris", &nation_id, 48.9, 2.4);
+    let paris_id = db.insert_town(&paris).unwrap();
+    assert_eq!(db.check_integrity().unwrap(), []);
+    db.backup(&backup_path.0).unwrap();
+    // A backup never replaces a file.
+    assert!(db.backup(&backup_path.0).is_err());
+
+    let missing_nation_id = db.insert_nation(&nation("Atlantis", "AT")).unwrap();
+    db.delete_nation(&missing_nation_id).unwrap();
+    let orphan_id = db
+        .insert_town(&town("Nowhere", &missing_nation_id, 0., 0.))
+        .unwrap();
+    db.delete_town(&paris_id).unwrap();
+    assert_eq!(
+        db.check_integrity().unwrap(),
+        [IntegrityProblem::MissingNation {
+            town_id: orphan_id.clone(),
+            nation_id: missing_nation_id,
+        }]
+    );
+    db.backup(&bad_backup_path.0).unwrap();
+    drop(db);
+
+    // An inconsistent backup is refused, and the database is left as it is.
+    assert!(C::restore(&bad_backup_path.0, &path.0).is_err());
+    assert!(C::open_existing(&path.0)
+        .unwrap()
+        .get_town(&orphan_id)
+        .unwrap()
+        .is_some());
+    C::restore(&backup_path.0, &path.0).unwrap();
+    let mut db = C::open_existing(&path.0).unwrap();
+    assert_eq!(db.get_town(&paris_id).unwrap(), Some(paris));
+    assert_eq!(db.get_town(&orphan_id).unwrap(), None);
+    assert_eq!(db.check_integrity().unwrap(), []);
+}
+
+#[test]
+fn backup_restore_and_check() {
+    check_maintenance::<SqliteConnection>("maintenance.sqlite");
+    check_maintenance::<PersyConnection<BincodeSerder>>("maintenance_bincode.persy");
+    check_maintenance::<PersyConnection<JsonSerder>>("maintenance_json.persy");
+}
@@ -4,17 +4,38 @@ use std::time::Instant;
 
 //use data_access::persy_db::{open, delete, insert, scan, update};
 use data_access::{
-    mock_db::MockDbConnection, DbConnection, Latitude, Longitude, Nation, NationId, NationName,
-    Town, TownId, TownName,
+    mock_db::MockDbConnection, AlternateName, Area, BincodeSerder, DbConnection, DbError,
+    DbMaintenance, Elevation, JsonSerder, LanguageCode, Latitude, Longitude, NameMatch, Nation,
+    NationCode, NationId, NationName, Population, Town, TownId, TownName, UniqueNames,
 };
 use std::error::Error;
 
-use crate::data_access::persy_db::{BincodeSerder, JsonSerder, PersyConnection};
+use crate::data_access::persy_db::PersyConnection;
 use crate::data_access::postgres_db::PostgresConnection;
+use crate::data_access::redb_db::RedbConnection;
 use crate::data_access::sqlite_db::SqliteConnection;
 use crate::data_access::OptionalTownId;
 
+/// Without arguments, the program compares the databases.
+/// Otherwise, it runs a maintenance command on a database file,
+/// where `<kind>` is `sqlite`, `persy-bincode` or `persy-json`:
+/// - `backup <kind> <database file> <backup file>`
+/// - `restore <kind> <backup file> <database file>`
+/// - `check <kind> <database file>`, printing a JSON line for every problem found.
+const USAGE: &str =
+    "Usage: using_db [backup|restore|check] [sqlite|persy-bincode|persy-json] FILES";
+
 fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return match args.get(1).map(String::as_str) {
+            Some("sqlite") => run_maintenance::<SqliteConnection>(&args),
+            Some("persy-bincode") => run_maintenance::<PersyConnection<BincodeSerder>>(&args),
+            Some("persy-json") => run_maintenance::<PersyConnection<JsonSerder>>(&args),
+            _ => Err(USAGE.into()),
+        };
+    }
+
     println!("****** Using a mock DB ******");
     let mock_start = Instant::now();
     let mock_creation;
@@ -65,7 +86,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     let persy_bincode_creation;
     {
         let mut persy_bincode_db =
-            PersyConnection::<BincodeSerder>::open_truncated_or_create("world.persy")?;
+            PersyConnection::<BincodeSerder>::open_truncated_or_create("world_bincode.persy")?;
         persy_bincode_creation = persy_bincode_start.elapsed();
         process_world(&mut persy_bincode_db)?;
     }
@@ -77,12 +98,36 @@ fn main() -> Result<(), Box<dyn Error>> {
     let persy_json_creation;
     {
         let mut persy_json_db =
-            PersyConnection::<JsonSerder>::open_truncated_or_create("world.persy")?;
+            PersyConnection::<JsonSerder>::open_truncated_or_create("world_json.persy")?;
         persy_json_creation = persy_json_start.elapsed();
         process_world(&mut persy_json_db)?;
     }
     let persy_json_query = persy_json_start.elapsed() - persy_json_creation;
 
+    println!();
+    println!("****** Using a persistent redb DB with Bincode serialization ******");
+    let redb_bincode_start = Instant::now();
+    let redb_bincode_creation;
+    {
+        let mut redb_bincode_db =
+            RedbConnection::<BincodeSerder>::open_truncated_or_create("world_bincode.redb")?;
+        redb_bincode_creation = redb_bincode_start.elapsed();
+        process_world(&mut redb_bincode_db)?;
+    }
+    let redb_bincode_query = redb_bincode_start.elapsed() - redb_bincode_creation;
+
+    println!();
+    println!("****** Using a persistent redb DB with JSON serialization ******");
+    let redb_json_start = Instant::now();
+    let redb_json_creation;
+    {
+        let mut redb_json_db =
+            RedbConnection::<JsonSerder>::open_truncated_or_create("world_json.redb")?;
+        redb_json_creation = redb_json_start.elapsed();
+        process_world(&mut redb_json_db)?;
+    }
+    let redb_json_query = redb_json_start.elapsed() - redb_json_creation;
+
     eprintln!(
         "Mock: {}, {}",
         mock_creation.as_micros(),
@@ -113,22 +158,56 @@ fn main() -> Result<(), Box<dyn Error>> {
         persy_json_creation.as_micros(),
         persy_json_query.as_micros()
     );
+    eprintln!(
+        "Redb Bincode: {}, {}",
+        redb_bincode_creation.as_micros(),
+        redb_bincode_query.as_micros()
+    );
+    eprintln!(
+        "Redb Json: {}, {}",
+        redb_json_creation.as_micros(),
+        redb_json_query.as_micros()
+    );
     Ok(())
 }
 
+fn run_maintenance<C: DbMaintenance>(args: &[String]) -> Result<(), Box<dyn Error>> {
+    match (args[0].as_str(), &args[2..]) {
+        ("backup", [path, backup_path]) => C::open_unchanged(path)?.backup(backup_path),
+        ("restore", [backup_path, path]) => C::restore(backup_path, path),
+        ("check", [path]) => {
+            let problems = C::open_unchanged(path)?.check_integrity()?;
+            for problem in &problems {
+                println!("{}", serde_json::to_string(problem)?);
+            }
+            if !problems.is_empty() {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        _ => Err(USAGE.into()),
+    }
+}
+
 fn process_world(db: &mut dyn DbConnection) -> Result<(), Box<dyn Error>> {
     // Defining nations
     let france = Nation {
         name: NationName("France".to_string()),
         capital_id: OptionalTownId(None),
+        iso_code: NationCode("FR".to_string()),
+        area: Area(551_695.),
     };
     let uk = Nation {
         name: NationName("United Kingdom".to_string()),
         capital_id: OptionalTownId(None),
+        iso_code: NationCode("GB".to_string()),
+        area: Area(242_495.),
     };
     let germany = Nation {
         name: NationName("Germany".to_string()),
         capital_id: OptionalTownId(None),
+        iso_code: NationCode("DE".to_string()),
+        area: Area(357_592.),
     };
 
     // Inserting nations
@@ -164,18 +243,51 @@ fn process_world(db: &mut dyn DbConnection) -> Result<(), Box<dyn Error>> {
         lat: Latitude(1.1),
         long: Longitude(2.2),
         nation_id: france_id.clone(),
+        population: Population(2_102_650),
+        elevation: Elevation(35.),
+        alternate_names: vec![AlternateName {
+            language: LanguageCode("it".to_string()),
+            name: TownName("Parigi".to_string()),
+        }],
     };
     let london = Town {
         name: TownName("London".to_string()),
         lat: Latitude(3.3),
         long: Longitude(4.4),
         nation_id: uk_id,
+        population: Population(8_799_800),
+        elevation: Elevation(11.),
+        alternate_names: vec![AlternateName {
+            language: LanguageCode("fr".to_string()),
+            name: TownName("Londres".to_string()),
+        }],
     };
     let berlin = Town {
         name: TownName("Berlin".to_string()),
         lat: Latitude(5.5),
         long: Longitude(6.6),
         nation_id: germany_id.clone(),
+        population: Population(3_677_472),
+        elevation: Elevation(34.),
+        alternate_names: vec![],
+    };
+    let munich = Town {
+        name: TownName("München".to_string()),
+        lat: Latitude(48.1),
+        long: Longitude(11.6),
+        nation_id: germany_id.clone(),
+        population: Population(1_488_202),
+        elevation: Elevation(519.),
+        alternate_names: vec![
+            AlternateName {
+                language: LanguageCode("en".to_string()),
+                name: TownName("Munich".to_string()),
+            },
+            AlternateName {
+                language: LanguageCode("it".to_string()),
+                name: TownName("Monaco di Baviera".to_string()),
+            },
+        ],
     };
 
     // Inserting towns
@@ -194,6 +306,51 @@ fn process_world(db: &mut dyn DbConnection) -> Result<(), Box<dyn Error>> {
         "Inserted {} {} {} {} {}",
         london_id, london.name.0, london.lat.0, london.long.0, london.nation_id
     );
+    let munich_id = db.insert_town(&munich)?;
+    println!(
+        "Inserted {} {} {} {} {}",
+        munich_id, munich.name.0, munich.lat.0, munich.long.0, munich.nation_id
+    );
+
+    // Enforcing unique names
+    match db.set_unique_names(&UniqueNames {
+        nation_name: true,
+        town_name_per_nation: true,
+    }) {
+        Ok(()) => println!("Enabled unique nation and town names"),
+        Err(err) => println!("Cannot enable unique nation and town names: {}", err),
+    }
+    db.set_unique_names(&UniqueNames {
+        nation_name: false,
+        town_name_per_nation: true,
+    })?;
+    println!("Unique names: {:?}", db.unique_names()?);
+    match db.insert_town(&paris) {
+        Ok(id) => println!("Inserted {} {} again", id, paris.name.0),
+        Err(err) => match err.downcast_ref::<DbError>() {
+            Some(DbError::DuplicateTownName) => {
+                println!("Cannot insert {} again: {}", paris.name.0, err)
+            }
+            _ => return Err(err),
+        },
+    }
+
+    // Upserting nations and towns
+    let (id, upserted) = db.upsert_town(&Town {
+        population: Population(2_145_906),
+        ..paris.clone()
+    })?;
+    println!("Upserted {} {}: {:?}", id, paris.name.0, upserted);
+    let italy = Nation {
+        name: NationName("Italy".to_string()),
+        capital_id: OptionalTownId(None),
+        iso_code: NationCode("IT".to_string()),
+        area: Area(302_073.),
+    };
+    let (id, upserted) = db.upsert_nation(&italy)?;
+    println!("Upserted {} {}: {:?}", id, italy.name.0, upserted);
+    let (id, upserted) = db.upsert_nation(&germany)?;
+    println!("Upserted {} {}: {:?}", id, germany.name.0, upserted);
 
     // Filtering towns by position
     println!("Towns with position in range lat 0 to 7 long 0 to 7");
@@ -341,6 +498,54 @@ fn process_world(db: &mut dyn DbConnection) -> Result<(), Box<dyn Error>> {
             );
         }
     }
+
+    // Filtering nations by ISO code
+    println!("Nations with ISO code 'DE':");
+    for row in db.filter_nations_by_iso_code(&NationCode("DE".to_string()))? {
+        let (id, nation) = row?;
+        println!(
+            "- id: {}, name: {}, area: {}",
+            id, nation.name.0, nation.area.0
+        );
+    }
+
+    // Filtering towns by population
+    println!("Towns with population in range 1000000 to 3000000:");
+    for row in db.filter_towns_by_population(&Population(1_000_000), &Population(3_000_000))? {
+        let (id, town) = row?;
+        println!(
+            "- id: {}, name: {}, population: {}, elevation: {}, alternate names: {}",
+            id,
+            town.name.0,
+            town.population.0,
+            town.elevation.0,
+            town.alternate_names
+                .iter()
+                .map(|alternate_name| format!(
+                    "{} ({})",
+                    alternate_name.name.0, alternate_name.language.0
+                ))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    // Searching towns by name
+    for (text, name_match) in [
+        ("MUNCHEN", NameMatch::Exact),
+        ("mün", NameMatch::Prefix),
+        ("Muenchen", NameMatch::Fuzzy(2)),
+        ("Brelin", NameMatch::Fuzzy(2)),
+    ] {
+        println!("Towns matching '{}' as {:?}:", text, name_match);
+        for row in db.search_towns_by_name(text, &name_match)? {
+            let (id, town) = row?;
+            println!(
+                "- id: {}, name: {}, nation_id: {}",
+                id, town.name.0, town.nation_id
+            );
+        }
+    }
     /*
      */
 